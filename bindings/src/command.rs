@@ -1,5 +1,11 @@
-use editor_core::Command;
+use editor_core::{Address, Command, ExCommand, ExOp, LineRange};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::{anychar, char, digit1, one_of, space0, space1};
 use nom::combinator::*;
+use nom::error::{Error, ErrorKind};
+use nom::multi::{many0, many1};
+use nom::sequence::{pair, preceded, tuple};
 use nom::IResult;
 
 #[derive(Debug)]
@@ -20,12 +26,20 @@ fn parse_set(i: Vec<&str>) -> IResult<Vec<&str>, Vec<Command>, CommandError> {
             &"q" | &"quit" => Ok((vec![], Command::Quit.into())),
             &"w" | &"write" => Ok((vec![], Command::Save.into())),
             &"wq" => Ok((vec![], vec![Command::Save, Command::Quit])),
+            &"cn" | &"cnext" => Ok((vec![], Command::QuickfixNext(1).into())),
+            &"cp" | &"cprev" | &"cN" | &"cNext" => Ok((vec![], Command::QuickfixNext(-1).into())),
             _ => Err(nom::Err::Error(CommandError::Error)),
         }
     } else if i.len() == 2 {
         let (a, b) = (i.get(0).unwrap(), i.get(1).unwrap());
         match a {
-            &"set" => Ok((vec![], Command::VarGet(b.to_string()).into())),
+            &"set" | &"se" => match b.split_once('=') {
+                Some((name, value)) => Ok((
+                    vec![],
+                    Command::VarSet(name.to_string(), value.to_string()).into(),
+                )),
+                None => Ok((vec![], Command::VarGet(b.to_string()).into())),
+            },
             &"e" | &"edit" => Ok((vec![], Command::Open(b.to_string()).into())),
             &"w" | &"write" => Ok((vec![], Command::SaveAs(b.to_string()).into())),
             _ => Err(nom::Err::Error(CommandError::Error)),
@@ -44,9 +58,509 @@ fn parse_set(i: Vec<&str>) -> IResult<Vec<&str>, Vec<Command>, CommandError> {
     }
 }
 
+// vim allows commands to be abbreviated, "del" is the same as "delete"
+fn is_abbrev(word: &str, full: &str, min: usize) -> bool {
+    word.len() >= min && full.starts_with(word)
+}
+
+fn number(i: &str) -> IResult<&str, usize> {
+    map_opt(digit1, |s: &str| s.parse::<usize>().ok())(i)
+}
+
+fn address_base(i: &str) -> IResult<&str, Address> {
+    alt((
+        value(Address::Current, char('.')),
+        value(Address::Last, char('$')),
+        map(number, Address::Line),
+        map(preceded(char('\''), anychar), Address::Mark),
+        map(preceded(char('/'), delimited_text('/')), |s| {
+            Address::Search(s, true)
+        }),
+        map(preceded(char('?'), delimited_text('?')), |s| {
+            Address::Search(s, false)
+        }),
+    ))(i)
+}
+
+// +N or -N, with N defaulting to 1
+fn address_offset(i: &str) -> IResult<&str, i64> {
+    map(pair(one_of("+-"), opt(number)), |(sign, n)| {
+        let n = n.unwrap_or(1) as i64;
+        if sign == '-' {
+            -n
+        } else {
+            n
+        }
+    })(i)
+}
+
+// an address is a base followed by offsets, offsets on their own are relative to the current line
+fn address(i: &str) -> IResult<&str, Address> {
+    let (rest, (base, offsets)) = pair(opt(address_base), many0(address_offset))(i)?;
+    match base {
+        None if offsets.is_empty() => Err(nom::Err::Error(Error::new(i, ErrorKind::Tag))),
+        _ => {
+            let base = base.unwrap_or(Address::Current);
+            let offset: i64 = offsets.iter().sum();
+            if offsets.is_empty() {
+                Ok((rest, base))
+            } else {
+                Ok((rest, Address::Offset(Box::new(base), offset)))
+            }
+        }
+    }
+}
+
+fn line_range(i: &str) -> IResult<&str, LineRange> {
+    alt((
+        value(LineRange::All, char('%')),
+        map(
+            pair(address, opt(preceded(char(','), address))),
+            |(a, b)| match b {
+                Some(b) => LineRange::Span(a, b),
+                None => LineRange::Single(a),
+            },
+        ),
+    ))(i)
+}
+
+fn delimiter(i: &str) -> IResult<&str, char> {
+    verify(anychar, |ch: &char| {
+        !ch.is_alphanumeric() && !ch.is_whitespace() && !"\\\"|".contains(*ch)
+    })(i)
+}
+
+// read up to an unescaped delimiter, consuming the delimiter if there is one
+fn delimited_text(delim: char) -> impl Fn(&str) -> IResult<&str, String> {
+    move |i: &str| {
+        let mut out = String::new();
+        let mut chars = i.char_indices();
+        while let Some((inx, ch)) = chars.next() {
+            if ch == delim {
+                return Ok((&i[inx + ch.len_utf8()..], out));
+            } else if ch == '\\' {
+                match chars.next() {
+                    Some((_, next)) if next == delim => out.push(next),
+                    Some((_, next)) => {
+                        out.push(ch);
+                        out.push(next);
+                    }
+                    None => out.push(ch),
+                }
+            } else {
+                out.push(ch);
+            }
+        }
+        Ok(("", out))
+    }
+}
+
+fn substitute(i: &str) -> IResult<&str, ExOp> {
+    let (i, delim) = delimiter(i)?;
+    let (i, pattern) = delimited_text(delim)(i)?;
+    let (i, replacement) = delimited_text(delim)(i)?;
+    let (i, flags) = take_while(|ch: char| ch == 'g')(i)?;
+    Ok((i, ExOp::Substitute(pattern, replacement, !flags.is_empty())))
+}
+
+fn global(invert: bool) -> impl Fn(&str) -> IResult<&str, ExOp> {
+    move |i: &str| {
+        let (i, delim) = delimiter(i)?;
+        let (i, pattern) = delimited_text(delim)(i)?;
+        let (i, command) = ex_command(i)?;
+        Ok((i, ExOp::Global(pattern, invert, Box::new(command))))
+    }
+}
+
+// an optional register name after a command
+fn register(i: &str) -> IResult<&str, Option<char>> {
+    opt(preceded(
+        space0,
+        verify(anychar, |ch: &char| {
+            !ch.is_ascii_digit() && !ch.is_whitespace()
+        }),
+    ))(i)
+}
+
+fn shift(i: &str) -> IResult<&str, ExOp> {
+    alt((
+        map(many1(char('>')), |v| ExOp::Shift(v.len() as i32)),
+        map(many1(char('<')), |v| ExOp::Shift(-(v.len() as i32))),
+    ))(i)
+}
+
+// a width in columns, zero isn't one
+fn tab_width(i: &str) -> IResult<&str, usize> {
+    verify(number, |n| *n > 0)(i)
+}
+
+fn filename(i: &str) -> IResult<&str, Option<String>> {
+    opt(map(preceded(space1, nom::combinator::rest), |s: &str| {
+        s.trim_end().to_string()
+    }))(i)
+}
+
+fn ex_op(i: &str) -> IResult<&str, ExOp> {
+    if let Ok(r) = shift(i) {
+        return Ok(r);
+    }
+    let mut name_bang = pair(take_while1(|ch: char| ch.is_alphabetic()), opt(char('!')));
+    let (rest, (name, bang)) = name_bang(i)?;
+    let invert = bang.is_some();
+    match name {
+        _ if is_abbrev(name, "delete", 1) => map(register, ExOp::Delete)(rest),
+        _ if is_abbrev(name, "yank", 1) => map(register, ExOp::Yank)(rest),
+        _ if is_abbrev(name, "join", 1) => Ok((rest, ExOp::Join)),
+        _ if is_abbrev(name, "write", 1) => map(filename, ExOp::Write)(rest),
+        _ if is_abbrev(name, "move", 1) => map(preceded(space0, address), ExOp::Move)(rest),
+        "t" => map(preceded(space0, address), ExOp::Copy)(rest),
+        _ if is_abbrev(name, "copy", 2) => map(preceded(space0, address), ExOp::Copy)(rest),
+        _ if is_abbrev(name, "normal", 4) => {
+            map(preceded(space1, nom::combinator::rest), |s: &str| {
+                ExOp::Normal(s.to_string())
+            })(rest)
+        }
+        _ if is_abbrev(name, "substitute", 1) => substitute(rest),
+        _ if is_abbrev(name, "global", 1) => global(invert)(rest),
+        _ if is_abbrev(name, "retab", 3) => map(opt(preceded(space1, tab_width)), move |n| {
+            ExOp::Retab(invert, n)
+        })(rest),
+        _ if is_abbrev(name, "reindent", 4) => {
+            map(opt(preceded(space1, tab_width)), ExOp::Reindent)(rest)
+        }
+        _ if is_abbrev(name, "vglobal", 1) => global(true)(rest),
+        _ => Err(nom::Err::Error(Error::new(i, ErrorKind::Tag))),
+    }
+}
+
+// grep pattern [paths], a pattern with spaces can be delimited with slashes
+fn grep(i: &str) -> IResult<&str, Command> {
+    let (i, _) = tuple((space0, tag("grep"), space1))(i)?;
+    let (i, pattern) = alt((
+        preceded(char('/'), delimited_text('/')),
+        map(take_while1(|ch: char| !ch.is_whitespace()), |s: &str| {
+            s.to_string()
+        }),
+    ))(i)?;
+    let paths = i.split_whitespace().map(|s| s.to_string()).collect();
+    Ok(("", Command::Grep(pattern, paths)))
+}
+
+// replace /pattern/replacement/[g] [paths] previews a replace, replace! applies it
+fn replace(i: &str) -> IResult<&str, Command> {
+    let (i, _) = pair(space0, tag("replace"))(i)?;
+    if i.trim_end() == "!" {
+        return Ok(("", Command::ReplaceApply));
+    }
+    let (i, op) = preceded(space1, substitute)(i)?;
+    let paths = i.split_whitespace().map(|s| s.to_string()).collect();
+    match op {
+        ExOp::Substitute(pattern, replacement, all) => {
+            Ok(("", Command::Replace(pattern, replacement, all, paths)))
+        }
+        _ => Err(nom::Err::Error(Error::new(i, ErrorKind::Tag))),
+    }
+}
+
+// marks and jumps list them, delmarks removes marks, with ranges like a-d, delmarks! removes a-z
+fn marks(i: &str) -> IResult<&str, Command> {
+    let (i, name) = preceded(space0, take_while1(|ch: char| ch.is_ascii_alphabetic()))(i)?;
+    if is_abbrev(name, "marks", 4) && i.trim().is_empty() {
+        return Ok(("", Command::Marks));
+    }
+    if is_abbrev(name, "jumps", 2) && i.trim().is_empty() {
+        return Ok(("", Command::Jumps));
+    }
+    if !is_abbrev(name, "delmarks", 4) {
+        return Err(nom::Err::Error(Error::new(i, ErrorKind::Tag)));
+    }
+    if i.trim() == "!" {
+        return Ok(("", Command::DelMarks(('a'..='z').collect())));
+    }
+    let chars = i
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .collect::<Vec<char>>();
+    let mut marks = vec![];
+    let mut inx = 0;
+    while inx < chars.len() {
+        match (chars.get(inx + 1), chars.get(inx + 2)) {
+            (Some('-'), Some(end)) if chars[inx].is_ascii_alphabetic() => {
+                marks.extend(chars[inx]..=*end);
+                inx += 3;
+            }
+            _ => {
+                marks.push(chars[inx]);
+                inx += 1;
+            }
+        }
+    }
+    if marks.is_empty() {
+        return Err(nom::Err::Error(Error::new(i, ErrorKind::Tag)));
+    }
+    Ok(("", Command::DelMarks(marks)))
+}
+
+// registers and display list the registers, or only the ones named
+fn registers(i: &str) -> IResult<&str, Command> {
+    let (i, name) = preceded(space0, take_while1(|ch: char| ch.is_ascii_alphabetic()))(i)?;
+    if !is_abbrev(name, "registers", 3) && !is_abbrev(name, "display", 2) {
+        return Err(nom::Err::Error(Error::new(i, ErrorKind::Tag)));
+    }
+    let names = i.chars().filter(|ch| !ch.is_whitespace()).collect();
+    Ok(("", Command::Registers(names)))
+}
+
+fn ex_command(i: &str) -> IResult<&str, ExCommand> {
+    map_opt(
+        tuple((space0, opt(line_range), space0, opt(ex_op), space0)),
+        |(_, range, _, op, _)| match (range, op) {
+            (None, None) => None,
+            (range, Some(op)) => Some(ExCommand::new(range, op)),
+            (range, None) => Some(ExCommand::new(range, ExOp::Goto)),
+        },
+    )(i)
+}
+
 pub fn command_parse(input: &str) -> Result<Vec<Command>, CommandError> {
+    match all_consuming(ex_command)(input) {
+        // writing without a range saves the buffer
+        Ok((
+            _,
+            ExCommand {
+                range: None,
+                op: ExOp::Write(_),
+            },
+        )) => (),
+        Ok((_, c)) => return Ok(Command::Ex(c).into()),
+        Err(_) => (),
+    }
+    if let Ok((_, c)) = alt((grep, replace, marks, registers))(input) {
+        return Ok(c.into());
+    }
     match map_res(split, |s| parse_set(s))(input) {
         Ok((_, (_, commands))) => Ok(commands),
         Err(_err) => Err(CommandError::Error),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ex(range: Option<LineRange>, op: ExOp) -> Vec<Command> {
+        Command::Ex(ExCommand::new(range, op)).into()
+    }
+
+    #[test]
+    fn test_ex_commands() {
+        use Address as A;
+        assert_eq!(command_parse("d").unwrap(), ex(None, ExOp::Delete(None)));
+        assert_eq!(
+            command_parse("%d").unwrap(),
+            ex(Some(LineRange::All), ExOp::Delete(None))
+        );
+        assert_eq!(
+            command_parse("2,$m0").unwrap(),
+            ex(
+                Some(LineRange::Span(A::Line(2), A::Last)),
+                ExOp::Move(A::Line(0))
+            )
+        );
+        assert_eq!(
+            command_parse(".t $").unwrap(),
+            ex(Some(LineRange::Single(A::Current)), ExOp::Copy(A::Last))
+        );
+        assert_eq!(
+            command_parse("s/a\\/b/c/g").unwrap(),
+            ex(None, ExOp::Substitute("a/b".into(), "c".into(), true))
+        );
+        assert_eq!(
+            command_parse("norm Ax").unwrap(),
+            ex(None, ExOp::Normal("Ax".into()))
+        );
+        assert!(command_parse("xyz").is_err());
+        assert_eq!(
+            command_parse("1,2w out.txt").unwrap(),
+            ex(
+                Some(LineRange::Span(A::Line(1), A::Line(2))),
+                ExOp::Write(Some("out.txt".into()))
+            )
+        );
+        assert_eq!(command_parse("w").unwrap(), vec![Command::Save]);
+        assert_eq!(
+            command_parse("w out.txt").unwrap(),
+            vec![Command::SaveAs("out.txt".into())]
+        );
+        assert_eq!(command_parse("q").unwrap(), vec![Command::Quit]);
+        assert_eq!(
+            command_parse("ret! 4").unwrap(),
+            ex(None, ExOp::Retab(true, Some(4)))
+        );
+        assert_eq!(
+            command_parse("2,3reindent").unwrap(),
+            ex(
+                Some(LineRange::Span(A::Line(2), A::Line(3))),
+                ExOp::Reindent(None)
+            )
+        );
+        assert!(command_parse("retab 0").is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        use Address as A;
+        let offset = |a: A, n: i64| A::Offset(Box::new(a), n);
+        assert_eq!(
+            command_parse("12").unwrap(),
+            ex(Some(LineRange::Single(A::Line(12))), ExOp::Goto)
+        );
+        assert_eq!(
+            command_parse(".,+2y a").unwrap(),
+            ex(
+                Some(LineRange::Span(A::Current, offset(A::Current, 2))),
+                ExOp::Yank(Some('a'))
+            )
+        );
+        assert_eq!(
+            command_parse("'a,'b-1>>").unwrap(),
+            ex(
+                Some(LineRange::Span(A::Mark('a'), offset(A::Mark('b'), -1))),
+                ExOp::Shift(2)
+            )
+        );
+        assert_eq!(
+            command_parse("/x\\/y/,?z?j").unwrap(),
+            ex(
+                Some(LineRange::Span(
+                    A::Search("x/y".into(), true),
+                    A::Search("z".into(), false)
+                )),
+                ExOp::Join
+            )
+        );
+        assert_eq!(
+            command_parse("$-<").unwrap(),
+            ex(
+                Some(LineRange::Single(offset(A::Last, -1))),
+                ExOp::Shift(-1)
+            )
+        );
+        assert_eq!(
+            command_parse("-,+co$").unwrap(),
+            ex(
+                Some(LineRange::Span(
+                    offset(A::Current, -1),
+                    offset(A::Current, 1)
+                )),
+                ExOp::Copy(A::Last)
+            )
+        );
+    }
+
+    #[test]
+    fn test_grep() {
+        assert_eq!(
+            command_parse("grep foo").unwrap(),
+            vec![Command::Grep("foo".into(), vec![])]
+        );
+        assert_eq!(
+            command_parse("grep /a b/ src tests").unwrap(),
+            vec![Command::Grep(
+                "a b".into(),
+                vec!["src".into(), "tests".into()]
+            )]
+        );
+        assert_eq!(
+            command_parse("replace /a b/c/g src").unwrap(),
+            vec![Command::Replace(
+                "a b".into(),
+                "c".into(),
+                true,
+                vec!["src".into()]
+            )]
+        );
+        assert_eq!(
+            command_parse("replace!").unwrap(),
+            vec![Command::ReplaceApply]
+        );
+        assert_eq!(
+            command_parse("cprev").unwrap(),
+            vec![Command::QuickfixNext(-1)]
+        );
+    }
+
+    #[test]
+    fn test_global() {
+        let sub = ExCommand::new(None, ExOp::Substitute("x".into(), "y".into(), false));
+        assert_eq!(
+            command_parse("g/a b/s#x#y").unwrap(),
+            ex(
+                None,
+                ExOp::Global("a b".into(), false, Box::new(sub.clone()))
+            )
+        );
+        assert_eq!(
+            command_parse("1,2g!/a/s/x/y/").unwrap(),
+            ex(
+                Some(LineRange::Span(Address::Line(1), Address::Line(2))),
+                ExOp::Global("a".into(), true, Box::new(sub))
+            )
+        );
+        assert_eq!(
+            command_parse("v/a/normal dd").unwrap(),
+            ex(
+                None,
+                ExOp::Global(
+                    "a".into(),
+                    true,
+                    Box::new(ExCommand::new(None, ExOp::Normal("dd".into())))
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn test_set() {
+        assert_eq!(
+            command_parse("set scrolloff=3").unwrap(),
+            vec![Command::VarSet("scrolloff".into(), "3".into())]
+        );
+        assert_eq!(
+            command_parse("set so?").unwrap(),
+            vec![Command::VarGet("so?".into())]
+        );
+    }
+
+    #[test]
+    fn test_marks() {
+        assert_eq!(command_parse("marks").unwrap(), vec![Command::Marks]);
+        assert_eq!(command_parse("ju").unwrap(), vec![Command::Jumps]);
+        assert_eq!(
+            command_parse("delm a c-e X").unwrap(),
+            vec![Command::DelMarks(vec!['a', 'c', 'd', 'e', 'X'])]
+        );
+        assert_eq!(
+            command_parse("delmarks!").unwrap(),
+            vec![Command::DelMarks(('a'..='z').collect())]
+        );
+        assert!(command_parse("delmarks").is_err());
+    }
+
+    #[test]
+    fn test_registers() {
+        assert_eq!(
+            command_parse("registers").unwrap(),
+            vec![Command::Registers(vec![])]
+        );
+        assert_eq!(
+            command_parse("reg a 1").unwrap(),
+            vec![Command::Registers(vec!['a', '1'])]
+        );
+        assert_eq!(
+            command_parse("di").unwrap(),
+            vec![Command::Registers(vec![])]
+        );
+        assert!(command_parse("re").is_err());
+    }
+}
@@ -57,6 +57,74 @@ impl SimpleHistory {
     }
 
     pub fn change_start(&mut self) {
+        // a change that never ended is dropped
+        self.acc.clear();
         self.record = true;
     }
+
+    // the last change for ., a count replaces the count of its operator and is kept
+    // for the next repeat, changes without one are played count times,
+    // command lines are always played once
+    pub fn repeat(&mut self, count: Option<usize>) -> Vec<Command> {
+        let change = match self.h.front_mut() {
+            Some(change) => change,
+            None => return vec![],
+        };
+        let n = match count {
+            Some(n) if n > 0 => n,
+            _ => return change.clone(),
+        };
+        if change.contains(&Command::CliExec) {
+            return change.clone();
+        }
+        match change.iter_mut().find_map(|c| c.count_mut()) {
+            Some(reps) => {
+                *reps = n;
+                change.clone()
+            }
+            None => (0..n).flat_map(|_| change.iter().cloned()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor_core::{Mode, Motion, Register};
+
+    #[test]
+    fn test_repeat() {
+        use Command as C;
+        let mut h = SimpleHistory::default();
+        assert_eq!(h.repeat(Some(2)), vec![]);
+
+        let insert = vec![
+            C::Mode(Mode::Insert),
+            C::Insert("a".into()),
+            C::Mode(Mode::Normal),
+        ];
+        h.change_start();
+        insert.iter().for_each(|c| h.add_elem(c));
+        h.change_end();
+        assert_eq!(h.repeat(None), insert);
+        assert_eq!(h.repeat(Some(2)), [insert.clone(), insert].concat());
+
+        // the new count replaces the old one, and stays
+        let delete = C::Delete(3, Register::UNNAMED, Motion::ForwardWord1);
+        h.change_start();
+        h.add_elem(&delete);
+        h.change_end();
+        assert_eq!(h.repeat(None), vec![delete]);
+        let delete = C::Delete(2, Register::UNNAMED, Motion::ForwardWord1);
+        assert_eq!(h.repeat(Some(2)), vec![delete.clone()]);
+        assert_eq!(h.repeat(None), vec![delete]);
+
+        // an unfinished change is dropped when the next one starts
+        h.change_start();
+        h.add_elem(&C::Insert("x".into()));
+        h.change_start();
+        h.add_elem(&C::Join);
+        h.change_end();
+        assert_eq!(h.repeat(None), vec![C::Join]);
+    }
 }
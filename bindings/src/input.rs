@@ -1,12 +1,21 @@
 use super::*;
-use editor_core::Command;
+use crate::range::{keys_to_string, string_to_keys};
+use editor_core::{Command, MacroId, Mode, Register, Registers};
 use log::*;
 
+// macros playing macros stop this deep, so a macro that plays itself ends
+const MAX_MACRO_DEPTH: usize = 100;
+
 pub struct InputReader {
     pub q: Vec<Elem>,
     pub quit: bool,
     pub state: ModeState,
     pub history: history::SimpleHistory,
+    pub registers: Registers, // shared with the editor, macros are recorded here
+    recording: Vec<Elem>,     // the keys typed since q{reg}
+    last_macro: Option<MacroId>,
+    depth: usize,
+    cli: String, // the command line being typed, so changes made by it can be repeated
 }
 impl Default for InputReader {
     fn default() -> Self {
@@ -15,6 +24,11 @@ impl Default for InputReader {
             quit: false,
             history: history::SimpleHistory::default(),
             state: ModeState::default(),
+            registers: Registers::default(),
+            recording: vec![],
+            last_macro: None,
+            depth: 0,
+            cli: String::new(),
         }
     }
 }
@@ -29,12 +43,82 @@ impl InputReader {
     }
 
     pub fn add(&mut self, e: Elem) -> Vec<Command> {
+        if self.state.record.is_some() {
+            self.recording.push(e);
+        }
+        self.parse(e)
+    }
+
+    // the keys of a macro, with a count, as the commands they parse to
+    fn play(&mut self, reps: usize, id: MacroId) -> Vec<Command> {
+        let id = match id {
+            MacroId::LAST => match self.last_macro {
+                Some(id) => id,
+                None => return vec![],
+            },
+            _ => id,
+        };
+        self.last_macro = Some(id);
+        let text = self.registers.get(&Register(id.0));
+        let keys = match id.0 {
+            ':' if !text.is_empty() => string_to_keys(&format!(":{}\r", text)),
+            _ => string_to_keys(&text),
+        };
+        if self.depth >= MAX_MACRO_DEPTH {
+            info!("Macro too deep: {:?}", id);
+            return vec![];
+        }
+        self.depth += 1;
+        let mut out = vec![];
+        for _ in 0..reps {
+            for e in keys.iter() {
+                out.extend(self.parse(*e));
+            }
+        }
+        self.depth -= 1;
+        out
+    }
+
+    // follow the command line, an ex command that changes the buffer is the change for .
+    fn cli_record(&mut self, c: &Command) {
+        match c {
+            Command::Mode(Mode::Cli) => self.cli.clear(),
+            Command::CliEdit(edits) => edits.iter().for_each(|e| match e {
+                Command::Insert(s) => self.cli.push_str(s),
+                Command::RemoveChar(-1) => {
+                    self.cli.pop();
+                }
+                _ => (),
+            }),
+            Command::CliExec => {
+                let is_change = match self.cli.strip_prefix(':').map(command_parse) {
+                    Some(Ok(commands)) => commands
+                        .iter()
+                        .any(|c| matches!(c, Command::Ex(ex) if ex.op.is_change())),
+                    _ => false,
+                };
+                if is_change {
+                    self.history.add(vec![
+                        Command::Mode(Mode::Cli),
+                        Command::CliEdit(Command::Insert(self.cli.clone()).into()),
+                        Command::Mode(Mode::Normal),
+                        Command::CliExec,
+                    ]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn parse(&mut self, e: Elem) -> Vec<Command> {
         self.q.push(e);
         let result = self.state.command(self.q.as_slice());
         let mut out = vec![];
         match result {
             Ok((_, commands)) => {
-                for c in commands.iter() {
+                for mut c in commands.into_iter() {
+                    self.state.resolve_find(&mut c);
+                    let c = &c;
                     info!("Mode Command {:?}", c);
                     match c {
                         Command::Quit => {
@@ -47,9 +131,26 @@ impl InputReader {
                         }
                         Command::MacroStart(id) => {
                             self.state.record.replace(*id);
+                            self.recording.clear();
+                            self.q.clear();
                         }
                         Command::MacroEnd => {
-                            self.state.record = None;
+                            // the q that stopped the recording isn't part of it
+                            self.recording.pop();
+                            if let Some(id) = self.state.record.take() {
+                                let keys = keys_to_string(&self.recording);
+                                self.registers.record(&Register(id.0), &keys);
+                            }
+                            self.recording.clear();
+                            self.q.clear();
+                        }
+                        Command::MacroPlay(reps, id) => {
+                            self.q.clear();
+                            let commands = self.play(*reps, *id);
+                            if self.quit {
+                                return vec![Command::Quit];
+                            }
+                            out.push(Command::Macro(commands));
                         }
                         Command::ChangeStart => {
                             self.history.change_start();
@@ -57,27 +158,22 @@ impl InputReader {
                         Command::ChangeEnd => {
                             self.history.change_end();
                         }
-                        Command::ChangeRepeat => {
-                            info!("Repeat: {:?}", self.history.front());
-                            self.history
-                                .front()
-                                .unwrap_or(vec![])
-                                .iter()
-                                .for_each(|cc| {
-                                    out.push(cc.clone());
-                                });
+                        Command::ChangeRepeat(count) => {
+                            let change = self.history.repeat(*count);
+                            info!("Repeat: {:?}", change);
+                            out.extend(change);
                             self.q.clear();
                         }
                         Command::Mode(m) => {
-                            self.state.macros_add(c.clone());
-                            self.state.mode = *m;
+                            self.cli_record(c);
+                            self.state.change_mode(*m);
                             out.push(Command::Mode(self.state.mode));
                             self.history.add_elem(&c);
                             self.q.clear();
                         }
                         _ => {
                             info!("[{:?}] Ok: {:?}\r", self.state.mode, (&self.q, &c));
-                            self.state.macros_add(c.clone());
+                            self.cli_record(c);
                             self.history.add_elem(&c);
                             self.q.clear();
                             out.push(c.clone());
@@ -1,6 +1,9 @@
 use crate::parser::T;
 use crate::range::{range_string, Elem, Range, R};
-use editor_core::{Command, MacroId, Macros, Mode, Motion};
+use editor_core::{
+    Address, Case, CharSearch, Command, ExCommand, ExOp, LineOp, LineRange, MacroId, Mode, Motion,
+    Register,
+};
 use nom::branch::alt;
 use nom::combinator;
 use nom::combinator::{complete, map, map_opt, value};
@@ -10,21 +13,26 @@ use nom::IResult;
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct ModeState {
     pub mode: Mode,
-    pub record: Option<MacroId>,
-    pub macros: Macros,
+    pub record: Option<MacroId>, // the register a macro is being recorded in
+    pub visual: Mode,            // the last visual mode, for gv
+    pub find: Option<CharSearch>, // the last char search, for ; and ,
 }
 impl Default for ModeState {
     fn default() -> Self {
         Self {
             mode: Mode::Normal,
             record: None,
-            macros: Macros::default(),
+            visual: Mode::Visual,
+            find: None,
         }
     }
 }
 
 impl<'a> ModeState {
     pub fn change_mode(&mut self, m: Mode) -> &mut Self {
+        if m.is_visual() {
+            self.visual = m;
+        }
         self.mode = m;
         self
     }
@@ -32,27 +40,56 @@ impl<'a> ModeState {
     pub fn command(&self, i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
         match self.mode {
             Mode::Normal => self.p_normal(i),
-            Mode::Insert => Self::p_insert(i),
+            Mode::Insert | Mode::Replace => Self::p_insert(i),
             Mode::Easy => self.p_normal(i),
             Mode::Cli => Self::p_cli(i),
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => self.p_visual(i),
+        }
+    }
+
+    // remember char searches, and replace ; and , with the search they repeat
+    pub fn resolve_find(&mut self, c: &mut Command) {
+        match c.motion_mut() {
+            Some(Motion::Find(search)) => {
+                self.find = Some(*search);
+            }
+            Some(m) => {
+                if let (Motion::RepeatFind(reverse), Some(search)) = (&m, self.find) {
+                    *m = Motion::Find(search.repeat(*reverse));
+                }
+            }
+            None => (),
         }
     }
 
     pub fn clear(&mut self) {
-        self.macros.clear_all();
         self.record = None;
     }
 
-    pub fn macros_add(&mut self, c: Command) {
-        if let Some(id) = self.record {
-            self.macros.add(&id, &c);
+    // q{reg} records a macro and q stops, [count]@{reg} plays one and @@ the last one played
+    fn p_macro(&self, i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        match self.record {
+            Some(_) => value(C::MacroEnd.into(), R::tag_string("q"))(i),
+            None => alt((
+                map_opt(tuple((R::tag_string("q"), R::char())), |(_, ch)| {
+                    let id = MacroId(ch);
+                    id.is_recordable().then(|| C::MacroStart(id).into())
+                }),
+                map_opt(
+                    tuple((T::number_or(1), R::tag_string("@"), R::char())),
+                    |(n, _, ch)| {
+                        let id = MacroId(ch);
+                        id.is_playable().then(|| C::MacroPlay(n, id).into())
+                    },
+                ),
+            ))(i),
         }
     }
 
     fn p_common(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
         alt((
             value(Command::Save.into(), R::oneof(&[Elem::Control('s')])),
-            value(Command::LineNav(0).into(), R::oneof(&[Elem::Control('a')])),
             value(Command::LineNav(-1).into(), R::oneof(&[Elem::Control('e')])),
             value(Command::Stop.into(), R::tag(&[Elem::Control('z')])),
             value(
@@ -68,6 +105,118 @@ impl<'a> ModeState {
         ))(i)
     }
 
+    // scroll the view by lines, or around the cursor line
+    fn p_scroll(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        alt((
+            map(
+                tuple((T::number_or(1), R::tag(&[Elem::Control('e')]))),
+                |(n, _)| C::Scroll(n as i16).into(),
+            ),
+            map(
+                tuple((T::number_or(1), R::tag(&[Elem::Control('y')]))),
+                |(n, _)| C::Scroll(-(n as i16)).into(),
+            ),
+            value(
+                C::ScrollCursor(Motion::ScreenTop).into(),
+                R::tag_string("zt"),
+            ),
+            value(
+                C::ScrollCursor(Motion::ScreenMiddle).into(),
+                R::tag_string("zz"),
+            ),
+            value(
+                C::ScrollCursor(Motion::ScreenBottom).into(),
+                R::tag_string("zb"),
+            ),
+        ))(i)
+    }
+
+    // m sets a mark, lowercase marks are local to the buffer and uppercase marks are global
+    fn p_mark(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        map_opt(tuple((R::tag_string("m"), R::char())), |(_, ch)| {
+            if ch.is_ascii_alphabetic() {
+                Some(Command::MarkSet(ch).into())
+            } else {
+                None
+            }
+        })(i)
+    }
+
+    // R overwrites until escape, [count]r{char} and [count]gr{char} replace chars
+    fn p_replace(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        let replacement = |i| {
+            map_opt(R::take(1), |e: Range| match e[0] {
+                Elem::Char(ch) => Some(ch),
+                Elem::Enter => Some('\n'),
+                Elem::Tab => Some('\t'),
+                _ => None,
+            })(i)
+        };
+        alt((
+            value(
+                vec![C::ChangeStart, C::Mode(Mode::Replace)],
+                R::tag_string("R"),
+            ),
+            map(
+                tuple((
+                    T::number_or(1),
+                    alt((
+                        value(false, R::tag_string("r")),
+                        value(true, R::tag_string("gr")),
+                    )),
+                    replacement,
+                )),
+                |(n, virtual_cols, ch)| {
+                    vec![
+                        C::ChangeStart,
+                        C::ReplaceChar(n, ch, virtual_cols),
+                        C::ChangeEnd,
+                    ]
+                },
+            ),
+        ))(i)
+    }
+
+    // [count]ctrl-a and [count]ctrl-x add to and subtract from the number at or after the cursor
+    fn p_increment(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        map(
+            tuple((
+                T::number_or(1),
+                R::oneof(&[Elem::Control('a'), Elem::Control('x')]),
+            )),
+            |(n, key)| {
+                let sign = match key {
+                    Elem::Control('x') => -1,
+                    _ => 1,
+                };
+                vec![C::ChangeStart, C::Increment(n, sign), C::ChangeEnd]
+            },
+        )(i)
+    }
+
+    // ctrl-o and ctrl-i (tab) walk the jump list, g; and g, the change list
+    fn p_jump(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        alt((
+            map(
+                tuple((T::number_or(1), R::tag(&[Elem::Control('o')]))),
+                |(n, _)| C::Jump(-(n as i32)).into(),
+            ),
+            map(tuple((T::number_or(1), R::tag(&[Elem::Tab]))), |(n, _)| {
+                C::Jump(n as i32).into()
+            }),
+            map(tuple((T::number_or(1), R::tag_string("g;"))), |(n, _)| {
+                C::ChangeList(-(n as i32)).into()
+            }),
+            map(tuple((T::number_or(1), R::tag_string("g,"))), |(n, _)| {
+                C::ChangeList(n as i32).into()
+            }),
+        ))(i)
+    }
+
     fn alias() -> impl FnMut(Range<'a>) -> IResult<Range<'a>, Vec<Elem>> {
         alt((
             value(range_string("dw"), R::tag_elem(range_string("asdf"))),
@@ -106,44 +255,186 @@ impl<'a> ModeState {
                     _ => None,
                 },
             ),
+            value(C::Select.into(), R::tag(&[Elem::Enter])),
             T::cli(),
-            value(C::Mode(Mode::Insert).into(), R::tag_string("i")),
+            value(
+                vec![C::ChangeStart, C::Mode(Mode::Insert)],
+                R::tag_string("i"),
+            ),
             value(C::Line(0).into(), R::tag_string("G")),
             value(C::Line(1).into(), R::tag_string("gg")),
-            value(C::Join.into(), R::tag_string("J")), // Join
-            value(C::ChangeRepeat.into(), R::tag(&[Elem::Char('.')])), // Change Repeat
+            |i| self.p_visual_start(i),
             value(
-                vec![
-                    C::Motion(1, Motion::NextLine),
-                    C::Mode(Mode::Insert),
-                    C::Insert("\n".to_string()),
-                    C::Motion(1, Motion::Left),
-                ],
+                vec![C::ChangeStart, C::Join, C::ChangeEnd],
+                R::tag_string("J"),
+            ), // Join
+            map(
+                tuple((combinator::opt(R::number()), R::tag_string("."))),
+                |(n, _)| C::ChangeRepeat(n).into(),
+            ), // Change Repeat
+            value(
+                vec![C::ChangeStart, C::Mode(Mode::Insert), C::OpenLine(true)],
                 R::tag_string("o"),
             ),
             value(
-                vec![
-                    C::Motion(1, Motion::SOL),
-                    C::Mode(Mode::Insert),
-                    C::Insert("\n".to_string()),
-                    C::Motion(1, Motion::Left),
-                ],
+                vec![C::ChangeStart, C::Mode(Mode::Insert), C::OpenLine(false)],
                 R::tag_string("O"),
             ),
-            value(C::BufferNext.into(), R::tag_string("]")),
-            value(C::BufferPrev.into(), R::tag_string("[")),
+            alt((
+                value(C::BufferNext.into(), R::tag_string("]")),
+                value(C::BufferPrev.into(), R::tag_string("[")),
+            )),
             value(C::Undo.into(), R::tag_string("u")),
             value(C::Redo.into(), R::tag(&[Elem::Control('r')])),
-            value(vec![C::Reset, C::Refresh], R::tag_string("RR")),
+            value(vec![C::Reset, C::Refresh], R::tag(&[Elem::Control('l')])),
             value(vec![C::Test], R::tag_string("TT")),
-            |i| Self::p_common(i),
+            alt((
+                |i| Self::p_scroll(i),
+                |i| Self::p_mark(i),
+                |i| Self::p_jump(i),
+                |i| Self::p_common(i),
+                |i| self.p_macro(i),
+                |i| Self::p_replace(i),
+                |i| Self::p_increment(i),
+            )),
             T::motion(),
             //combinator::peek(T::search_inc()),
             //T::search_inc(),
             //T::search(),
             T::operator_motion(),
             T::register_motion(),
-            //|i| T::p_macros(i, self.record),
+        ))(i)
+    }
+
+    fn p_visual_start(&self, i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        alt((
+            value(C::Mode(Mode::Visual).into(), R::tag_string("v")),
+            value(C::Mode(Mode::VisualLine).into(), R::tag_string("V")),
+            value(
+                C::Mode(Mode::VisualBlock).into(),
+                R::tag(&[Elem::Control('v')]),
+            ),
+            value(
+                vec![C::Mode(self.visual), C::VisualRestore],
+                R::tag_string("gv"),
+            ),
+        ))(i)
+    }
+
+    // the key for the current visual mode leaves it, the others change the kind of selection
+    fn visual_toggle(&self, m: Mode) -> Vec<Command> {
+        if m == self.mode {
+            Command::Mode(Mode::Normal).into()
+        } else {
+            Command::Mode(m).into()
+        }
+    }
+
+    fn visual_ex(op: ExOp) -> Vec<Command> {
+        let range = LineRange::Span(Address::Mark('<'), Address::Mark('>'));
+        vec![
+            Command::Ex(ExCommand::new(Some(range), op)),
+            Command::Mode(Mode::Normal),
+        ]
+    }
+
+    fn p_visual(&self, i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
+        use Command as C;
+        use Elem::Char;
+        use Motion as M;
+        let normal = C::Mode(Mode::Normal);
+        alt((
+            value(
+                normal.clone().into(),
+                R::oneof(&[Elem::Esc, Elem::Control('c')]),
+            ),
+            map(R::tag_string("v"), |_| self.visual_toggle(Mode::Visual)),
+            map(R::tag_string("V"), |_| self.visual_toggle(Mode::VisualLine)),
+            map(R::tag(&[Elem::Control('v')]), |_| {
+                self.visual_toggle(Mode::VisualBlock)
+            }),
+            value(C::VisualSwap.into(), R::tag_string("o")),
+            map(
+                tuple((
+                    T::register_or(Register::UNNAMED),
+                    R::oneof(&[Char('d'), Char('x')]),
+                )),
+                |(reg, _)| {
+                    vec![
+                        C::ChangeStart,
+                        C::Delete(1, reg, M::Selection),
+                        C::ChangeEnd,
+                        C::Mode(Mode::Normal),
+                    ]
+                },
+            ),
+            map(
+                tuple((
+                    T::register_or(Register::UNNAMED),
+                    R::oneof(&[Char('c'), Char('s')]),
+                )),
+                |(reg, _)| {
+                    vec![
+                        C::ChangeStart,
+                        C::Delete(1, reg, M::Selection),
+                        C::Mode(Mode::Insert),
+                    ]
+                },
+            ),
+            map(
+                tuple((T::register_or(Register::UNNAMED), R::tag_string("y"))),
+                |(reg, _)| vec![C::Yank(reg, M::Selection), C::Mode(Mode::Normal)],
+            ),
+            map(
+                tuple((T::number_or(1), R::oneof(&[Char('>'), Char('<')]))),
+                |(n, op)| match op {
+                    Char('<') => Self::visual_ex(ExOp::Shift(-(n as i32))),
+                    _ => Self::visual_ex(ExOp::Shift(n as i32)),
+                },
+            ),
+            value(Self::visual_ex(ExOp::Join), R::tag_string("J")),
+            map(
+                alt((
+                    value(LineOp::Reindent, R::tag_string("=")),
+                    value(LineOp::Format(false), R::tag_string("gq")),
+                    value(LineOp::Format(true), R::tag_string("gw")),
+                    value(LineOp::Comment, R::tag_string("gc")),
+                )),
+                |op| {
+                    vec![
+                        C::ChangeStart,
+                        C::LineOp(1, op, M::Selection),
+                        C::ChangeEnd,
+                        C::Mode(Mode::Normal),
+                    ]
+                },
+            ),
+            map(R::oneof(&[Char('~'), Char('u'), Char('U')]), |ch| {
+                let case = match ch {
+                    Char('u') => Case::Lower,
+                    Char('U') => Case::Upper,
+                    _ => Case::Toggle,
+                };
+                vec![C::ChangeCase(1, case, M::Selection), C::Mode(Mode::Normal)]
+            }),
+            value(
+                vec![
+                    C::Mode(Mode::Cli),
+                    C::CliEdit(C::Insert(":'<,'>".to_string()).into()),
+                ],
+                R::tag_string(":"),
+            ),
+            alt((
+                map(tuple((R::number(), R::oneof(&[Elem::Char('G')]))), |x| {
+                    C::Line(x.0).into()
+                }),
+                value(C::Line(0).into(), R::tag_string("G")),
+                value(C::Line(1).into(), R::tag_string("gg")),
+                |i| Self::p_scroll(i),
+                |i| Self::p_common(i),
+                T::motion(),
+            )),
         ))(i)
     }
 
@@ -213,6 +504,66 @@ impl<'a> ModeState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn visual() {
+        use Command as C;
+        let parse = |state: &ModeState, s: &str| {
+            let inp = range_string(s);
+            state.command(&inp).map(|(_, c)| c).unwrap()
+        };
+        let mut state = ModeState::default();
+        assert_eq!(parse(&state, "V"), vec![C::Mode(Mode::VisualLine)]);
+        state.change_mode(Mode::VisualLine);
+        assert_eq!(parse(&state, "V"), vec![C::Mode(Mode::Normal)]);
+        assert_eq!(parse(&state, "v"), vec![C::Mode(Mode::Visual)]);
+        assert_eq!(parse(&state, "3j"), vec![C::Motion(3, Motion::Down)]);
+        assert_eq!(
+            parse(&state, "\"ay"),
+            vec![
+                C::Yank(Register('a'), Motion::Selection),
+                C::Mode(Mode::Normal)
+            ]
+        );
+        let range = LineRange::Span(Address::Mark('<'), Address::Mark('>'));
+        assert_eq!(
+            parse(&state, "2<"),
+            vec![
+                C::Ex(ExCommand::new(Some(range), ExOp::Shift(-2))),
+                C::Mode(Mode::Normal)
+            ]
+        );
+
+        // gv returns to the last kind of visual mode
+        state.change_mode(Mode::Normal);
+        assert_eq!(
+            parse(&state, "gv"),
+            vec![C::Mode(Mode::VisualLine), C::VisualRestore]
+        );
+    }
+
+    #[test]
+    fn scroll() {
+        use Command as C;
+        let state = ModeState::default();
+        let mut inp = range_string("3");
+        inp.push(Elem::Control('e'));
+        let (_, commands) = state.command(&inp).unwrap();
+        assert_eq!(commands, vec![C::Scroll(3)]);
+        let (_, commands) = state.command(&[Elem::Control('y')]).unwrap();
+        assert_eq!(commands, vec![C::Scroll(-1)]);
+        let (_, commands) = state.command(&range_string("zz")).unwrap();
+        assert_eq!(commands, vec![C::ScrollCursor(Motion::ScreenMiddle)]);
+
+        let mut inp = range_string("2");
+        inp.push(Elem::Control('o'));
+        let (_, commands) = state.command(&inp).unwrap();
+        assert_eq!(commands, vec![C::Jump(-2)]);
+        let (_, commands) = state.command(&[Elem::Tab]).unwrap();
+        assert_eq!(commands, vec![C::Jump(1)]);
+        let (_, commands) = state.command(&range_string("3g;")).unwrap();
+        assert_eq!(commands, vec![C::ChangeList(-3)]);
+    }
+
     #[test]
     fn quit() {
         let inp = vec![Elem::Control('q')];
@@ -220,4 +571,139 @@ mod tests {
         let (_, commands) = state.command(&inp).unwrap();
         assert_eq!(vec![Command::Quit], commands);
     }
+
+    #[test]
+    fn macros() {
+        use Command as C;
+        let parse = |state: &ModeState, s: &str| {
+            let inp = range_string(s);
+            state.command(&inp).map(|(_, c)| c).unwrap()
+        };
+        let mut state = ModeState::default();
+        assert_eq!(parse(&state, "qa"), vec![C::MacroStart(MacroId('a'))]);
+        assert_eq!(parse(&state, "3@a"), vec![C::MacroPlay(3, MacroId('a'))]);
+        assert_eq!(parse(&state, "@@"), vec![C::MacroPlay(1, MacroId::LAST)]);
+        assert_eq!(parse(&state, "@:"), vec![C::MacroPlay(1, MacroId(':'))]);
+        assert!(state.command(&range_string("q%")).is_err());
+        state.record = Some(MacroId('a'));
+        assert_eq!(parse(&state, "q"), vec![C::MacroEnd]);
+    }
+
+    #[test]
+    fn replace() {
+        use Command as C;
+        let parse =
+            |state: &ModeState, elems: Vec<Elem>| state.command(&elems).map(|(_, c)| c).unwrap();
+        let state = ModeState::default();
+        assert_eq!(
+            parse(&state, range_string("R")),
+            vec![C::ChangeStart, C::Mode(Mode::Replace)]
+        );
+        assert_eq!(
+            parse(&state, range_string("3rx"))[1],
+            C::ReplaceChar(3, 'x', false)
+        );
+        assert_eq!(
+            parse(&state, vec![Elem::Char('r'), Elem::Enter])[1],
+            C::ReplaceChar(1, '\n', false)
+        );
+        assert_eq!(
+            parse(&state, range_string("2gr-"))[1],
+            C::ReplaceChar(2, '-', true)
+        );
+        assert!(state.command(&[Elem::Char('r'), Elem::Esc]).is_err());
+    }
+
+    #[test]
+    fn case_and_increment() {
+        use Command as C;
+        let parse =
+            |state: &ModeState, elems: Vec<Elem>| state.command(&elems).map(|(_, c)| c).unwrap();
+        let state = ModeState::default();
+        assert_eq!(
+            parse(&state, range_string("gUiw"))[1],
+            C::ChangeCase(1, Case::Upper, Motion::Inner(editor_core::TextObject::Word))
+        );
+        assert_eq!(
+            parse(&state, range_string("2g~w"))[1],
+            C::ChangeCase(2, Case::Toggle, Motion::ForwardWord1)
+        );
+        assert_eq!(
+            parse(&state, range_string("3guu"))[1],
+            C::ChangeCase(3, Case::Lower, Motion::Line)
+        );
+        assert_eq!(
+            parse(&state, range_string("gUgU"))[1],
+            C::ChangeCase(1, Case::Upper, Motion::Line)
+        );
+        assert_eq!(
+            parse(&state, range_string("4~")),
+            vec![
+                C::ChangeStart,
+                C::ChangeCase(4, Case::Toggle, Motion::Right),
+                C::ChangeEnd
+            ]
+        );
+        assert_eq!(
+            parse(&state, vec![Elem::Control('a')])[1],
+            C::Increment(1, 1)
+        );
+        let mut elems = range_string("12");
+        elems.push(Elem::Control('x'));
+        assert_eq!(parse(&state, elems)[1], C::Increment(12, -1));
+    }
+
+    #[test]
+    fn line_ops() {
+        use Command as C;
+        let parse =
+            |state: &ModeState, elems: Vec<Elem>| state.command(&elems).map(|(_, c)| c).unwrap();
+        let state = ModeState::default();
+        assert_eq!(
+            parse(&state, range_string("3>>")),
+            vec![
+                C::ChangeStart,
+                C::LineOp(3, LineOp::Shift(1), Motion::Line),
+                C::ChangeEnd
+            ]
+        );
+        assert_eq!(
+            parse(&state, range_string("<j"))[1],
+            C::LineOp(1, LineOp::Shift(-1), Motion::Down)
+        );
+        assert_eq!(
+            parse(&state, range_string("=ip"))[1],
+            C::LineOp(
+                1,
+                LineOp::Reindent,
+                Motion::Inner(editor_core::TextObject::Paragraph)
+            )
+        );
+        assert_eq!(
+            parse(&state, range_string("gqgq"))[1],
+            C::LineOp(1, LineOp::Format(false), Motion::Line)
+        );
+        assert_eq!(
+            parse(&state, range_string("gw}"))[1],
+            C::LineOp(1, LineOp::Format(true), Motion::ParagraphForward)
+        );
+        assert_eq!(
+            parse(&state, range_string("2gcc"))[1],
+            C::LineOp(2, LineOp::Comment, Motion::Line)
+        );
+        assert_eq!(
+            parse(&state, range_string("gcj"))[1],
+            C::LineOp(1, LineOp::Comment, Motion::Down)
+        );
+        let mut state = ModeState::default();
+        state.change_mode(Mode::Visual);
+        assert_eq!(
+            parse(&state, range_string("gq"))[1],
+            C::LineOp(1, LineOp::Format(false), Motion::Selection)
+        );
+        assert_eq!(
+            parse(&state, range_string("gc"))[1],
+            C::LineOp(1, LineOp::Comment, Motion::Selection)
+        );
+    }
 }
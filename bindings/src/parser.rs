@@ -1,7 +1,8 @@
 use super::helpers::*;
 use super::range::{Elem, Range, R};
 use crossterm::event::Event;
-use editor_core::{Command, MacroId, Mode, Motion, Register};
+use editor_core::marks;
+use editor_core::{Case, CharSearch, Command, LineOp, MacroId, Mode, Motion, Register, TextObject};
 use log::*;
 use nom::combinator;
 use std::convert::From;
@@ -112,6 +113,16 @@ impl MotionParse {
                 '$' => Some(Motion::EOL),
                 '^' => Some(Motion::SOLT),
                 '0' => Some(Motion::SOL),
+                ';' => Some(Motion::RepeatFind(false)),
+                ',' => Some(Motion::RepeatFind(true)),
+                '{' => Some(Motion::ParagraphBack),
+                '}' => Some(Motion::ParagraphForward),
+                '(' => Some(Motion::SentenceBack),
+                ')' => Some(Motion::SentenceForward),
+                '%' => Some(Motion::MatchBracket),
+                'H' => Some(Motion::ScreenTop),
+                'M' => Some(Motion::ScreenMiddle),
+                'L' => Some(Motion::ScreenBottom),
                 _ => None,
             },
             _ => None,
@@ -119,20 +130,78 @@ impl MotionParse {
     }
 
     fn p_motion(i: Range) -> IResult<Range, Motion> {
+        let find = R::oneof(&[
+            Elem::Char('f'),
+            Elem::Char('F'),
+            Elem::Char('t'),
+            Elem::Char('T'),
+        ]);
         alt((
-            map(tuple((R::tag_string("t"), R::char())), |(_, ch)| {
-                Motion::Til1(ch)
+            map(tuple((find, R::char())), |(op, ch)| {
+                let forward = op == Elem::Char('f') || op == Elem::Char('t');
+                let till = op == Elem::Char('t') || op == Elem::Char('T');
+                Motion::Find(CharSearch::new(ch, forward, till))
             }),
-            map(tuple((R::tag_string("T"), R::char())), |(_, ch)| {
-                Motion::Til2(ch)
+            value(Motion::BackWordEnd1, R::tag_string("ge")),
+            value(Motion::BackWordEnd2, R::tag_string("gE")),
+            map_opt(tuple((R::tag_string("`"), R::char())), |(_, ch)| {
+                Self::_mark(ch).map(Motion::Mark)
             }),
+            map_opt(tuple((R::tag_string("'"), R::char())), |(_, ch)| {
+                Self::_mark(ch).map(Motion::MarkLine)
+            }),
+            value(Motion::DisplayDown, R::tag_string("gj")),
+            value(Motion::DisplayUp, R::tag_string("gk")),
             map_opt(R::take(1), Self::_next),
         ))(i)
     }
 
+    // ` is another name for the previous context mark
+    fn _mark(ch: char) -> Option<char> {
+        match ch {
+            '`' => Some(marks::PREVIOUS),
+            _ if marks::is_mark(ch) => Some(ch),
+            _ => None,
+        }
+    }
+
     fn motion() -> impl FnMut(Range) -> IResult<Range, Motion> {
         |i| Self::p_motion(i)
     }
+
+    fn _object(ch: char) -> Option<TextObject> {
+        use TextObject::*;
+        match ch {
+            'w' => Some(Word),
+            'W' => Some(BigWord),
+            's' => Some(Sentence),
+            'p' => Some(Paragraph),
+            '"' | '\'' | '`' => Some(Quote(ch)),
+            '(' | ')' | 'b' => Some(Block('(', ')')),
+            '{' | '}' | 'B' => Some(Block('{', '}')),
+            '[' | ']' => Some(Block('[', ']')),
+            '<' | '>' => Some(Block('<', '>')),
+            't' => Some(Tag),
+            _ => None,
+        }
+    }
+
+    fn p_object(i: Range) -> IResult<Range, Motion> {
+        map_opt(
+            tuple((R::oneof(&[Elem::Char('i'), Elem::Char('a')]), R::char())),
+            |(ia, ch)| {
+                Self::_object(ch).map(|obj| match ia {
+                    Elem::Char('i') => Motion::Inner(obj),
+                    _ => Motion::Around(obj),
+                })
+            },
+        )(i)
+    }
+
+    // operators take a text object or a motion
+    fn target() -> impl FnMut(Range) -> IResult<Range, Motion> {
+        |i| alt((Self::p_object, Self::p_motion))(i)
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -271,8 +340,8 @@ impl<'a> T {
     pub fn p_register_motion(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
         use Command as C;
 
-        let char_motion = tuple((R::char(), MotionParse::motion()));
-        let x = Register('x');
+        let char_motion = tuple((R::char(), MotionParse::target()));
+        let x = Register::UNNAMED;
         alt((
             combinator::map_opt(
                 tuple((Self::register_or(x), char_motion)),
@@ -299,11 +368,23 @@ impl<'a> T {
         })
     }
 
-    fn register_or(r: Register) -> impl FnMut(Range<'a>) -> IResult<Range<'a>, Register> {
+    pub fn register_or(r: Register) -> impl FnMut(Range<'a>) -> IResult<Range<'a>, Register> {
         move |i| combinator::map(opt(Self::register()), |o| o.unwrap_or(r))(i)
     }
 
-    fn number_or(n: usize) -> impl FnMut(Range<'a>) -> IResult<Range<'a>, usize> {
+    // a count and register before an operator, the count can be on either side of the register
+    pub fn count_register() -> impl FnMut(Range<'a>) -> IResult<Range<'a>, (usize, Register)> {
+        combinator::map(
+            tuple((
+                Self::number_or(1),
+                Self::register_or(Register::UNNAMED),
+                Self::number_or(1),
+            )),
+            |(n1, reg, n2)| (n1 * n2, reg),
+        )
+    }
+
+    pub fn number_or(n: usize) -> impl FnMut(Range<'a>) -> IResult<Range<'a>, usize> {
         move |i| Self::p_number_or(i, n)
     }
 
@@ -316,19 +397,75 @@ impl<'a> T {
         use Elem::*;
         use Motion as M;
         let d_motion = tuple((
-            Self::number_or(1),
+            Self::count_register(),
             R::oneof(&[Elem::Char('d'), Elem::Char('c')]),
-            MotionParse::motion(),
+            MotionParse::target(),
         ));
-        let dd = tuple((Self::number_or(1), R::tag_string("dd")));
-        let x = tuple((Self::number_or(1), R::tag_string("x")));
+        let dd = tuple((Self::count_register(), R::tag_string("dd")));
+        let x = tuple((Self::count_register(), R::tag_string("x")));
         let paste = tuple((
-            Self::number_or(1),
-            Self::register_or(Register('x')),
+            Self::count_register(),
             R::oneof(&[Elem::Char('p'), Elem::Char('P'), Elem::Alt('v')]),
         ));
+        let case = |i| {
+            alt((
+                value(Case::Toggle, R::tag_string("g~")),
+                value(Case::Lower, R::tag_string("gu")),
+                value(Case::Upper, R::tag_string("gU")),
+            ))(i)
+        };
+        // g~~ and g~g~ both change the whole line
+        let case_line = |i| {
+            alt((
+                value(
+                    Case::Toggle,
+                    alt((R::tag_string("g~~"), R::tag_string("g~g~"))),
+                ),
+                value(
+                    Case::Lower,
+                    alt((R::tag_string("guu"), R::tag_string("gugu"))),
+                ),
+                value(
+                    Case::Upper,
+                    alt((R::tag_string("gUU"), R::tag_string("gUgU"))),
+                ),
+            ))(i)
+        };
+        let line_op = |i| {
+            alt((
+                value(LineOp::Shift(1), R::tag_string(">")),
+                value(LineOp::Shift(-1), R::tag_string("<")),
+                value(LineOp::Reindent, R::tag_string("=")),
+                value(LineOp::Format(false), R::tag_string("gq")),
+                value(LineOp::Format(true), R::tag_string("gw")),
+                value(LineOp::Comment, R::tag_string("gc")),
+            ))(i)
+        };
+        // >> and gqq, gqgq or gcc take count lines
+        let line_op_line = |i| {
+            alt((
+                value(LineOp::Shift(1), R::tag_string(">>")),
+                value(LineOp::Shift(-1), R::tag_string("<<")),
+                value(LineOp::Reindent, R::tag_string("==")),
+                value(
+                    LineOp::Format(false),
+                    alt((R::tag_string("gqq"), R::tag_string("gqgq"))),
+                ),
+                value(
+                    LineOp::Format(true),
+                    alt((R::tag_string("gww"), R::tag_string("gwgw"))),
+                ),
+                value(
+                    LineOp::Comment,
+                    alt((R::tag_string("gcc"), R::tag_string("gcgc"))),
+                ),
+            ))(i)
+        };
+        let lines = |reps, op, m| vec![C::ChangeStart, C::LineOp(reps, op, m), C::ChangeEnd];
+        let change_case =
+            |reps, case, m| vec![C::ChangeStart, C::ChangeCase(reps, case, m), C::ChangeEnd];
         alt((
-            combinator::map_opt(paste, |(reps, reg, op)| match op {
+            combinator::map_opt(paste, |((reps, reg), op)| match op {
                 Elem::Alt('p') => Some(vec![
                     C::ChangeStart,
                     C::Paste(reps, reg, M::OnCursor),
@@ -336,67 +473,63 @@ impl<'a> T {
                 ]),
                 Elem::Char('P') => Some(vec![
                     C::ChangeStart,
-                    C::Paste(reps, reg, M::SOL),
+                    C::Paste(reps, reg, M::OnCursor),
                     C::ChangeEnd,
                 ]),
                 Elem::Char('p') => Some(vec![
                     C::ChangeStart,
-                    C::Paste(reps, reg, M::NextLine),
+                    C::Paste(reps, reg, M::Right),
                     C::ChangeEnd,
                 ]),
                 _ => None,
             }),
-            combinator::map(x, |(reps, _)| {
-                vec![C::ChangeStart, C::Delete(reps, Motion::Right), C::ChangeEnd]
+            combinator::map(x, |((reps, reg), _)| {
+                vec![
+                    C::ChangeStart,
+                    C::Delete(reps, reg, Motion::Right),
+                    C::ChangeEnd,
+                ]
             }),
-            combinator::map(dd, |(reps, _)| {
-                vec![C::ChangeStart, C::Delete(reps, Motion::Line), C::ChangeEnd]
+            combinator::map(dd, |((reps, reg), _)| {
+                vec![
+                    C::ChangeStart,
+                    C::Delete(reps, reg, Motion::Line),
+                    C::ChangeEnd,
+                ]
             }),
-            combinator::map_opt(d_motion, |(reps, op, m)| match op {
-                Elem::Char('d') => Some(vec![C::ChangeStart, C::Delete(reps, m), C::ChangeEnd]),
+            combinator::map(
+                tuple((Self::number_or(1), line_op_line)),
+                move |(reps, op)| lines(reps, op, M::Line),
+            ),
+            combinator::map(
+                tuple((Self::number_or(1), line_op, MotionParse::target())),
+                move |(reps, op, m)| lines(reps, op, m),
+            ),
+            combinator::map(
+                tuple((Self::number_or(1), case_line)),
+                move |(reps, case)| change_case(reps, case, M::Line),
+            ),
+            combinator::map(
+                tuple((Self::number_or(1), case, MotionParse::target())),
+                move |(reps, case, m)| change_case(reps, case, m),
+            ),
+            combinator::map(
+                tuple((Self::number_or(1), R::tag_string("~"))),
+                move |(reps, _)| change_case(reps, Case::Toggle, M::Right),
+            ),
+            combinator::map_opt(d_motion, |((reps, reg), op, m)| match op {
+                Elem::Char('d') => {
+                    Some(vec![C::ChangeStart, C::Delete(reps, reg, m), C::ChangeEnd])
+                }
                 Elem::Char('c') => Some(vec![
                     C::ChangeStart,
-                    C::Delete(reps, m),
+                    C::Delete(reps, reg, m),
                     C::Mode(Mode::Insert),
                 ]),
                 _ => None,
             }),
         ))(i)
     }
-
-    fn p_macros(i: Range<'a>, record: Option<MacroId>) -> IResult<Range<'a>, Vec<Command>> {
-        info!("p_macros:{:?}", (i, record));
-        use combinator::*;
-        //map_opt(alt((
-        //cond(record.is_none(), value(Command::MacroEnd.into(), R::tag(&[Elem::Char('q')]))),
-        //cond(record.is_some(), map(tuple((R::tag(&[Elem::Char('q')]), R::char())), |(_,ch)| {
-        //Command::MacroStart(MacroId(ch)).into()
-        //}))
-        //)), |v| v)(i)
-
-        match record {
-            Some(_) => {
-                value(Command::MacroEnd.into(), R::tag(&[Elem::Char('q')]))(i)
-                //Self::p_macro_exit(r)
-            }
-            None => {
-                map(tuple((R::tag(&[Elem::Char('q')]), R::char())), |(_, ch)| {
-                    Command::MacroStart(MacroId(ch)).into()
-                })(i)
-                //Self::p_macro_enter(r)
-            }
-        }
-    }
-
-    fn p_macro_enter(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
-        combinator::map(tuple((R::tag(&[Elem::Char('q')]), R::char())), |(_, ch)| {
-            Command::MacroStart(MacroId(ch)).into()
-        })(i)
-    }
-
-    fn p_macro_exit(i: Range<'a>) -> IResult<Range<'a>, Vec<Command>> {
-        value(Command::MacroEnd.into(), R::tag(&[Elem::Char('q')]))(i)
-    }
 }
 
 #[derive(Debug)]
@@ -407,7 +540,8 @@ pub enum ParseError {
 
 #[cfg(test)]
 mod tests {
-    use super::super::range::range_enter;
+    use super::super::modestate::ModeState;
+    use super::super::range::{range_enter, range_string};
     use super::*;
 
     #[test]
@@ -456,4 +590,137 @@ mod tests {
         let (_, v) = state.command(i.as_slice()).unwrap();
         assert_eq!(v, vec![Command::Line(1234)]);
     }
+
+    #[test]
+    fn test_text_objects() {
+        use Command as C;
+        let state = ModeState::default();
+        let (_, v) = state.command(&range_string("ci\"")).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                C::ChangeStart,
+                C::Delete(1, Register::UNNAMED, Motion::Inner(TextObject::Quote('"'))),
+                C::Mode(Mode::Insert)
+            ]
+        );
+        let (_, v) = state.command(&range_string("2da(")).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                C::ChangeStart,
+                C::Delete(
+                    2,
+                    Register::UNNAMED,
+                    Motion::Around(TextObject::Block('(', ')'))
+                ),
+                C::ChangeEnd
+            ]
+        );
+        let (_, v) = state.command(&range_string("yiw")).unwrap();
+        assert_eq!(
+            v,
+            vec![C::Yank(Register::UNNAMED, Motion::Inner(TextObject::Word))]
+        );
+        // waits for the object
+        assert!(state.command(&range_string("di")).is_err());
+    }
+
+    #[test]
+    fn test_char_search() {
+        use crate::InputReader;
+        use Command as C;
+        let mut reader = InputReader::default();
+        let mut keys = |s: &str| {
+            range_string(s)
+                .into_iter()
+                .flat_map(|e| reader.add(e))
+                .collect::<Vec<Command>>()
+        };
+        let search = CharSearch::new(')', true, true);
+        assert_eq!(
+            keys("dt)"),
+            vec![C::Delete(1, Register::UNNAMED, Motion::Find(search))]
+        );
+        let search = CharSearch::new(',', false, false);
+        assert_eq!(keys("3F,"), vec![C::Motion(3, Motion::Find(search))]);
+
+        // ; and , repeat the last search
+        assert_eq!(
+            keys(";"),
+            vec![C::Motion(1, Motion::Find(search.repeat(false)))]
+        );
+        assert_eq!(
+            keys("2,"),
+            vec![C::Motion(2, Motion::Find(search.repeat(true)))]
+        );
+        assert!(search.repeat(true).forward);
+    }
+
+    #[test]
+    fn test_word_end() {
+        use Command as C;
+        let state = ModeState::default();
+        let (_, v) = state.command(&range_string("2gE")).unwrap();
+        assert_eq!(v, vec![C::Motion(2, Motion::BackWordEnd2)]);
+        let (_, v) = state.command(&range_string("dge")).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                C::ChangeStart,
+                C::Delete(1, Register::UNNAMED, Motion::BackWordEnd1),
+                C::ChangeEnd
+            ]
+        );
+    }
+
+    #[test]
+    fn test_motions() {
+        use Command as C;
+        let state = ModeState::default();
+        let (_, v) = state.command(&range_string("2}")).unwrap();
+        assert_eq!(v, vec![C::Motion(2, Motion::ParagraphForward)]);
+        let (_, v) = state.command(&range_string("3gj")).unwrap();
+        assert_eq!(v, vec![C::Motion(3, Motion::DisplayDown)]);
+        let (_, v) = state.command(&range_string("H")).unwrap();
+        assert_eq!(v, vec![C::Motion(1, Motion::ScreenTop)]);
+        let (_, v) = state.command(&range_string("'a")).unwrap();
+        assert_eq!(v, vec![C::Motion(1, Motion::MarkLine('a'))]);
+        let (_, v) = state.command(&range_string("``")).unwrap();
+        assert_eq!(v, vec![C::Motion(1, Motion::Mark('\''))]);
+        let (_, v) = state.command(&range_string("mB")).unwrap();
+        assert_eq!(v, vec![C::MarkSet('B')]);
+        let (_, v) = state.command(&range_string("d%")).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                C::ChangeStart,
+                C::Delete(1, Register::UNNAMED, Motion::MatchBracket),
+                C::ChangeEnd
+            ]
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        use Command as C;
+        let state = ModeState::default();
+        let (_, v) = state.command(&range_string("\"a3dw")).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                C::ChangeStart,
+                C::Delete(3, Register('a'), Motion::ForwardWord1),
+                C::ChangeEnd
+            ]
+        );
+        let (_, v) = state.command(&range_string("2\"_dd")).unwrap();
+        assert_eq!(v[1], C::Delete(2, Register('_'), Motion::Line));
+        let (_, v) = state.command(&range_string("\"Ayy")).unwrap();
+        assert_eq!(v, vec![C::Yank(Register('A'), Motion::Line)]);
+        let (_, v) = state.command(&range_string("2\"1P")).unwrap();
+        assert_eq!(v[1], C::Paste(2, Register('1'), Motion::OnCursor));
+        let (_, v) = state.command(&range_string("p")).unwrap();
+        assert_eq!(v[1], C::Paste(1, Register::UNNAMED, Motion::Right));
+    }
 }
@@ -1,6 +1,6 @@
 use super::helpers::*;
 use crossterm::event::Event;
-use editor_core::{Command, MacroId, Macros, Mode, Motion, Register};
+use editor_core::{Command, MacroId, Mode, Motion, Register};
 use log::*;
 use nom::combinator;
 use std::convert::From;
@@ -41,6 +41,64 @@ impl Elem {
     }
 }
 
+// keys that aren't a char are written with the 0x80 prefix vim uses in registers
+const SPECIAL: char = '\u{80}';
+
+// the keys of a macro as register text, control keys are control chars and
+// Esc, Enter and Tab are the chars a terminal sends for them
+pub fn keys_to_string(keys: &[Elem]) -> String {
+    let mut s = String::new();
+    for key in keys {
+        match key {
+            Elem::Char(c) => s.push(*c),
+            Elem::Control(c) if c.is_ascii_alphabetic() || "@[\\]^_".contains(*c) => {
+                s.push((c.to_ascii_uppercase() as u8 ^ 0x40) as char)
+            }
+            Elem::Enter => s.push('\r'),
+            Elem::Esc => s.push('\x1b'),
+            Elem::Tab => s.push('\t'),
+            Elem::Control(c) => s.extend([SPECIAL, 'C', *c]),
+            Elem::Alt(c) => s.extend([SPECIAL, 'M', *c]),
+            Elem::Backspace => s.extend([SPECIAL, 'k', 'b']),
+            Elem::Delete => s.extend([SPECIAL, 'k', 'D']),
+            Elem::Up => s.extend([SPECIAL, 'k', 'u']),
+            Elem::Down => s.extend([SPECIAL, 'k', 'd']),
+            Elem::Left => s.extend([SPECIAL, 'k', 'l']),
+            Elem::Right => s.extend([SPECIAL, 'k', 'r']),
+            Elem::Resize(_, _) => (),
+        }
+    }
+    s
+}
+
+// register text back into keys, the reverse of keys_to_string
+pub fn string_to_keys(s: &str) -> Vec<Elem> {
+    let mut keys = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\r' | '\n' => Elem::Enter,
+            '\x1b' => Elem::Esc,
+            '\t' => Elem::Tab,
+            c if (c as u32) < 0x20 => Elem::Control((c as u8 ^ 0x40).to_ascii_lowercase() as char),
+            SPECIAL => match (chars.next(), chars.next()) {
+                (Some('C'), Some(c)) => Elem::Control(c),
+                (Some('M'), Some(c)) => Elem::Alt(c),
+                (Some('k'), Some('b')) => Elem::Backspace,
+                (Some('k'), Some('D')) => Elem::Delete,
+                (Some('k'), Some('u')) => Elem::Up,
+                (Some('k'), Some('d')) => Elem::Down,
+                (Some('k'), Some('l')) => Elem::Left,
+                (Some('k'), Some('r')) => Elem::Right,
+                _ => continue,
+            },
+            c => Elem::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
 pub fn range_enter(s: &str) -> Vec<Elem> {
     let mut v = range_string(s);
     v.push(Elem::Enter);
@@ -191,3 +249,39 @@ impl<'a> R<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_string() {
+        let keys = vec![
+            Elem::Char('i'),
+            Elem::Char('x'),
+            Elem::Esc,
+            Elem::Control('a'),
+            Elem::Control('['),
+            Elem::Enter,
+            Elem::Tab,
+            Elem::Alt('f'),
+            Elem::Backspace,
+            Elem::Up,
+            Elem::Right,
+            Elem::Control('1'),
+        ];
+        let s = keys_to_string(&keys);
+        assert_eq!(&s[..6], "ix\x1b\x01\x1b\r");
+        let mut back = keys.clone();
+        // Ctrl-[ is the same key as Esc
+        back[4] = Elem::Esc;
+        assert_eq!(string_to_keys(&s), back);
+        assert_eq!(
+            string_to_keys("a\nb"),
+            range_enter("a")
+                .into_iter()
+                .chain([Elem::Char('b')])
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,17 +1,21 @@
 use super::*;
 use log::*;
 use parking_lot::RwLock;
+use regex::Regex;
 use ropey::Rope;
 use std::collections::VecDeque;
 use std::convert::From;
 use std::fs::File;
 use std::io;
+use std::ops::Range;
 use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct UndoList {
     ahead: VecDeque<Rope>,
     behind: VecDeque<Rope>,
+    group: usize,
+    group_pushed: bool,
 }
 
 impl Default for UndoList {
@@ -19,6 +23,8 @@ impl Default for UndoList {
         Self {
             ahead: VecDeque::new(),
             behind: VecDeque::new(),
+            group: 0,
+            group_pushed: false,
         }
     }
 }
@@ -49,11 +55,29 @@ impl UndoList {
     }
 
     // push state before change
+    // inside of a group, only the state before the first change is kept
     fn push(&mut self, text: Rope) -> &mut Self {
+        if self.group > 0 {
+            if self.group_pushed {
+                return self;
+            }
+            self.group_pushed = true;
+        }
         self.ahead.truncate(0);
         self.behind.push_front(text);
         self
     }
+
+    fn group_start(&mut self) {
+        self.group += 1;
+    }
+
+    fn group_end(&mut self) {
+        self.group = self.group.saturating_sub(1);
+        if self.group == 0 {
+            self.group_pushed = false;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -63,6 +87,33 @@ pub struct FileBuffer {
     pub config: BufferConfig,
     version: u64,
     history: UndoList,
+    marks: Marks,
+}
+
+impl FileBuffer {
+    // all edits go through insert and remove, so the marks follow the text
+    fn insert(&mut self, c: usize, s: &str) {
+        self.text.insert(c, s);
+        self.marks.inserted(c, s.chars().count());
+        self.changed(c);
+    }
+
+    fn remove(&mut self, range: Range<usize>) {
+        self.marks.removed(range.start, range.end);
+        self.text.remove(range.clone());
+        self.changed(range.start);
+    }
+
+    // changes on the same line as the last one are merged in the change list
+    fn changed(&mut self, c: usize) {
+        let end = self.text.len_chars();
+        let line_inx = self.text.char_to_line(std::cmp::min(c, end));
+        let merge = match self.marks.changes().last() {
+            Some(last) => self.text.char_to_line(std::cmp::min(*last, end)) == line_inx,
+            None => false,
+        };
+        self.marks.changed(c, merge);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -99,7 +150,7 @@ impl Buffer {
     pub fn from_path(path: &String) -> Result<Self, BufferError> {
         let f = File::open(&path.clone())?;
         let text = Rope::from_reader(&mut io::BufReader::new(f))?;
-        let config = BufferConfig::config_for(Some(path));
+        let config = BufferConfig::config_for_text(Some(path), &text);
         info!("Add window: {:?}", config);
         Ok(Self {
             buf: Arc::new(RwLock::new(FileBuffer {
@@ -108,6 +159,7 @@ impl Buffer {
                 config,
                 version: 0,
                 history: UndoList::default(),
+                marks: Marks::default(),
             })),
         })
     }
@@ -121,14 +173,69 @@ impl Buffer {
                 text,
                 version: 0,
                 history: UndoList::default(),
+                marks: Marks::default(),
             })),
         }
     }
 
+    // true if both refer to the same underlying buffer
+    pub fn is_same(&self, other: &Buffer) -> bool {
+        Arc::ptr_eq(&self.buf, &other.buf)
+    }
+
     pub fn get_config(&self) -> BufferConfig {
         self.buf.read().config.clone()
     }
 
+    pub fn set_config(&mut self, config: BufferConfig) -> &mut Self {
+        self.buf.write().config = config;
+        self
+    }
+
+    // the filetype of the buffer, empty when it isn't known
+    pub fn filetype(&self) -> String {
+        self.buf.read().config.filetype.clone()
+    }
+
+    pub fn set_mark(&mut self, ch: char, c: usize) -> &mut Self {
+        self.buf.write().marks.set(ch, c);
+        self
+    }
+
+    // the position of a mark, undo doesn't move marks so it is kept inside the text
+    pub fn get_mark(&self, ch: char) -> Option<usize> {
+        let fb = self.buf.read();
+        fb.marks
+            .get(ch)
+            .map(|c| std::cmp::min(c, fb.text.len_chars()))
+    }
+
+    pub fn remove_mark(&mut self, ch: char) -> &mut Self {
+        self.buf.write().marks.remove(ch);
+        self
+    }
+
+    pub fn get_marks(&self) -> Vec<(char, usize)> {
+        let fb = self.buf.read();
+        let end = fb.text.len_chars();
+        fb.marks
+            .list()
+            .into_iter()
+            .map(|(ch, c)| (ch, std::cmp::min(c, end)))
+            .collect()
+    }
+
+    // change positions, oldest first
+    pub fn get_changes(&self) -> Vec<usize> {
+        let fb = self.buf.read();
+        let end = fb.text.len_chars();
+        fb.marks
+            .changes()
+            .iter()
+            .map(|c| std::cmp::min(*c, end))
+            .collect()
+    }
+
     pub fn get_text(&self) -> Rope {
         self.buf.read().text.clone()
     }
@@ -141,13 +248,33 @@ impl Buffer {
         let mut fb = self.buf.write();
         let u = fb.text.clone();
         let end = fb.text.len_chars();
-        fb.text.remove(0..end);
+        fb.remove(0..end);
         fb.text.append(Rope::from_str(s));
         fb.history.push(u);
         drop(fb);
         self
     }
 
+    // replace all the text and forget the undo history, for lists the editor fills in
+    pub fn reset_text(&mut self, s: &str) -> &mut Self {
+        let mut fb = self.buf.write();
+        let end = fb.text.len_chars();
+        fb.remove(0..end);
+        fb.insert(0, s);
+        fb.history = UndoList::default();
+        drop(fb);
+        self
+    }
+
+    // add text at the end without an undo
+    pub fn append_text(&mut self, s: &str) -> &mut Self {
+        let mut fb = self.buf.write();
+        let end = fb.text.len_chars();
+        fb.insert(end, s);
+        drop(fb);
+        self
+    }
+
     pub fn set_path(&mut self, s: &str) -> &mut Self {
         let mut fb = self.buf.write();
         fb.path = String::from(s);
@@ -165,7 +292,7 @@ impl Buffer {
         }
 
         if start < end0 {
-            fb.text.remove(start..end0);
+            fb.remove(start..end0);
             fb.history.push(u);
         }
         drop(fb);
@@ -184,7 +311,7 @@ impl Buffer {
             })
             .collect::<Vec<String>>()
             .join("");
-        fb.text.insert(c, &out);
+        fb.insert(c, &out);
         info!("insert: {:?}", (c, &out));
         fb.history.push(u);
         drop(fb);
@@ -195,7 +322,7 @@ impl Buffer {
         if c > 0 {
             let mut fb = self.buf.write();
             let u = fb.text.clone();
-            fb.text.remove(c - 1..c);
+            fb.remove(c - 1..c);
             info!("remove: {:?}", (c - 1, c));
             fb.history.push(u);
             drop(fb);
@@ -221,7 +348,7 @@ impl Buffer {
         let start = end - remove;
 
         if remove > 0 {
-            fb.text.remove(start..end);
+            fb.remove(start..end);
             fb.history.push(u);
         }
         drop(fb);
@@ -234,13 +361,231 @@ impl Buffer {
         let c0 = fb.text.line_to_char(start_inx);
         let c1 = fb.text.line_to_char(end_inx);
         if c1 > c0 {
-            fb.text.remove(c0..c1);
+            fb.remove(c0..c1);
             fb.history.push(u);
         }
         drop(fb);
         self
     }
 
+    // insert text as is, without expanding tabs or newlines
+    pub fn insert_text(&mut self, c: usize, s: &str) -> usize {
+        let mut fb = self.buf.write();
+        let u = fb.text.clone();
+        fb.insert(c, s);
+        fb.history.push(u);
+        drop(fb);
+        s.chars().count()
+    }
+
+    // replace the chars start..end with s as one change, returns the chars inserted
+    pub fn replace_range(&mut self, start: usize, end: usize, s: &str) -> usize {
+        let mut fb = self.buf.write();
+        let u = fb.text.clone();
+        let end = std::cmp::min(end, fb.text.len_chars());
+        if start < end {
+            fb.remove(start..end);
+        }
+        fb.insert(start, s);
+        fb.history.push(u);
+        drop(fb);
+        s.chars().count()
+    }
+
+    // copy lines start_inx..end_inx, so they start at line dest_inx
+    pub fn copy_line_range(
+        &mut self,
+        start_inx: usize,
+        end_inx: usize,
+        dest_inx: usize,
+    ) -> &mut Self {
+        let mut fb = self.buf.write();
+        let u = fb.text.clone();
+        let sep = fb.config.line_sep().to_string();
+        let block = line_block(&fb.text, start_inx, end_inx, &sep);
+        insert_line_block(&mut fb, dest_inx, &block, &sep);
+        fb.history.push(u);
+        drop(fb);
+        self
+    }
+
+    // move lines start_inx..end_inx, so they start at what is currently line dest_inx
+    pub fn move_line_range(
+        &mut self,
+        start_inx: usize,
+        end_inx: usize,
+        dest_inx: usize,
+    ) -> &mut Self {
+        if dest_inx >= start_inx && dest_inx <= end_inx {
+            return self;
+        }
+        let mut fb = self.buf.write();
+        let u = fb.text.clone();
+        let sep = fb.config.line_sep().to_string();
+        let block = line_block(&fb.text, start_inx, end_inx, &sep);
+        let c0 = fb.text.line_to_char(start_inx);
+        let c1 = fb.text.line_to_char(end_inx);
+        fb.remove(c0..c1);
+        let dest = if dest_inx > end_inx {
+            dest_inx - (end_inx - start_inx)
+        } else {
+            dest_inx
+        };
+        insert_line_block(&mut fb, dest, &block, &sep);
+        fb.history.push(u);
+        drop(fb);
+        self
+    }
+
+    // replace the first, or all matches of pattern in a line, returns true on a match
+    pub fn substitute_line(
+        &mut self,
+        line_inx: usize,
+        pattern: &Regex,
+        replacement: &str,
+        all: bool,
+    ) -> bool {
+        let mut fb = self.buf.write();
+        if line_inx >= fb.text.len_lines() {
+            return false;
+        }
+        let line = fb.text.line(line_inx).to_string();
+        let content = line.trim_end_matches(&['\r', '\n'][..]);
+        if !pattern.is_match(content) {
+            return false;
+        }
+        let limit = if all { 0 } else { 1 };
+        let replaced = pattern.replacen(content, limit, replacement).to_string();
+        let u = fb.text.clone();
+        let c0 = fb.text.line_to_char(line_inx);
+        fb.remove(c0..c0 + content.chars().count());
+        fb.insert(c0, &replaced);
+        fb.history.push(u);
+        true
+    }
+
+    // shift the indentation of a line by a number of indent levels, empty lines are left alone
+    pub fn shift_line(&mut self, line_inx: usize, levels: i32) -> &mut Self {
+        let mut fb = self.buf.write();
+        let line = fb.text.line(line_inx).to_string();
+        if line.trim_end_matches(&['\r', '\n'][..]).is_empty() {
+            drop(fb);
+            return self;
+        }
+        let (width, chars) = fb.config.leading_indent(&line);
+        let step = fb.config.indent_width() as i64 * levels as i64;
+        let indent = fb
+            .config
+            .indent_to(std::cmp::max(0, width as i64 + step) as usize);
+        let u = fb.text.clone();
+        let c0 = fb.text.line_to_char(line_inx);
+        fb.remove(c0..c0 + chars);
+        fb.insert(c0, &indent);
+        fb.history.push(u);
+        drop(fb);
+        self
+    }
+
+    // lines start_inx..end_inx without their line endings
+    fn line_contents(&self, start_inx: usize, end_inx: usize) -> Vec<String> {
+        let text = self.get_text();
+        (start_inx..end_inx)
+            .map(|line_inx| {
+                let line = text.line(line_inx).to_string();
+                line.trim_end_matches(&['\r', '\n'][..]).to_string()
+            })
+            .collect()
+    }
+
+    // replace lines start_inx..end_inx, keeping the line ending after the last one
+    fn set_line_contents(&mut self, start_inx: usize, end_inx: usize, lines: &[String]) {
+        let text = self.get_text();
+        let c0 = text.line_to_char(start_inx);
+        let c1 = text.line_to_char(end_inx);
+        let old = text.slice(c0..c1).to_string();
+        let content = old.trim_end_matches(&['\r', '\n'][..]);
+        let sep = self.get_config().line_sep().to_string();
+        let new = lines.join(&sep) + &old[content.len()..];
+        if new != old {
+            self.replace_range(c0, c1, &new);
+        }
+    }
+
+    // indent lines start_inx..end_inx again, following the nearest non-blank line above them
+    pub fn reindent_lines(&mut self, start_inx: usize, end_inx: usize) -> &mut Self {
+        let config = self.get_config();
+        let text = self.get_text();
+        let above = (0..start_inx)
+            .rev()
+            .map(|line_inx| text.line(line_inx).to_string())
+            .find(|line| !line.trim().is_empty());
+        let lines = self.line_contents(start_inx, end_inx);
+        let lines = indent::reindent(above.as_deref(), &lines, &config);
+        self.set_line_contents(start_inx, end_inx, &lines);
+        self
+    }
+
+    // :retab, the whitespace in lines start_inx..end_inx was written with tab_width
+    pub fn retab_lines(
+        &mut self,
+        start_inx: usize,
+        end_inx: usize,
+        tab_width: usize,
+        bang: bool,
+    ) -> &mut Self {
+        let config = self.get_config();
+        let lines = self
+            .line_contents(start_inx, end_inx)
+            .iter()
+            .map(|line| indent::retab(line, tab_width, bang, config.retab_leading, &config))
+            .collect::<Vec<_>>();
+        self.set_line_contents(start_inx, end_inx, &lines);
+        self
+    }
+
+    // :reindent, indent levels of from columns in lines start_inx..end_inx become levels of the
+    // configured indent size and style
+    pub fn relevel_lines(&mut self, start_inx: usize, end_inx: usize, from: usize) -> &mut Self {
+        let config = self.get_config();
+        let lines = self
+            .line_contents(start_inx, end_inx)
+            .iter()
+            .map(|line| indent::relevel(line, from, &config))
+            .collect::<Vec<_>>();
+        self.set_line_contents(start_inx, end_inx, &lines);
+        self
+    }
+
+    // fill the paragraphs in lines start_inx..end_inx to the text width, returns the number of
+    // lines they take now
+    pub fn format_lines(&mut self, start_inx: usize, end_inx: usize) -> usize {
+        let config = self.get_config();
+        let lines = self.line_contents(start_inx, end_inx);
+        let lines = reflow::reflow(&lines, config.fill_width(), config.tab_width as usize);
+        self.set_line_contents(start_inx, end_inx, &lines);
+        lines.len()
+    }
+
+    // comment lines start_inx..end_inx with the comment string of the filetype, or uncomment them
+    pub fn comment_lines(&mut self, start_inx: usize, end_inx: usize) -> &mut Self {
+        let config = self.get_config();
+        let lines = self.line_contents(start_inx, end_inx);
+        let lines = comment::toggle(&lines, &config.comment);
+        self.set_line_contents(start_inx, end_inx, &lines);
+        self
+    }
+
+    // group all changes until change_end into a single undo
+    pub fn change_start(&mut self) -> &mut Self {
+        self.buf.write().history.group_start();
+        self
+    }
+
+    pub fn change_end(&mut self) -> &mut Self {
+        self.buf.write().history.group_end();
+        self
+    }
+
     pub fn undo(&mut self) -> &mut Self {
         let mut fb = self.buf.write();
         let save = fb.text.clone();
@@ -264,6 +609,32 @@ impl Buffer {
 
 pub type LockedFileBuffer = Arc<RwLock<FileBuffer>>;
 
+// the text of lines start_inx..end_inx, always terminated by a line separator
+fn line_block(text: &Rope, start_inx: usize, end_inx: usize, sep: &str) -> String {
+    let c0 = text.line_to_char(start_inx);
+    let c1 = text.line_to_char(end_inx);
+    let mut block = text.slice(c0..c1).to_string();
+    if !block.ends_with('\n') && !block.ends_with('\r') {
+        block.push_str(sep);
+    }
+    block
+}
+
+fn insert_line_block(fb: &mut FileBuffer, dest_inx: usize, block: &str, sep: &str) {
+    let text = &fb.text;
+    let end = text.len_chars();
+    let c = text.line_to_char(std::cmp::min(dest_inx, text.len_lines()));
+    if c == end && text.line(text.len_lines() - 1).len_chars() > 0 {
+        // the last line has no line separator, so add one before the block instead
+        fb.insert(
+            end,
+            &format!("{}{}", sep, block.strip_suffix(sep).unwrap_or(block)),
+        );
+    } else {
+        fb.insert(c, block);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -293,4 +664,156 @@ mod tests {
         fb.remove_range(0, 1);
         println!("{:?}", fb);
     }
+
+    #[test]
+    fn test_move_copy_lines() {
+        let mut fb = Buffer::from_string(&"a\nb\nc".to_string());
+        fb.move_line_range(0, 1, 3);
+        assert_eq!(fb.get_text().to_string(), "b\nc\na");
+        fb.copy_line_range(1, 2, 0);
+        assert_eq!(fb.get_text().to_string(), "c\nb\nc\na");
+        fb.move_line_range(3, 4, 1);
+        assert_eq!(fb.get_text().to_string(), "c\na\nb\nc\n");
+    }
+
+    #[test]
+    fn test_change_group() {
+        let mut fb = Buffer::from_string(&"a\nb\nc\n".to_string());
+        let re = Regex::new("a").unwrap();
+        fb.change_start();
+        assert!(fb.substitute_line(0, &re, "x", false));
+        assert!(!fb.substitute_line(1, &re, "x", false));
+        assert!(!fb.substitute_line(99, &re, "x", false));
+        fb.delete_line_range(2, 3);
+        fb.change_end();
+        assert_eq!(fb.get_text().to_string(), "x\nb\n");
+        fb.undo();
+        assert_eq!(fb.get_text().to_string(), "a\nb\nc\n");
+        fb.redo();
+        assert_eq!(fb.get_text().to_string(), "x\nb\n");
+    }
+
+    #[test]
+    fn test_shift_line() {
+        let mut fb = Buffer::from_string(&"a\n\n  b\n\tc\n".to_string());
+        fb.shift_line(0, 1)
+            .shift_line(1, 1)
+            .shift_line(2, -1)
+            .shift_line(3, 1);
+        assert_eq!(fb.get_text().to_string(), "    a\n\nb\n        c\n");
+    }
+
+    #[test]
+    fn test_reindent_format_lines() {
+        let mut fb = Buffer::from_string(&"if a {\n  b\n}\nc d e".to_string());
+        fb.reindent_lines(1, 3);
+        assert_eq!(fb.get_text().to_string(), "if a {\n    b\n}\nc d e");
+        let mut config = fb.get_config();
+        config.textwidth = 3;
+        fb.set_config(config);
+        assert_eq!(fb.format_lines(3, 4), 2);
+        assert_eq!(fb.get_text().to_string(), "if a {\n    b\n}\nc d\ne");
+    }
+
+    #[test]
+    fn test_detect_indent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.c").to_string_lossy().to_string();
+        std::fs::write(&path, "a {\n\tb;\n}\n").unwrap();
+        let fb = Buffer::from_path(&path).unwrap();
+        let mut config = fb.get_config();
+        assert_eq!(config.indent_status(), "tabs:4 (detected)");
+
+        // :set overrides it
+        config.set_option("et", "true").unwrap();
+        config.set_option("sw", "2").unwrap();
+        assert_eq!(config.indent(), "  ");
+        assert_eq!(config.indent_status(), "spaces:2 (set)");
+    }
+
+    #[test]
+    fn test_filetype() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run").to_string_lossy().to_string();
+        std::fs::write(&path, "#!/usr/bin/python3\nif a:\n  b\n").unwrap();
+        let fb = Buffer::from_path(&path).unwrap();
+        assert_eq!(fb.filetype(), "python");
+        let mut config = fb.get_config();
+        assert_eq!(config.comment, "# %s");
+        assert_eq!(config.indent_chars, ":");
+        // the indent in the text wins over the filetype
+        assert_eq!(config.indent_status(), "spaces:2 (detected)");
+
+        config.set_option("ft", "go").unwrap();
+        assert_eq!(config.get_option("filetype").unwrap(), "go");
+        assert_eq!(config.comment, "// %s");
+        assert_eq!(config.indent(), "  ");
+        assert!(config.set_option("ft", "a b").is_err());
+
+        let config = BufferConfig::config_for(Some("Makefile"));
+        assert_eq!(config.filetype, "make");
+        assert_eq!(config.indent(), "\t");
+
+        // an .editorconfig maps other files to a filetype, or to a new one
+        let ec = "[*.tpl]\nfiletype = html\n[*.foo]\nfiletype = foo\ncomment_string = -- %s\n";
+        std::fs::write(dir.path().join(".editorconfig"), ec).unwrap();
+        let path = dir.path().join("a.tpl").to_string_lossy().to_string();
+        std::fs::write(&path, "<b>\n").unwrap();
+        let config = Buffer::from_path(&path).unwrap().get_config();
+        assert_eq!(config.filetype, "html");
+        assert_eq!(config.comment, "<!-- %s -->");
+        let path = dir.path().join("a.foo").to_string_lossy().to_string();
+        std::fs::write(&path, "x\n").unwrap();
+        let config = Buffer::from_path(&path).unwrap().get_config();
+        assert_eq!(config.filetype, "foo");
+        assert_eq!(config.comment, "-- %s");
+    }
+
+    #[test]
+    fn test_modelines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt").to_string_lossy().to_string();
+        let text = "a\n\tb\n# vim: set ft=yaml ts=2 sw=3 et so=9 ml=0:\n";
+        std::fs::write(&path, text).unwrap();
+        let config = Buffer::from_path(&path).unwrap().get_config();
+        assert_eq!(config.filetype, "yaml");
+        assert_eq!(config.tab_width, 2);
+        assert_eq!(config.indent_status(), "spaces:3 (set)");
+        // options that aren't allowed are ignored
+        assert_eq!(config.scrolloff, 0);
+        assert!(config.modeline);
+
+        let path = dir.path().join("b.c").to_string_lossy().to_string();
+        std::fs::write(
+            &path,
+            "/* -*- indent-tabs-mode: t; c-basic-offset: 8 -*- */\n",
+        )
+        .unwrap();
+        let config = Buffer::from_path(&path).unwrap().get_config();
+        assert_eq!(config.indent(), "\t");
+
+        // and they can be turned off
+        std::fs::write(dir.path().join(".editorconfig"), "[*]\nmodeline = false\n").unwrap();
+        let path = dir.path().join("a.txt").to_string_lossy().to_string();
+        let config = Buffer::from_path(&path).unwrap().get_config();
+        assert_eq!(config.filetype, "text");
+        assert_eq!(config.tab_width, 4);
+        assert_eq!(config.indent_status(), "tabs:4 (detected)");
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut fb = Buffer::from_string(&"a\nb\nc\nd\n".to_string());
+        fb.set_mark('x', 6);
+        fb.insert_string(0, "z\n");
+        assert_eq!(fb.get_mark('x'), Some(8));
+        fb.remove_range(0, 2);
+        assert_eq!(fb.get_mark('x'), Some(6));
+        fb.delete_line_range(0, 1);
+        assert_eq!(fb.get_mark('x'), Some(4));
+        assert_eq!(fb.get_mark('.'), Some(0));
+        // a mark on a deleted line moves to the start of the deletion
+        fb.delete_line_range(1, 3);
+        assert_eq!(fb.get_mark('x'), Some(2));
+    }
 }
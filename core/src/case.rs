@@ -0,0 +1,37 @@
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum Case {
+    Toggle,
+    Lower,
+    Upper,
+}
+
+impl Case {
+    pub fn apply(&self, s: &str) -> String {
+        match self {
+            Self::Lower => s.to_lowercase(),
+            Self::Upper => s.to_uppercase(),
+            Self::Toggle => s
+                .chars()
+                .map(|ch| {
+                    if ch.is_lowercase() {
+                        ch.to_uppercase().collect::<String>()
+                    } else {
+                        ch.to_lowercase().collect::<String>()
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case() {
+        assert_eq!(Case::Toggle.apply("aB c"), "Ab C");
+        assert_eq!(Case::Upper.apply("straße"), "STRASSE");
+        assert_eq!(Case::Lower.apply("ÀB"), "àb");
+    }
+}
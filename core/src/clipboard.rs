@@ -0,0 +1,219 @@
+use log::*;
+use std::path::Path;
+
+// programs that copy stdin to the system clipboard and print it back out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardProvider {
+    pub copy: Vec<String>,
+    pub paste: Vec<String>,
+}
+
+impl ClipboardProvider {
+    // each command is a program and its arguments, separated by spaces
+    pub fn new(copy: &str, paste: &str) -> Self {
+        let words = |s: &str| s.split_whitespace().map(|w| w.to_string()).collect();
+        Self {
+            copy: words(copy),
+            paste: words(paste),
+        }
+    }
+
+    // EDITOR_CLIPBOARD_COPY and EDITOR_CLIPBOARD_PASTE if they are both set,
+    // otherwise the first of wl-copy, xclip or pbcopy that can be used here
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|s| !s.trim().is_empty());
+        if let (Some(copy), Some(paste)) =
+            (var("EDITOR_CLIPBOARD_COPY"), var("EDITOR_CLIPBOARD_PASTE"))
+        {
+            return Some(Self::new(&copy, &paste));
+        }
+        if var("WAYLAND_DISPLAY").is_some() && on_path("wl-copy") {
+            Some(Self::new("wl-copy", "wl-paste --no-newline"))
+        } else if var("DISPLAY").is_some() && on_path("xclip") {
+            Some(Self::new(
+                "xclip -selection clipboard -in",
+                "xclip -selection clipboard -out",
+            ))
+        } else if on_path("pbcopy") {
+            Some(Self::new("pbcopy", "pbpaste"))
+        } else {
+            None
+        }
+    }
+
+    pub fn copy(&self, s: &str) -> Result<(), String> {
+        let (program, args) = self.copy.split_first().ok_or("No clipboard copy command")?;
+        duct::cmd(program, args)
+            .stdin_bytes(s.as_bytes().to_vec())
+            .stdout_null()
+            .stderr_null()
+            .run()
+            .map(|_| ())
+            .map_err(|err| format!("Clipboard copy failed: {}", err))
+    }
+
+    // the clipboard as is, without trimming the final newline
+    pub fn paste(&self) -> Result<String, String> {
+        let (program, args) = self
+            .paste
+            .split_first()
+            .ok_or("No clipboard paste command")?;
+        duct::cmd(program, args)
+            .stdout_capture()
+            .stderr_null()
+            .run()
+            .map(|out| String::from_utf8_lossy(&out.stdout).to_string())
+            .map_err(|err| format!("Clipboard paste failed: {}", err))
+    }
+}
+
+// the + and * registers, copies go to the provider and out to the terminal with OSC 52
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    pub provider: Option<ClipboardProvider>,
+    pub osc52: bool,
+    pending: Vec<(char, String)>, // copies the terminal still has to write
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            osc52: true,
+            pending: vec![],
+        }
+    }
+}
+
+impl Clipboard {
+    pub fn new(provider: Option<ClipboardProvider>) -> Self {
+        Self {
+            provider,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(ClipboardProvider::from_env())
+    }
+
+    // copy to the clipboard, the register picks the selection OSC 52 writes to
+    pub fn copy(&mut self, reg: char, s: &str) -> Result<(), String> {
+        if self.osc52 {
+            self.pending.push((osc52_selection(reg), s.to_string()));
+        }
+        match &self.provider {
+            Some(provider) => provider.copy(s),
+            None => Ok(()),
+        }
+    }
+
+    // the clipboard from the provider, None without one as OSC 52 can't be read back
+    pub fn paste(&self) -> Result<Option<String>, String> {
+        match &self.provider {
+            Some(provider) => provider.paste().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // the copies waiting for the terminal, as selection and text
+    pub fn take_pending(&mut self) -> Vec<(char, String)> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// OSC 52 names the clipboard c and the primary selection p
+fn osc52_selection(reg: char) -> char {
+    match reg {
+        '*' => 'p',
+        _ => 'c',
+    }
+}
+
+// the escape sequence that sets a terminal selection, this works over ssh,
+// inside tmux it needs set-clipboard on
+pub fn osc52(selection: char, s: &str) -> String {
+    format!("\x1b]52;{};{}\x07", selection, base64(s.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn on_path(program: &str) -> bool {
+    let found = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false);
+    debug!("on_path: {:?}", (program, found));
+    found || Path::new(program).is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(osc52('c', "hi\n"), "\x1b]52;c;aGkK\x07");
+
+        let mut clipboard = Clipboard::default();
+        clipboard.copy('+', "a").unwrap();
+        clipboard.copy('*', "b").unwrap();
+        assert_eq!(
+            clipboard.take_pending(),
+            vec![('c', "a".to_string()), ('p', "b".to_string())]
+        );
+        assert!(clipboard.take_pending().is_empty());
+        assert_eq!(clipboard.paste(), Ok(None));
+    }
+
+    #[test]
+    fn test_provider() {
+        // a stub provider that keeps the clipboard in a file
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("clipboard");
+        let script = dir.path().join("clip.sh");
+        let mut f = std::fs::File::create(&script).unwrap();
+        write!(
+            f,
+            "#!/bin/sh\nif [ \"$1\" = copy ]; then cat > {0}; else cat {0}; fi\n",
+            file.display()
+        )
+        .unwrap();
+        drop(f);
+        let script = script.display().to_string();
+        let provider = ClipboardProvider::new(
+            &format!("sh {} copy", script),
+            &format!("sh {} paste", script),
+        );
+
+        provider.copy("one\ntwo\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+        assert_eq!(provider.paste().unwrap(), "one\ntwo\n");
+
+        let missing = ClipboardProvider::new("/nonexistent/copy", "");
+        assert!(missing.copy("x").is_err());
+        assert!(missing.paste().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+// toggling comments on lines with the comment string of a filetype, like "// %s" or "/* %s */"
+
+// the text before and after the commented text, without the spaces around %s
+fn parts(comment: &str) -> Option<(&str, &str)> {
+    let (left, right) = comment.split_once("%s")?;
+    let left = left.trim();
+    match left.is_empty() {
+        true => None,
+        false => Some((left, right.trim())),
+    }
+}
+
+fn leading(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+// the line without its comment, if it's commented
+fn uncomment(line: &str, left: &str, right: &str) -> Option<String> {
+    let (indent, text) = line.split_at(leading(line));
+    let text = text.strip_prefix(left)?;
+    let text = match right.is_empty() {
+        true => text,
+        false => text.trim_end().strip_suffix(right)?,
+    };
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    let text = match right.is_empty() {
+        true => text,
+        false => text.strip_suffix(' ').unwrap_or(text),
+    };
+    Some(format!("{}{}", indent, text))
+}
+
+// uncomment the lines if they are all commented, otherwise comment all of them, blank lines are
+// left alone and the comments go after the smallest indent so they line up
+pub fn toggle(lines: &[String], comment: &str) -> Vec<String> {
+    let (left, right) = match parts(comment) {
+        Some(parts) => parts,
+        None => return lines.to_vec(),
+    };
+    let text = || lines.iter().filter(|line| !line.trim().is_empty());
+    if text().all(|line| uncomment(line, left, right).is_some()) {
+        return lines
+            .iter()
+            .map(|line| uncomment(line, left, right).unwrap_or_else(|| line.clone()))
+            .collect();
+    }
+    let indent = text().map(|line| leading(line)).min().unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.clone();
+            }
+            let (indent, text) = line.split_at(indent);
+            match right.is_empty() {
+                true => format!("{}{} {}", indent, left, text),
+                false => format!("{}{} {} {}", indent, left, text, right),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        let lines = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let code = lines(&["  if a {", "", "    b", "  }"]);
+        let commented = lines(&["  // if a {", "", "  //   b", "  // }"]);
+        assert_eq!(toggle(&code, "// %s"), commented);
+        assert_eq!(toggle(&commented, "// %s"), code);
+
+        // a mix is commented again, so it toggles back
+        let mixed = lines(&["# a", "b"]);
+        assert_eq!(toggle(&mixed, "# %s"), lines(&["# # a", "# b"]));
+        assert_eq!(
+            toggle(&lines(&["#a", "  #  b"]), "# %s"),
+            lines(&["a", "   b"])
+        );
+
+        // block comments for each line
+        let code = lines(&["\tint a;"]);
+        let commented = lines(&["\t/* int a; */"]);
+        assert_eq!(toggle(&code, "/* %s */"), commented);
+        assert_eq!(toggle(&commented, "/* %s */"), code);
+
+        // nothing without a comment string
+        assert_eq!(toggle(&code, ""), code);
+    }
+}
@@ -1,8 +1,38 @@
 // From: https://github.com/mathphreak/mfte/blob/master/src/config.rs
+use crate::filetype::{self, FileType};
+use crate::indent;
+use crate::modeline;
 use editorconfig::get_config;
 use log::*;
+use ropey::Rope;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+// lines read from the start of a file to guess its indent
+const DETECT_LINES: usize = 5000;
+
+// the options a modeline can set, others are ignored
+const MODELINE_OPTIONS: &[&str] = &[
+    "tabstop",
+    "ts",
+    "shiftwidth",
+    "sw",
+    "expandtab",
+    "et",
+    "textwidth",
+    "tw",
+    "autoindent",
+    "ai",
+    "smartindent",
+    "si",
+    "nrformats",
+    "nf",
+    "commentstring",
+    "cms",
+    "keywordchars",
+    "kc",
+];
+
 #[derive(Debug, Clone)]
 pub enum IndentStyle {
     Tab,
@@ -15,6 +45,15 @@ pub enum IndentSize {
     Tab,
 }
 
+// where the indent style and size came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentSource {
+    Default,
+    EditorConfig,
+    Detected,
+    Set, // :set
+}
+
 #[derive(Debug, Clone)]
 pub enum EndOfLine {
     Lf,
@@ -39,6 +78,18 @@ pub struct BufferConfig {
     pub charset: Charset,
     pub trim_trailing_whitespace: bool,
     pub insert_final_newline: bool,
+    pub keyword_chars: String, // chars besides letters, digits and _ that are part of a word
+    pub scrolloff: usize,      // rows kept visible above and below the cursor
+    pub nrformats: String,     // number formats for ctrl-a and ctrl-x, besides decimal
+    pub textwidth: usize,      // the width gq fills lines to, 0 for the default
+    pub autoindent: bool,      // new lines copy the indent of the line they follow
+    pub smartindent: bool,     // new lines also follow brackets, closing brackets dedent
+    pub indent_chars: String,  // chars besides open brackets that indent the next line
+    pub retab_leading: bool,   // :retab only changes the whitespace before the text
+    pub indent_source: IndentSource,
+    pub filetype: String, // empty when not known
+    pub comment: String,  // how to comment a line for the filetype, %s is the text
+    pub modeline: bool,   // modelines in the file set options
 }
 
 #[derive(Debug, Clone)]
@@ -93,6 +144,18 @@ impl BufferConfig {
             charset: Charset::UTF8,
             trim_trailing_whitespace: true,
             insert_final_newline: true,
+            keyword_chars: String::new(),
+            scrolloff: 0,
+            nrformats: "bin,hex".to_string(),
+            textwidth: 0,
+            autoindent: true,
+            smartindent: true,
+            indent_chars: String::new(),
+            retab_leading: false,
+            indent_source: IndentSource::Default,
+            filetype: String::new(),
+            comment: String::new(),
+            modeline: true,
         }
     }
 
@@ -106,94 +169,220 @@ impl BufferConfig {
             charset: Charset::UTF8,
             trim_trailing_whitespace: true,
             insert_final_newline: true,
+            keyword_chars: String::new(),
+            scrolloff: 0,
+            nrformats: "bin,hex".to_string(),
+            textwidth: 0,
+            autoindent: true,
+            smartindent: true,
+            indent_chars: String::new(),
+            retab_leading: false,
+            indent_source: IndentSource::Default,
+            filetype: String::new(),
+            comment: String::new(),
+            modeline: true,
         }
     }
 
     pub fn config_for(path: Option<&str>) -> Self {
-        let mut result = Self {
-            // sensible editor defaults
-            indent_style: IndentStyle::Space,
-            indent_size: IndentSize::Size(4),
-            tab_width: 4,
-            end_of_line: EndOfLine::Lf,
-            charset: Charset::UTF8,
-            trim_trailing_whitespace: true,
-            insert_final_newline: true,
+        Self::config_for_text(path, &Rope::new())
+    }
+
+    // the config for a file and its text, the filetype settings, then the .editorconfig, then the
+    // modelines, then the indent detected from the text
+    pub fn config_for_text(path: Option<&str>, text: &Rope) -> Self {
+        let mut result = Self::config_spaces(4);
+        let conf = Self::editorconfig(path);
+        // "modeline = false" in an .editorconfig turns them off
+        if let Some(ml) = conf.get("modeline") {
+            result.modeline = ml != "false";
+        }
+        let modelines = match result.modeline {
+            true => modeline::options(text),
+            false => vec![],
         };
-        if let Some(p) = normalize_path(path) {
-            let result_conf = get_config(&p);
-            info!("config: {:?}", (path, &result_conf));
-            match result_conf {
-                Err(err) => error!("Error: {:?}", err),
-                Ok(conf) => {
-                    if let Some(style) = conf.get("indent_style") {
-                        if style == "tab" {
-                            result.indent_style = IndentStyle::Tab;
-                        } else if style == "space" {
-                            result.indent_style = IndentStyle::Space;
-                        }
-                    }
-
-                    if let Some(size) = conf.get("indent_size") {
-                        if size == "tab" {
-                            result.indent_size = IndentSize::Tab;
-                        } else {
-                            if let Ok(size) = size.parse() {
-                                result.indent_size = IndentSize::Size(size);
-                            }
-                        }
-                    }
-
-                    if let Some(width) = conf.get("tab_width") {
-                        if let Ok(width) = width.parse() {
-                            result.tab_width = width;
-                        }
-                    }
-
-                    if let Some(eol) = conf.get("end_of_line") {
-                        if eol == "cr" {
-                            result.end_of_line = EndOfLine::Cr;
-                        } else if eol == "crlf" {
-                            result.end_of_line = EndOfLine::CrLf;
-                        } else if eol == "lf" {
-                            result.end_of_line = EndOfLine::Lf;
-                        }
-                    }
-
-                    if let Some(charset) = conf.get("charset") {
-                        if charset == "latin1" {
-                            result.charset = Charset::Latin1;
-                        } else if charset == "utf-8" {
-                            result.charset = Charset::UTF8;
-                        } else if charset == "utf-16be" {
-                            result.charset = Charset::UTF16BE;
-                        } else if charset == "utf-16le" {
-                            result.charset = Charset::UTF16LE;
-                        }
-                    }
-
-                    if let Some(ttw) = conf.get("trim_trailing_whitespace") {
-                        if ttw == "true" {
-                            result.trim_trailing_whitespace = true;
-                        } else if ttw == "false" {
-                            result.trim_trailing_whitespace = false;
-                        }
-                    }
-
-                    if let Some(ifn) = conf.get("insert_final_newline") {
-                        if ifn == "true" {
-                            result.insert_final_newline = true;
-                        } else if ifn == "false" {
-                            result.insert_final_newline = false;
-                        }
-                    }
-                }
-            }
+        // "filetype = html" in an .editorconfig section like [*.tpl] maps files to a filetype
+        let configured = conf.get("filetype").map(|ft| ft.as_str());
+        if let Some(name) = filetype::detect(path, text, &modelines, configured) {
+            result.set_filetype(&name);
         }
+        result.apply_editorconfig(&conf);
+        result.apply_modelines(&modelines);
+        result.detect_indent(text);
         info!("config: {:?}", (&result));
         result
     }
 
+    // switch to a filetype and its settings, an indent from an .editorconfig, the text or :set
+    // is kept
+    pub fn set_filetype(&mut self, name: &str) -> &mut Self {
+        let ft = filetype::by_name(name).unwrap_or(&FileType::PLAIN);
+        self.filetype = name.to_string();
+        self.comment = ft.comment.to_string();
+        self.keyword_chars = ft.keyword_chars.to_string();
+        self.indent_chars = ft.indent_chars.to_string();
+        self.textwidth = ft.textwidth;
+        self.smartindent = !ft.prose;
+        if self.indent_source == IndentSource::Default {
+            match ft.indent {
+                Some((true, _)) => {
+                    self.indent_style = IndentStyle::Tab;
+                    self.indent_size = IndentSize::Tab;
+                }
+                Some((false, size)) => {
+                    self.indent_style = IndentStyle::Space;
+                    self.indent_size = IndentSize::Size(size);
+                }
+                None => (),
+            }
+        }
+        self
+    }
+
+    // the properties in the .editorconfig files for a path
+    fn editorconfig(path: Option<&str>) -> HashMap<String, String> {
+        match normalize_path(path).map(|p| get_config(&p)) {
+            Some(Ok(conf)) => {
+                info!("config: {:?}", (path, &conf));
+                conf.into_iter().collect()
+            }
+            Some(Err(err)) => {
+                error!("Error: {:?}", err);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        }
+    }
+
+    fn apply_editorconfig(&mut self, conf: &HashMap<String, String>) {
+        let result = self;
+        if let Some(style) = conf.get("indent_style") {
+            if style == "tab" {
+                result.indent_style = IndentStyle::Tab;
+            } else if style == "space" {
+                result.indent_style = IndentStyle::Space;
+            }
+            result.indent_source = IndentSource::EditorConfig;
+        }
+
+        if let Some(size) = conf.get("indent_size") {
+            if size == "tab" {
+                result.indent_size = IndentSize::Tab;
+            } else {
+                if let Ok(size) = size.parse() {
+                    result.indent_size = IndentSize::Size(size);
+                }
+            }
+            result.indent_source = IndentSource::EditorConfig;
+        }
+
+        if let Some(width) = conf.get("tab_width") {
+            if let Ok(width) = width.parse() {
+                result.tab_width = width;
+            }
+        }
+
+        if let Some(eol) = conf.get("end_of_line") {
+            if eol == "cr" {
+                result.end_of_line = EndOfLine::Cr;
+            } else if eol == "crlf" {
+                result.end_of_line = EndOfLine::CrLf;
+            } else if eol == "lf" {
+                result.end_of_line = EndOfLine::Lf;
+            }
+        }
+
+        if let Some(charset) = conf.get("charset") {
+            if charset == "latin1" {
+                result.charset = Charset::Latin1;
+            } else if charset == "utf-8" {
+                result.charset = Charset::UTF8;
+            } else if charset == "utf-16be" {
+                result.charset = Charset::UTF16BE;
+            } else if charset == "utf-16le" {
+                result.charset = Charset::UTF16LE;
+            }
+        }
+
+        if let Some(ttw) = conf.get("trim_trailing_whitespace") {
+            if ttw == "true" {
+                result.trim_trailing_whitespace = true;
+            } else if ttw == "false" {
+                result.trim_trailing_whitespace = false;
+            }
+        }
+
+        if let Some(chars) = conf.get("keyword_chars") {
+            result.keyword_chars = chars.to_string();
+        }
+
+        if let Some(comment) = conf.get("comment_string") {
+            result.comment = comment.to_string();
+        }
+
+        if let Some(ifn) = conf.get("insert_final_newline") {
+            if ifn == "true" {
+                result.insert_final_newline = true;
+            } else if ifn == "false" {
+                result.insert_final_newline = false;
+            }
+        }
+    }
+
+    // set the allowed options from modelines, the filetype is already set
+    fn apply_modelines(&mut self, options: &[(String, String)]) {
+        for (name, value) in options {
+            let (name, value) = match value.is_empty() {
+                true => match Self::flag_option(name) {
+                    Some((flag, on)) => (flag, on.to_string()),
+                    None => (name.as_str(), value.clone()),
+                },
+                false => (name.as_str(), value.clone()),
+            };
+            if !MODELINE_OPTIONS.contains(&name) {
+                info!("modeline option ignored: {}", name);
+                continue;
+            }
+            if let Err(err) = self.set_option(name, &value) {
+                warn!("modeline: {}", err);
+            }
+        }
+    }
+
+    // guess the indent from the text, unless an .editorconfig or :set gave it
+    pub fn detect_indent(&mut self, text: &Rope) -> &mut Self {
+        if self.indent_source != IndentSource::Default {
+            return self;
+        }
+        match indent::detect(text, DETECT_LINES) {
+            Some((IndentStyle::Tab, _)) => {
+                self.indent_style = IndentStyle::Tab;
+                self.indent_size = IndentSize::Tab;
+            }
+            Some((IndentStyle::Space, size)) => {
+                self.indent_style = IndentStyle::Space;
+                self.indent_size = IndentSize::Size(size);
+            }
+            None => return self,
+        }
+        self.indent_source = IndentSource::Detected;
+        self
+    }
+
+    // the indent for the status line, like spaces:4 or tabs:8, and where it came from
+    pub fn indent_status(&self) -> String {
+        let style = match self.indent_style {
+            IndentStyle::Tab => format!("tabs:{}", self.tab_width),
+            IndentStyle::Space => format!("spaces:{}", self.indent_width()),
+        };
+        match self.indent_source {
+            IndentSource::Default => style,
+            IndentSource::EditorConfig => format!("{} (editorconfig)", style),
+            IndentSource::Detected => format!("{} (detected)", style),
+            IndentSource::Set => format!("{} (set)", style),
+        }
+    }
+
     pub fn indent(&self) -> String {
         match self.indent_style {
             IndentStyle::Tab => "\t".to_string(),
@@ -207,6 +396,150 @@ impl BufferConfig {
         }
     }
 
+    // the width of one level of indentation, in columns
+    pub fn indent_width(&self) -> usize {
+        match self.indent_size {
+            IndentSize::Tab => self.tab_width as usize,
+            IndentSize::Size(n) => n as usize,
+        }
+    }
+
+    // whitespace that indents to a column, using tabs when the indent style allows
+    pub fn indent_to(&self, width: usize) -> String {
+        match self.indent_style {
+            IndentStyle::Tab if self.tab_width > 0 => {
+                let tab_width = self.tab_width as usize;
+                "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+            }
+            _ => " ".repeat(width),
+        }
+    }
+
+    // the width in columns of the leading whitespace of a line, and its length in chars
+    pub fn leading_indent(&self, line: &str) -> (usize, usize) {
+        let tab_width = std::cmp::max(1, self.tab_width as usize);
+        let mut width = 0;
+        let mut chars = 0;
+        for ch in line.chars() {
+            match ch {
+                ' ' => width += 1,
+                '\t' => width += tab_width - width % tab_width,
+                _ => break,
+            }
+            chars += 1;
+        }
+        (width, chars)
+    }
+
+    // the value of an option for :set, by its full or short name
+    pub fn get_option(&self, name: &str) -> Option<String> {
+        match name {
+            "scrolloff" | "so" => Some(self.scrolloff.to_string()),
+            "nrformats" | "nf" => Some(self.nrformats.clone()),
+            "textwidth" | "tw" => Some(self.textwidth.to_string()),
+            "autoindent" | "ai" => Some(self.autoindent.to_string()),
+            "smartindent" | "si" => Some(self.smartindent.to_string()),
+            "retableading" | "rtl" => Some(self.retab_leading.to_string()),
+            "tabstop" | "ts" => Some(self.tab_width.to_string()),
+            "shiftwidth" | "sw" => Some(self.indent_width().to_string()),
+            "expandtab" | "et" => Some(matches!(self.indent_style, IndentStyle::Space).to_string()),
+            "filetype" | "ft" => Some(self.filetype.clone()),
+            "commentstring" | "cms" => Some(self.comment.clone()),
+            "keywordchars" | "kc" => Some(self.keyword_chars.clone()),
+            "modeline" | "ml" => Some(self.modeline.to_string()),
+            _ => None,
+        }
+    }
+
+    // :set name and :set noname switch a flag on and off, the name and the value for a flag
+    pub fn flag_option(name: &str) -> Option<(&str, bool)> {
+        let flags = [
+            "autoindent",
+            "ai",
+            "smartindent",
+            "si",
+            "retableading",
+            "rtl",
+            "expandtab",
+            "et",
+            "modeline",
+            "ml",
+        ];
+        match name.strip_prefix("no") {
+            Some(flag) if flags.contains(&flag) => Some((flag, false)),
+            _ if flags.contains(&name) => Some((name, true)),
+            _ => None,
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid number: {}={}", name, value))
+        };
+        let flag = || match value {
+            "true" | "on" | "1" => Ok(true),
+            "false" | "off" | "0" => Ok(false),
+            _ => Err(format!("Invalid flag: {}={}", name, value)),
+        };
+        match name {
+            "scrolloff" | "so" => self.scrolloff = number()?,
+            "autoindent" | "ai" => self.autoindent = flag()?,
+            "smartindent" | "si" => self.smartindent = flag()?,
+            "retableading" | "rtl" => self.retab_leading = flag()?,
+            "modeline" | "ml" => self.modeline = flag()?,
+            "tabstop" | "ts" => match number()? {
+                n @ 1..=255 => self.tab_width = n as u8,
+                _ => return Err(format!("Invalid number: {}={}", name, value)),
+            },
+            "shiftwidth" | "sw" => {
+                match number()? {
+                    0 => self.indent_size = IndentSize::Tab,
+                    n @ 1..=255 => self.indent_size = IndentSize::Size(n as u8),
+                    _ => return Err(format!("Invalid number: {}={}", name, value)),
+                }
+                self.indent_source = IndentSource::Set;
+            }
+            "expandtab" | "et" => {
+                self.indent_style = match flag()? {
+                    true => IndentStyle::Space,
+                    false => IndentStyle::Tab,
+                };
+                self.indent_source = IndentSource::Set;
+            }
+            "textwidth" | "tw" => self.textwidth = number()?,
+            "filetype" | "ft" => {
+                if !value.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+                    return Err(format!("Invalid argument: {}={}", name, value));
+                }
+                self.set_filetype(value);
+            }
+            "commentstring" | "cms" => self.comment = value.to_string(),
+            "keywordchars" | "kc" => self.keyword_chars = value.to_string(),
+            "nrformats" | "nf" => {
+                let formats = ["alpha", "octal", "hex", "bin"];
+                if let Some(bad) = value
+                    .split(',')
+                    .find(|f| !f.is_empty() && !formats.contains(f))
+                {
+                    return Err(format!("Invalid argument: {}={}", name, bad));
+                }
+                self.nrformats = value.to_string();
+            }
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    // the width to fill lines to, 79 when textwidth isn't set
+    pub fn fill_width(&self) -> usize {
+        match self.textwidth {
+            0 => 79,
+            n => n,
+        }
+    }
+
     pub fn line_sep(&self) -> &str {
         match self.end_of_line {
             EndOfLine::Lf => "\n",
@@ -0,0 +1,247 @@
+use regex::Regex;
+use ropey::Rope;
+use std::collections::HashMap;
+
+/// A line address in an ex command line, resolved against the buffer when the command runs
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Address {
+    Current,                   // .
+    Last,                      // $
+    Line(usize),               // one based line number, 0 means before the first line
+    Mark(char),                // 'a
+    Search(String, bool),      // /pattern/ forward, ?pattern? backward
+    Offset(Box<Address>, i64), // +N, -N
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum LineRange {
+    All, // %
+    Single(Address),
+    Span(Address, Address),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ExOp {
+    Goto, // a range on its own moves to the last line in the range
+    Delete(Option<char>),
+    Yank(Option<char>),
+    Substitute(String, String, bool), // pattern, replacement, replace all
+    Normal(String),
+    Move(Address),
+    Copy(Address),
+    Join,
+    Shift(i32),                 // indent levels, negative shifts left
+    Retab(bool, Option<usize>), // also change runs of spaces, the new tab width
+    Reindent(Option<usize>),    // the width of an indent level the lines have now
+    Write(Option<String>),
+    Global(String, bool, Box<ExCommand>), // pattern, invert, command
+}
+
+impl ExOp {
+    // commands that change the buffer, these can be repeated with .
+    pub fn is_change(&self) -> bool {
+        match self {
+            Self::Goto | Self::Yank(_) | Self::Write(_) => false,
+            Self::Global(_, _, command) => command.op.is_change(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct ExCommand {
+    pub range: Option<LineRange>,
+    pub op: ExOp,
+}
+
+impl ExCommand {
+    pub fn new(range: Option<LineRange>, op: ExOp) -> Self {
+        Self { range, op }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ExError {
+    InvalidRange,
+    InvalidPattern(String),
+    PatternNotFound(String),
+    MarkNotSet(char),
+    InvalidArgument(String),
+}
+
+/// The state of the buffer that addresses are resolved against
+pub struct ExContext<'a> {
+    pub text: &'a Rope,
+    pub current: usize,              // zero based line index of the cursor
+    pub marks: HashMap<char, usize>, // mark to zero based line index
+}
+
+impl<'a> ExContext<'a> {
+    pub fn new(text: &'a Rope, current: usize) -> Self {
+        Self {
+            text,
+            current,
+            marks: HashMap::new(),
+        }
+    }
+}
+
+pub fn compile_pattern(pattern: &str) -> Result<Regex, ExError> {
+    Regex::new(pattern).map_err(|_| ExError::InvalidPattern(pattern.to_string()))
+}
+
+// convert vim replacement syntax (& and \1) into the syntax used by Regex::replace
+pub fn expand_replacement(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '&' => out.push_str("${0}"),
+            '$' => out.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => out.push_str(&format!("${{{}}}", d)),
+                Some('n') | Some('r') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(other) => out.push(other),
+                None => out.push(ch),
+            },
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+// index of the last line that has text, ignoring the empty line after a trailing newline
+pub fn last_line(text: &Rope) -> usize {
+    let lines = text.len_lines();
+    if lines > 1 && text.line(lines - 1).len_chars() == 0 {
+        lines - 2
+    } else {
+        lines - 1
+    }
+}
+
+impl Address {
+    // one based line number, 0 is before the first line
+    fn line_number(&self, ctx: &ExContext) -> Result<usize, ExError> {
+        let last = last_line(ctx.text) + 1;
+        match self {
+            Self::Current => Ok(ctx.current + 1),
+            Self::Last => Ok(last),
+            Self::Line(n) if *n <= last => Ok(*n),
+            Self::Line(_) => Err(ExError::InvalidRange),
+            Self::Mark(ch) => match ctx.marks.get(ch) {
+                Some(line_inx) => Ok(line_inx + 1),
+                None => Err(ExError::MarkNotSet(*ch)),
+            },
+            Self::Search(pattern, forward) => {
+                let re = compile_pattern(pattern)?;
+                // search starts on the line after (or before) the cursor and wraps around
+                (1..=last)
+                    .map(|offset| {
+                        if *forward {
+                            (ctx.current + offset) % last
+                        } else {
+                            (ctx.current + last - offset) % last
+                        }
+                    })
+                    .find(|line_inx| re.is_match(&ctx.text.line(*line_inx).to_string()))
+                    .map(|line_inx| line_inx + 1)
+                    .ok_or_else(|| ExError::PatternNotFound(pattern.clone()))
+            }
+            Self::Offset(base, n) => {
+                let line = base.line_number(ctx)? as i64 + n;
+                if line < 0 || line > last as i64 {
+                    Err(ExError::InvalidRange)
+                } else {
+                    Ok(line as usize)
+                }
+            }
+        }
+    }
+
+    // resolve to a zero based line index, line 0 resolves to None
+    pub fn resolve(&self, ctx: &ExContext) -> Result<Option<usize>, ExError> {
+        Ok(self.line_number(ctx)?.checked_sub(1))
+    }
+
+    // resolve a destination for move and copy, lines are placed after the result
+    pub fn resolve_dest(&self, ctx: &ExContext) -> Result<usize, ExError> {
+        self.line_number(ctx)
+    }
+}
+
+impl LineRange {
+    // resolve to zero based inclusive line indices
+    pub fn resolve(&self, ctx: &ExContext) -> Result<(usize, usize), ExError> {
+        let (a, b) = match self {
+            Self::All => (0, last_line(ctx.text)),
+            Self::Single(a) => {
+                let a = a.resolve(ctx)?.unwrap_or(0);
+                (a, a)
+            }
+            Self::Span(a, b) => (a.resolve(ctx)?.unwrap_or(0), b.resolve(ctx)?.unwrap_or(0)),
+        };
+        if a > b {
+            Err(ExError::InvalidRange)
+        } else {
+            Ok((a, b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_range() {
+        let text = Rope::from_str("a\nb\nc\n");
+        let ctx = ExContext::new(&text, 1);
+        assert_eq!(last_line(&text), 2);
+        assert_eq!(LineRange::All.resolve(&ctx), Ok((0, 2)));
+        assert_eq!(
+            LineRange::Span(Address::Current, Address::Last).resolve(&ctx),
+            Ok((1, 2))
+        );
+        assert_eq!(
+            LineRange::Single(Address::Line(4)).resolve(&ctx),
+            Err(ExError::InvalidRange)
+        );
+        assert_eq!(
+            LineRange::Span(Address::Line(3), Address::Line(1)).resolve(&ctx),
+            Err(ExError::InvalidRange)
+        );
+        assert_eq!(Address::Line(0).resolve_dest(&ctx), Ok(0));
+        assert_eq!(Address::Last.resolve_dest(&ctx), Ok(3));
+    }
+
+    #[test]
+    fn test_resolve_address() {
+        use Address as A;
+        let text = Rope::from_str("a\nb\nc\na\n");
+        let mut ctx = ExContext::new(&text, 1);
+        ctx.marks.insert('x', 2);
+        assert_eq!(A::Mark('x').resolve(&ctx), Ok(Some(2)));
+        assert_eq!(A::Mark('y').resolve(&ctx), Err(ExError::MarkNotSet('y')));
+        assert_eq!(A::Search("a".into(), true).resolve(&ctx), Ok(Some(3)));
+        assert_eq!(A::Search("a".into(), false).resolve(&ctx), Ok(Some(0)));
+        assert_eq!(A::Search("b".into(), true).resolve(&ctx), Ok(Some(1)));
+        assert_eq!(
+            A::Offset(Box::new(A::Current), 2).resolve(&ctx),
+            Ok(Some(3))
+        );
+        assert_eq!(A::Offset(Box::new(A::Last), -4).resolve(&ctx), Ok(None));
+        assert_eq!(
+            A::Offset(Box::new(A::Last), 1).resolve(&ctx),
+            Err(ExError::InvalidRange)
+        );
+    }
+
+    #[test]
+    fn test_expand_replacement() {
+        assert_eq!(expand_replacement("[&]"), "[${0}]");
+        assert_eq!(expand_replacement("\\2-\\1 $x"), "${2}-${1} $$x");
+        assert_eq!(expand_replacement("a\\&b\\r"), "a&b\n");
+    }
+}
@@ -0,0 +1,281 @@
+// the language of a buffer, from its name, a #! line or a modeline, and the settings that go with it
+use ropey::Rope;
+use std::path::Path;
+
+pub struct FileType {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    interpreters: &'static [&'static str], // the program in a #! line
+    pub comment: &'static str,             // how to comment a line, %s is the text
+    pub keyword_chars: &'static str,       // chars besides letters, digits and _ in a word
+    pub indent_chars: &'static str,        // chars besides open brackets that indent the next line
+    pub indent: Option<(bool, u8)>,        // tabs, and the indent size
+    pub textwidth: usize,
+    pub prose: bool, // no smart indent
+}
+
+const fn filetype(name: &'static str, comment: &'static str) -> FileType {
+    FileType {
+        name,
+        extensions: &[],
+        filenames: &[],
+        interpreters: &[],
+        comment,
+        keyword_chars: "",
+        indent_chars: "",
+        indent: None,
+        textwidth: 0,
+        prose: false,
+    }
+}
+
+impl FileType {
+    // the settings for a filetype that isn't known
+    pub const PLAIN: FileType = filetype("", "");
+}
+
+pub const FILETYPES: &[FileType] = &[
+    FileType {
+        extensions: &["rs"],
+        textwidth: 100,
+        ..filetype("rust", "// %s")
+    },
+    FileType {
+        extensions: &["c", "h"],
+        ..filetype("c", "/* %s */")
+    },
+    FileType {
+        extensions: &["cc", "cpp", "cxx", "hpp", "hh"],
+        ..filetype("cpp", "// %s")
+    },
+    FileType {
+        extensions: &["go"],
+        indent: Some((true, 0)),
+        ..filetype("go", "// %s")
+    },
+    FileType {
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        interpreters: &["node"],
+        ..filetype("javascript", "// %s")
+    },
+    FileType {
+        extensions: &["ts", "tsx"],
+        ..filetype("typescript", "// %s")
+    },
+    FileType {
+        extensions: &["json"],
+        ..filetype("json", "")
+    },
+    FileType {
+        extensions: &["py", "pyw"],
+        interpreters: &["python"],
+        indent_chars: ":",
+        indent: Some((false, 4)),
+        textwidth: 79,
+        ..filetype("python", "# %s")
+    },
+    FileType {
+        extensions: &["nim"],
+        indent_chars: ":",
+        indent: Some((false, 2)),
+        ..filetype("nim", "# %s")
+    },
+    FileType {
+        extensions: &["rb"],
+        interpreters: &["ruby"],
+        indent: Some((false, 2)),
+        ..filetype("ruby", "# %s")
+    },
+    FileType {
+        extensions: &["sh", "bash", "zsh"],
+        filenames: &[".bashrc", ".bash_profile", ".profile", ".zshrc"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        ..filetype("sh", "# %s")
+    },
+    FileType {
+        extensions: &["mk", "mak"],
+        filenames: &["Makefile", "makefile", "GNUmakefile"],
+        indent: Some((true, 0)),
+        ..filetype("make", "# %s")
+    },
+    FileType {
+        filenames: &["Dockerfile", "Containerfile"],
+        ..filetype("dockerfile", "# %s")
+    },
+    FileType {
+        extensions: &["toml"],
+        filenames: &["Cargo.lock"],
+        ..filetype("toml", "# %s")
+    },
+    FileType {
+        extensions: &["yaml", "yml"],
+        keyword_chars: "-",
+        indent_chars: ":",
+        indent: Some((false, 2)),
+        ..filetype("yaml", "# %s")
+    },
+    FileType {
+        extensions: &["lua"],
+        interpreters: &["lua"],
+        ..filetype("lua", "-- %s")
+    },
+    FileType {
+        extensions: &["sql"],
+        ..filetype("sql", "-- %s")
+    },
+    FileType {
+        extensions: &["lisp", "el", "clj", "cljs", "scm", "rkt"],
+        keyword_chars: "-?!*+<>=",
+        indent: Some((false, 2)),
+        ..filetype("lisp", ";; %s")
+    },
+    FileType {
+        extensions: &["css", "scss", "less"],
+        keyword_chars: "-",
+        ..filetype("css", "/* %s */")
+    },
+    FileType {
+        extensions: &["html", "htm", "xml", "svg"],
+        keyword_chars: "-",
+        indent: Some((false, 2)),
+        ..filetype("html", "<!-- %s -->")
+    },
+    FileType {
+        extensions: &["php"],
+        interpreters: &["php"],
+        keyword_chars: "$",
+        ..filetype("php", "// %s")
+    },
+    FileType {
+        extensions: &["pl", "pm"],
+        interpreters: &["perl"],
+        keyword_chars: "$",
+        ..filetype("perl", "# %s")
+    },
+    FileType {
+        extensions: &["vim"],
+        filenames: &[".vimrc", "_vimrc"],
+        ..filetype("vim", "\" %s")
+    },
+    FileType {
+        extensions: &["md", "markdown"],
+        prose: true,
+        ..filetype("markdown", "<!-- %s -->")
+    },
+    FileType {
+        extensions: &["rst"],
+        prose: true,
+        ..filetype("rst", ".. %s")
+    },
+    FileType {
+        filenames: &["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG"],
+        textwidth: 72,
+        prose: true,
+        ..filetype("gitcommit", "# %s")
+    },
+    FileType {
+        extensions: &["txt", "text"],
+        prose: true,
+        ..filetype("text", "")
+    },
+];
+
+pub fn by_name(name: &str) -> Option<&'static FileType> {
+    FILETYPES.iter().find(|ft| ft.name == name)
+}
+
+// the filetype from the file name, then the extension
+pub fn from_path(path: Option<&str>) -> Option<&'static FileType> {
+    let path = Path::new(path?);
+    let name = path.file_name()?.to_string_lossy();
+    if let Some(ft) = FILETYPES.iter().find(|ft| ft.filenames.contains(&&*name)) {
+        return Some(ft);
+    }
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    FILETYPES
+        .iter()
+        .find(|ft| ft.extensions.contains(&ext.as_str()))
+}
+
+// the filetype from a #! line, "#!/usr/bin/env python3" is python
+pub fn from_shebang(line: &str) -> Option<&'static FileType> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    let program = program.trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+    FILETYPES
+        .iter()
+        .find(|ft| ft.interpreters.contains(&program))
+}
+
+// the name of the filetype for a file, a filetype in the modeline options wins, then a configured
+// one, then the name of the file, then a #! line
+pub fn detect(
+    path: Option<&str>,
+    text: &Rope,
+    modelines: &[(String, String)],
+    configured: Option<&str>,
+) -> Option<String> {
+    let modeline = modelines
+        .iter()
+        .rev()
+        .find(|(name, _)| name == "ft" || name == "filetype")
+        .map(|(_, value)| value.as_str());
+    if let Some(name) = modeline.or(configured) {
+        return Some(name.to_string());
+    }
+    let first = match text.len_lines() {
+        0 => String::new(),
+        _ => text.line(0).to_string(),
+    };
+    from_path(path)
+        .or_else(|| from_shebang(&first))
+        .map(|ft| ft.name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modeline;
+
+    #[test]
+    fn test_detect() {
+        let empty = Rope::new();
+        assert_eq!(
+            detect(Some("src/main.rs"), &empty, &[], None).unwrap(),
+            "rust"
+        );
+        assert_eq!(
+            detect(Some("/a/Makefile"), &empty, &[], None).unwrap(),
+            "make"
+        );
+        assert_eq!(detect(Some("a.PY"), &empty, &[], None).unwrap(), "python");
+        assert_eq!(detect(Some("a.unknown"), &empty, &[], None), None);
+        assert_eq!(detect(None, &empty, &[], None), None);
+
+        let script = Rope::from_str("#!/usr/bin/env -S python3.11 -u\nprint()\n");
+        assert_eq!(detect(Some("run"), &script, &[], None).unwrap(), "python");
+        let script = Rope::from_str("#!/bin/bash\n");
+        assert_eq!(detect(None, &script, &[], None).unwrap(), "sh");
+
+        // a modeline wins over the name
+        let text = Rope::from_str("x\n# vim: set ft=yaml:\n");
+        let modelines = modeline::options(&text);
+        assert_eq!(
+            detect(Some("a.txt"), &text, &modelines, None).unwrap(),
+            "yaml"
+        );
+        assert_eq!(detect(Some("a.txt"), &text, &[], None).unwrap(), "text");
+        assert_eq!(
+            detect(Some("a.txt"), &text, &[], Some("html")).unwrap(),
+            "html"
+        );
+        assert_eq!(
+            detect(Some("a.txt"), &text, &modelines, Some("html")).unwrap(),
+            "yaml"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use ignore::WalkBuilder;
+use log::*;
+use regex::Regex;
+
+/// A line that matched a search, line and column are one based
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+impl GrepMatch {
+    // the line shown in the results list
+    pub fn format(&self) -> String {
+        format!("{}:{}:{}: {}", self.path, self.line, self.col, self.text)
+    }
+}
+
+// every line of the text that matches, in order
+pub fn grep_text(re: &Regex, path: &str, text: &str) -> Vec<GrepMatch> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(inx, line)| {
+            re.find(line).map(|m| GrepMatch {
+                path: path.to_string(),
+                line: inx + 1,
+                col: line[..m.start()].chars().count() + 1,
+                text: line.to_string(),
+            })
+        })
+        .collect()
+}
+
+// the files under the paths that should be searched, skipping anything ignored by .gitignore
+pub fn grep_files(paths: &[String]) -> Vec<String> {
+    let mut paths = paths.iter();
+    let mut builder = WalkBuilder::new(paths.next().map(|p| p.as_str()).unwrap_or("."));
+    paths.for_each(|p| {
+        builder.add(p);
+    });
+    builder
+        .require_git(false)
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().map(|t| t.is_file()).unwrap_or(false) => {
+                let path = entry.path().to_string_lossy().to_string();
+                Some(path.strip_prefix("./").unwrap_or(&path).to_string())
+            }
+            Ok(_) => None,
+            Err(err) => {
+                error!("grep: {:?}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+// search the files under the paths, calling f for each match as it is found
+pub fn grep_paths<F>(re: &Regex, paths: &[String], mut f: F) -> usize
+where
+    F: FnMut(GrepMatch),
+{
+    let mut count = 0;
+    for path in grep_files(paths) {
+        match std::fs::read_to_string(&path) {
+            // skip binary files
+            Ok(text) if !text.contains('\0') => {
+                for m in grep_text(re, &path, &text) {
+                    count += 1;
+                    f(m);
+                }
+            }
+            Ok(_) => (),
+            Err(err) => info!("grep: skip {}: {:?}", path, err),
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_grep_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+        fs::write(root.join("a.txt"), "one\ntwo foo\nthree\n").unwrap();
+        fs::write(root.join("ignored.txt"), "foo\n").unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub").join("b.txt"), "foo foo\n").unwrap();
+
+        let re = Regex::new("foo").unwrap();
+        let mut matches = vec![];
+        let count = grep_paths(&re, &[root.to_string_lossy().to_string()], |m| {
+            matches.push(m)
+        });
+        matches.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(count, 2);
+        assert!(matches[0].path.ends_with("a.txt"));
+        assert_eq!((matches[0].line, matches[0].col), (2, 5));
+        assert!(matches[1].path.ends_with("b.txt"));
+        assert_eq!(matches[1].format().split_once(": ").unwrap().1, "foo foo");
+    }
+}
@@ -0,0 +1,341 @@
+// indentation worked out from brackets, for = and for new lines
+use crate::{BufferConfig, IndentStyle};
+use ropey::Rope;
+use unicode_width::UnicodeWidthChar;
+
+// open brackets less closed brackets, skipping strings and comments, the number of closing
+// brackets at the start of the line and the last char that isn't in a comment
+fn brackets(line: &str) -> (i64, i64, Option<char>) {
+    let mut depth = 0;
+    let mut leading = 0;
+    let mut at_start = true;
+    let mut quote = None;
+    let mut last = None;
+    let mut prev = ' ';
+    let mut chars = line.trim().chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), ch) if ch == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"') => quote = Some('"'),
+            (None, '/') if chars.peek() == Some(&'/') => break,
+            (None, '#') if prev.is_whitespace() => break,
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => {
+                depth -= 1;
+                if at_start {
+                    leading += 1;
+                }
+            }
+            _ => (),
+        }
+        at_start = at_start && matches!(ch, ')' | ']' | '}' | ' ' | '\t');
+        if !ch.is_whitespace() {
+            last = Some(ch);
+        }
+        prev = ch;
+    }
+    (depth, leading, last)
+}
+
+// a line that starts by closing a bracket goes back one level
+pub fn starts_with_closer(line: &str) -> bool {
+    brackets(line).1 > 0
+}
+
+// the indent width in columns for the line after this one, a line that opens a bracket or ends
+// with one of the indent chars for the filetype adds one level and a line that closes a bracket it
+// didn't open takes one away
+pub fn indent_after(line: &str, config: &BufferConfig) -> usize {
+    let (width, _) = config.leading_indent(line);
+    let (depth, leading, last) = brackets(line);
+    let opens = last.is_some_and(|ch| config.indent_chars.contains(ch));
+    let levels = match opens {
+        true => 1,
+        false => (depth + leading).clamp(-1, 1),
+    };
+    let step = config.indent_width() as i64 * levels;
+    std::cmp::max(0, width as i64 + step) as usize
+}
+
+// the indent width for a line following above, given the line's own text
+pub fn indent_for(above: Option<&str>, line: &str, config: &BufferConfig) -> usize {
+    let width = above.map(|a| indent_after(a, config)).unwrap_or(0);
+    match starts_with_closer(line) {
+        true => width.saturating_sub(config.indent_width()),
+        false => width,
+    }
+}
+
+// the indent for a new line split from a line at the cursor, autoindent copies the indent of the
+// line and smart indent also follows the brackets
+pub fn new_line_indent(before: &str, after: &str, config: &BufferConfig) -> String {
+    if config.smartindent {
+        config.indent_to(indent_for(Some(before), after, config))
+    } else if config.autoindent {
+        let indent = before.len() - before.trim_start().len();
+        before[..indent].to_string()
+    } else {
+        String::new()
+    }
+}
+
+// the indent for a new line opened above a line, smart indent adds a level above a line that
+// closes a bracket
+pub fn above_line_indent(line: &str, config: &BufferConfig) -> String {
+    if config.smartindent {
+        let (width, _) = config.leading_indent(line);
+        let extra = match starts_with_closer(line) {
+            true => config.indent_width(),
+            false => 0,
+        };
+        config.indent_to(width + extra)
+    } else {
+        new_line_indent(line, "", config)
+    }
+}
+
+// lines without their line endings indented again, following on from the nearest non-blank line
+// above them, blank lines lose any whitespace
+pub fn reindent(above: Option<&str>, lines: &[String], config: &BufferConfig) -> Vec<String> {
+    let mut above = above.map(|s| s.to_string());
+    lines
+        .iter()
+        .map(|line| {
+            let content = line.trim_start();
+            if content.trim_end().is_empty() {
+                return String::new();
+            }
+            let width = indent_for(above.as_deref(), content, config);
+            let out = config.indent_to(width) + content;
+            above = Some(out.clone());
+            out
+        })
+        .collect()
+}
+
+// whitespace from column start to column end, with tabs when the indent style allows, a single
+// space stays a space
+fn fill(start: usize, end: usize, config: &BufferConfig) -> String {
+    let tab_width = config.tab_width as usize;
+    let mut out = String::new();
+    let mut col = start;
+    let tabs = matches!(config.indent_style, IndentStyle::Tab);
+    if tabs && tab_width > 0 && end - start > 1 {
+        while (col / tab_width + 1) * tab_width <= end {
+            out.push('\t');
+            col = (col / tab_width + 1) * tab_width;
+        }
+    }
+    out + &" ".repeat(end - col)
+}
+
+// :retab, runs of whitespace with a tab, read with the old tab width, are written again in the
+// configured style, with bang runs of spaces are too, leading only leaves runs after the text
+pub fn retab(
+    line: &str,
+    tab_width: usize,
+    bang: bool,
+    leading_only: bool,
+    config: &BufferConfig,
+) -> String {
+    let tab_width = std::cmp::max(1, tab_width);
+    let mut out = String::new();
+    let mut col = 0;
+    let mut chars = line.chars().peekable();
+    let mut leading = true;
+    while let Some(&ch) = chars.peek() {
+        if ch != ' ' && ch != '\t' {
+            chars.next();
+            out.push(ch);
+            col += ch.width().unwrap_or(0);
+            leading = false;
+            continue;
+        }
+        let start = col;
+        let mut run = String::new();
+        while let Some(&ch) = chars.peek().filter(|ch| **ch == ' ' || **ch == '\t') {
+            chars.next();
+            run.push(ch);
+            col = match ch {
+                '\t' => col + tab_width - col % tab_width,
+                _ => col + 1,
+            };
+        }
+        let change = (bang || run.contains('\t')) && (leading || !leading_only);
+        match change {
+            true => out.push_str(&fill(start, col, config)),
+            false => out.push_str(&run),
+        }
+    }
+    out
+}
+
+// the indent of a line read as levels of from columns, written as levels of the configured
+// indent size, blank lines are left alone
+pub fn relevel(line: &str, from: usize, config: &BufferConfig) -> String {
+    if line.trim().is_empty() {
+        return line.to_string();
+    }
+    let (width, chars) = config.leading_indent(line);
+    let from = std::cmp::max(1, from);
+    let width = width / from * config.indent_width() + width % from;
+    config.indent_to(width) + &line.chars().skip(chars).collect::<String>()
+}
+
+// the indent style of a text from up to max_lines lines, tabs if more lines start with a tab than
+// with spaces, otherwise the most common step between the indents of lines that follow each other
+pub fn detect(text: &Rope, max_lines: usize) -> Option<(IndentStyle, u8)> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    let mut steps = [0; 9];
+    let mut prev = 0;
+    for line in text.lines().take(max_lines) {
+        let line = line.to_string();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let width = line.len() - line.trim_start_matches(' ').len();
+        match line.chars().next() {
+            Some('\t') => {
+                tabs += 1;
+                continue;
+            }
+            Some(' ') => spaces += 1,
+            _ => (),
+        }
+        // a step of one is usually a comment continuing with " *"
+        let step = width.abs_diff(prev);
+        if (2..steps.len()).contains(&step) {
+            steps[step] += 1;
+        }
+        prev = width;
+    }
+    if tabs == 0 && spaces == 0 {
+        return None;
+    }
+    if tabs > spaces {
+        return Some((IndentStyle::Tab, 0));
+    }
+    // the first of the most common steps, so the smaller size wins a tie
+    let (step, count) = steps
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, count)| **count)?;
+    match count {
+        0 => None,
+        _ => Some((IndentStyle::Space, step as u8)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reindent() {
+        let config = BufferConfig::config_spaces(4);
+        let lines = [
+            "fn a() {",
+            "if x {",
+            "y(\"{\"); // {",
+            "}",
+            "",
+            "  let z = [",
+            "1,",
+            "];",
+            "}",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            reindent(None, &lines, &config),
+            vec![
+                "fn a() {",
+                "    if x {",
+                "        y(\"{\"); // {",
+                "    }",
+                "",
+                "    let z = [",
+                "        1,",
+                "    ];",
+                "}"
+            ]
+        );
+
+        // following the line above, with tabs
+        let config = BufferConfig::config_tabs();
+        let lines = vec!["x".to_string(), "}) {".to_string(), "y".to_string()];
+        assert_eq!(
+            reindent(Some("\t\tf({"), &lines, &config),
+            vec!["\t\t\tx", "\t\t}) {", "\t\t\ty"]
+        );
+        assert_eq!(indent_after("a)", &config), 0);
+    }
+
+    #[test]
+    fn test_retab() {
+        let mut config = BufferConfig::config_spaces(4);
+        config.tab_width = 4;
+        assert_eq!(
+            retab("\t  a\tb", 8, false, false, &config),
+            "          a     b"
+        );
+        assert_eq!(retab("\t  a\tb", 8, false, true, &config), "          a\tb");
+
+        // runs of spaces only change with bang, a single space never becomes a tab
+        let config = BufferConfig::config_tabs();
+        assert_eq!(retab("    \ta b", 4, false, false, &config), "\ta b");
+        assert_eq!(
+            retab("        x  y", 8, false, false, &config),
+            "        x  y"
+        );
+        assert_eq!(retab("        x  y", 8, true, false, &config), "\tx  y");
+        assert_eq!(
+            retab("12345678        x", 8, true, false, &config),
+            "12345678\tx"
+        );
+
+        // levels of two columns become levels of four
+        let config = BufferConfig::config_spaces(4);
+        assert_eq!(relevel("    a", 2, &config), "        a");
+        assert_eq!(relevel("     a", 2, &config), "         a");
+        assert_eq!(relevel("  ", 2, &config), "  ");
+        assert_eq!(relevel("    a", 4, &BufferConfig::config_tabs()), "\ta");
+    }
+
+    #[test]
+    fn test_detect() {
+        let text = Rope::from_str("a {\n  b {\n    c\n     * d\n  }\n\n  e\n}\n");
+        assert!(matches!(detect(&text, 100), Some((IndentStyle::Space, 2))));
+        let text = Rope::from_str("a\n    b\n\tc\n\td\n");
+        assert!(matches!(detect(&text, 100), Some((IndentStyle::Tab, _))));
+        // only the first lines are read
+        assert!(detect(&text, 1).is_none());
+        assert!(detect(&Rope::from_str("a\nb\n"), 100).is_none());
+    }
+
+    #[test]
+    fn test_new_line_indent() {
+        let mut config = BufferConfig::config_spaces(2);
+        assert_eq!(new_line_indent("  a {", "", &config), "    ");
+        assert_eq!(new_line_indent("  a {", "}", &config), "  ");
+        assert_eq!(new_line_indent("  a:", "", &config), "  ");
+        assert_eq!(above_line_indent("  }", &config), "    ");
+        config.indent_chars = ":".to_string();
+        assert_eq!(new_line_indent("  a: # b", "", &config), "    ");
+        assert_eq!(new_line_indent("  x = \"#\" # a:", "", &config), "  ");
+
+        // autoindent copies the whitespace as it is
+        config.smartindent = false;
+        assert_eq!(new_line_indent(" \ta {", "", &config), " \t");
+        assert_eq!(above_line_indent(" \t}", &config), " \t");
+        config.autoindent = false;
+        assert_eq!(new_line_indent(" \ta {", "", &config), "");
+    }
+}
@@ -1,19 +1,39 @@
 pub mod buffer;
+pub mod case;
+pub mod clipboard;
+pub mod comment;
 pub mod config;
+pub mod ex;
+pub mod filetype;
 pub mod grapheme_iter;
 pub mod grapheme_step;
+pub mod grep;
+pub mod indent;
 pub mod macros;
+pub mod marks;
+pub mod modeline;
+pub mod motion;
+pub mod number;
+pub mod reflow;
 pub mod registers;
+pub mod textobject;
 pub mod variables;
+pub mod word;
 
 pub use buffer::Buffer;
-pub use config::{BufferConfig, EndOfLine, IndentSize, IndentStyle, ViewPos};
+pub use case::Case;
+pub use clipboard::{Clipboard, ClipboardProvider};
+pub use config::{BufferConfig, EndOfLine, IndentSize, IndentSource, IndentStyle, ViewPos};
+pub use ex::{Address, ExCommand, ExContext, ExError, ExOp, LineRange};
 pub use grapheme_iter::{
     grapheme_width, nth_next_grapheme_boundary, nth_prev_grapheme_boundary, RopeGraphemes,
 };
 pub use grapheme_step::prev_grapheme_boundary;
-pub use macros::{MacroId, Macros};
-pub use registers::{Register, Registers};
+pub use grep::GrepMatch;
+pub use macros::MacroId;
+pub use marks::Marks;
+pub use registers::{Register, RegisterKind, RegisterValue, Registers};
+pub use textobject::TextObject;
 pub use variables::{Variable, Variables};
 
 use ropey::Rope;
@@ -22,14 +42,28 @@ use ropey::Rope;
 pub enum Mode {
     Normal,
     Insert,
+    Replace, // R, typed text overwrites the text under the cursor
     Easy,
     Cli,
+    Visual,
+    VisualLine,
+    VisualBlock,
 }
 impl Default for Mode {
     fn default() -> Self {
         Self::Normal
     }
 }
+impl Mode {
+    pub fn is_visual(&self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+
+    // the modes that type text into the buffer
+    pub fn is_insert(&self) -> bool {
+        matches!(self, Self::Insert | Self::Replace)
+    }
+}
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Motion {
@@ -53,17 +87,145 @@ pub enum Motion {
     ForwardWordEnd2,
     BackWord1,
     BackWord2,
+    BackWordEnd1,
+    BackWordEnd2,
     NextWord,
     EOW,
     PrevWord,
     SOW,
     NextSearch,
     PrevSearch,
-    Til1(char),
-    Til2(char),
+    Find(CharSearch),
+    RepeatFind(bool), // ; and , before they are resolved, true reverses the search
     // start and end of buffer
     SOB,
     EOB,
+    // { } ( ) %
+    ParagraphBack,
+    ParagraphForward,
+    SentenceBack,
+    SentenceForward,
+    MatchBracket,
+    // H M L, relative to the lines on screen
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    // gk and gj move by display line, Up and Down by buffer line
+    DisplayUp,
+    DisplayDown,
+    // ` and ', the position of a mark or the first non-blank on its line
+    Mark(char),
+    MarkLine(char),
+    // text objects, only valid as operator targets
+    Inner(TextObject),
+    Around(TextObject),
+    // the visual selection, only valid as an operator target
+    Selection,
+}
+
+impl Motion {
+    // operators on inclusive motions also take the char the motion stops on
+    pub fn is_inclusive(&self) -> bool {
+        match self {
+            Self::ForwardWordEnd1 | Self::ForwardWordEnd2 => true,
+            Self::BackWordEnd1 | Self::BackWordEnd2 => true,
+            Self::Find(search) => search.forward,
+            Self::MatchBracket => true,
+            _ => false,
+        }
+    }
+
+    // operators on linewise motions take whole lines
+    pub fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Self::Up
+                | Self::Down
+                | Self::ScreenTop
+                | Self::ScreenMiddle
+                | Self::ScreenBottom
+                | Self::MarkLine(_)
+        )
+    }
+
+    // motions that fail when they can't move the cursor, which stops a macro,
+    // the others can leave the cursor where it is
+    pub fn can_fail(&self) -> bool {
+        matches!(
+            self,
+            Self::Left
+                | Self::Right
+                | Self::Up
+                | Self::Down
+                | Self::NextLine
+                | Self::ForwardWord1
+                | Self::ForwardWord2
+                | Self::ForwardWordEnd1
+                | Self::ForwardWordEnd2
+                | Self::BackWord1
+                | Self::BackWord2
+                | Self::BackWordEnd1
+                | Self::BackWordEnd2
+                | Self::NextWord
+                | Self::PrevWord
+                | Self::NextSearch
+                | Self::PrevSearch
+                | Self::Find(_)
+                | Self::MatchBracket
+                | Self::DisplayUp
+                | Self::DisplayDown
+        )
+    }
+
+    // motions that can move far away, the position before them is kept in the '' mark
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Self::SOB
+                | Self::EOB
+                | Self::NextSearch
+                | Self::PrevSearch
+                | Self::ParagraphBack
+                | Self::ParagraphForward
+                | Self::SentenceBack
+                | Self::SentenceForward
+                | Self::MatchBracket
+                | Self::ScreenTop
+                | Self::ScreenMiddle
+                | Self::ScreenBottom
+                | Self::Mark(_)
+                | Self::MarkLine(_)
+        )
+    }
+}
+
+// a search for a char on the current line, f F t T
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct CharSearch {
+    pub ch: char,
+    pub forward: bool,
+    pub till: bool,   // stop next to the char
+    pub repeat: bool, // repeated with ; or ,
+}
+
+impl CharSearch {
+    pub fn new(ch: char, forward: bool, till: bool) -> Self {
+        Self {
+            ch,
+            forward,
+            till,
+            repeat: false,
+        }
+    }
+
+    // the search to run for ; or ,
+    pub fn repeat(&self, reverse: bool) -> Self {
+        Self {
+            forward: self.forward != reverse,
+            repeat: true,
+            ..*self
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -71,19 +233,24 @@ pub enum Command {
     Insert(String),
     Join,
     Motion(usize, Motion),
-    Delete(usize, Motion),
-    Yank(Register, Motion),         // register, Motion
-    Paste(usize, Register, Motion), // repetitions, register, Motion
+    Delete(usize, Register, Motion), // repetitions, register, Motion
+    Yank(Register, Motion),          // register, Motion
+    // repetitions, register, Right pastes after the cursor and OnCursor before it
+    Paste(usize, Register, Motion),
     RemoveChar(i32),
+    // r and gr, count, char, true replaces screen columns so tabs keep their layout
+    ReplaceChar(usize, char, bool),
     Mode(Mode),
     MacroStart(macros::MacroId),
     ChangeStart,
     ChangeEnd,
-    ChangeRepeat,
+    ChangeRepeat(Option<usize>), // ., with a count that replaces the one in the change
     CliEdit(Vec<Command>),
     CliExec,
     CliCancel,
     MacroEnd,
+    MacroPlay(usize, macros::MacroId), // the count and register of @
+    Macro(Vec<Command>), // the commands played from a macro, they stop at the first failing motion
     Quit,
     Stop,
     Save,
@@ -93,6 +260,8 @@ pub enum Command {
     SaveBuffer(String, Rope),
     Mouse(u16, u16),
     Scroll(i16),
+    // zt zz zb, scroll so the cursor line is at ScreenTop, ScreenMiddle or ScreenBottom
+    ScrollCursor(Motion),
     ScrollPage(i8),
     Line(i64),
     LineNav(i32),
@@ -108,6 +277,65 @@ pub enum Command {
     Reset,
     VarGet(String),
     VarSet(String, String),
+    Ex(ExCommand),
+    Grep(String, Vec<String>),              // pattern, paths
+    GrepSearch(usize, String, Vec<String>), // search id, pattern, paths
+    GrepMatch(usize, GrepMatch),            // search id, match
+    GrepDone(usize, usize),                 // search id, number of matches
+    QuickfixNext(i32),
+    Replace(String, String, bool, Vec<String>), // pattern, replacement, replace all, paths
+    ReplaceApply,
+    Select,
+    VisualSwap,
+    VisualRestore,
+    ChangeCase(usize, Case, Motion),
+    Increment(usize, i8), // [count]ctrl-a and [count]ctrl-x, the count and 1 or -1
+    LineOp(usize, LineOp, Motion),
+    OpenLine(bool), // o and O, true opens a line below the cursor
+    MarkSet(char),
+    Marks,
+    DelMarks(Vec<char>),
+    Jump(i32),            // ctrl-o and ctrl-i, negative goes back
+    Jumps,                // list the jumps
+    ChangeList(i32),      // g; and g, negative goes to older changes
+    Registers(Vec<char>), // list the registers, or only these
+}
+
+// operators that act on whole lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOp {
+    Shift(i32),   // > and <, indent levels
+    Reindent,     // =
+    Format(bool), // gq, and gw which leaves the cursor where it was
+    Comment,      // gc
+}
+
+impl Command {
+    // the motion an operator or motion command acts on
+    pub fn motion_mut(&mut self) -> Option<&mut Motion> {
+        match self {
+            Self::Motion(_, m)
+            | Self::Delete(_, _, m)
+            | Self::Yank(_, m)
+            | Self::Paste(_, _, m)
+            | Self::ChangeCase(_, _, m)
+            | Self::LineOp(_, _, m) => Some(m),
+            _ => None,
+        }
+    }
+
+    // the count of an operator, a new count for . replaces it
+    pub fn count_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Self::Delete(reps, _, _)
+            | Self::Paste(reps, _, _)
+            | Self::ReplaceChar(reps, _, _)
+            | Self::ChangeCase(reps, _, _)
+            | Self::Increment(reps, _)
+            | Self::LineOp(reps, _, _) => Some(reps),
+            _ => None,
+        }
+    }
 }
 
 use std::convert::{From, Into};
@@ -1,43 +1,17 @@
-use super::Command;
-use log::*;
-use std::collections::HashMap;
-
+// the register a macro is recorded in or played from, @ is the last macro played
 #[derive(Eq, Hash, PartialEq, Debug, Copy, Clone)]
 pub struct MacroId(pub char);
 
-#[derive(Eq, PartialEq, Debug, Clone)]
-pub struct Macros {
-    h: HashMap<MacroId, Vec<Command>>,
-}
-
-impl Default for Macros {
-    fn default() -> Self {
-        Self { h: HashMap::new() }
-    }
-}
-
-impl Macros {
-    pub fn clear_all(&mut self) {
-        self.h.clear();
-    }
-
-    pub fn add(&mut self, id: &MacroId, c: &Command) {
-        match self.h.get_mut(id) {
-            Some(v) => {
-                v.push(c.clone());
-            }
-            None => {
-                self.h.insert(*id, vec![]);
-            }
-        }
-        info!("Macro[{:?}] = {:?}", id, self.h.get(id));
-    }
+impl MacroId {
+    pub const LAST: MacroId = MacroId('@');
 
-    pub fn clear(&mut self, id: &MacroId) {
-        self.h.remove(id);
+    // registers that can be recorded into, uppercase appends
+    pub fn is_recordable(&self) -> bool {
+        self.0.is_ascii_alphanumeric() || self.0 == '"'
     }
 
-    pub fn get(&self, id: &MacroId) -> Vec<Command> {
-        self.h.get(id).unwrap_or(&vec![]).clone()
+    // registers that can be played, : plays the last command line
+    pub fn is_playable(&self) -> bool {
+        self.is_recordable() || "-.+*:@".contains(self.0)
     }
 }
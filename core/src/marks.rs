@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+// the previous context mark, '' and `` both refer to it
+pub const PREVIOUS: char = '\'';
+// the last change, '.
+pub const CHANGE: char = '.';
+// where insert mode was last left, '^
+pub const INSERT: char = '^';
+
+const MAX_CHANGES: usize = 100;
+
+// named char positions in a buffer, anchored to the text so they move with edits
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    marks: HashMap<char, usize>,
+    changes: Vec<usize>, // recent change positions, oldest first, for g; and g,
+}
+
+impl Marks {
+    pub fn set(&mut self, ch: char, c: usize) {
+        self.marks.insert(ch, c);
+    }
+
+    pub fn get(&self, ch: char) -> Option<usize> {
+        self.marks.get(&ch).copied()
+    }
+
+    pub fn remove(&mut self, ch: char) {
+        self.marks.remove(&ch);
+    }
+
+    // all marks, sorted by name
+    pub fn list(&self) -> Vec<(char, usize)> {
+        let mut marks = self
+            .marks
+            .iter()
+            .map(|(ch, c)| (*ch, *c))
+            .collect::<Vec<(char, usize)>>();
+        marks.sort();
+        marks
+    }
+
+    pub fn changes(&self) -> &[usize] {
+        &self.changes
+    }
+
+    // record a change at c, merge replaces the last change, so typing on a line is one entry
+    pub fn changed(&mut self, c: usize, merge: bool) {
+        self.set(CHANGE, c);
+        match self.changes.last_mut() {
+            Some(last) if merge => *last = c,
+            _ => {
+                self.changes.push(c);
+                if self.changes.len() > MAX_CHANGES {
+                    self.changes.remove(0);
+                }
+            }
+        }
+    }
+
+    // len chars were inserted at c, marks at or after c move with the text
+    pub fn inserted(&mut self, c: usize, len: usize) {
+        self.marks
+            .values_mut()
+            .chain(self.changes.iter_mut())
+            .for_each(|m| {
+                if *m >= c {
+                    *m += len;
+                }
+            });
+    }
+
+    // the chars start..end were removed, marks inside the range move to its start
+    pub fn removed(&mut self, start: usize, end: usize) {
+        self.marks
+            .values_mut()
+            .chain(self.changes.iter_mut())
+            .for_each(|m| {
+                if *m >= end {
+                    *m -= end - start;
+                } else if *m > start {
+                    *m = start;
+                }
+            });
+    }
+}
+
+// a mark that can be jumped to, ` is another name for '
+pub fn is_mark(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || "'`.^<>".contains(ch)
+}
+
+// uppercase marks are global, they remember the file as well as the position
+pub fn is_file_mark(ch: char) -> bool {
+    ch.is_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut marks = Marks::default();
+        marks.set('a', 5);
+        marks.set('b', 10);
+        marks.set('c', 2);
+
+        marks.inserted(3, 4);
+        marks.changed(3, false);
+        assert_eq!(marks.get('a'), Some(9));
+        assert_eq!(marks.get('b'), Some(14));
+        assert_eq!(marks.get('c'), Some(2));
+        assert_eq!(marks.get(CHANGE), Some(3));
+
+        // marks inside a removed range move to the start of it
+        marks.removed(8, 12);
+        marks.changed(8, false);
+        assert_eq!(marks.get('a'), Some(8));
+        assert_eq!(marks.get('b'), Some(10));
+        assert_eq!(marks.get('c'), Some(2));
+        assert_eq!(marks.get(CHANGE), Some(8));
+
+        marks.remove('a');
+        assert_eq!(marks.get('a'), None);
+        assert_eq!(marks.list(), vec![(CHANGE, 8), ('b', 10), ('c', 2)]);
+
+        // changes move with the text, and a merged change replaces the last one
+        assert_eq!(marks.changes(), &[3, 8]);
+        marks.inserted(0, 2);
+        marks.changed(0, false);
+        marks.changed(1, true);
+        assert_eq!(marks.changes(), &[5, 10, 1]);
+    }
+}
@@ -0,0 +1,146 @@
+// modelines in the first and last lines of a file, vim ones like "vim: set ts=4 sw=4:" or
+// "vi: et", and emacs ones like "-*- mode: yaml; indent-tabs-mode: nil -*-"
+use ropey::Rope;
+
+// lines read at each end of a file
+const MODELINES: usize = 5;
+
+// the options in a modeline, in order, with the names :set uses, a flag has an empty value
+pub fn parse(line: &str) -> Option<Vec<(String, String)>> {
+    parse_vim(line).or_else(|| parse_emacs(line))
+}
+
+fn parse_vim(line: &str) -> Option<Vec<(String, String)>> {
+    let start = ["vim:", "vi:", "ex:", "Vim:"]
+        .iter()
+        .filter_map(|tag| {
+            line.match_indices(tag)
+                .find(|(i, _)| *i == 0 || line[..*i].ends_with(char::is_whitespace))
+                .map(|(i, _)| i + tag.len())
+        })
+        .min()?;
+    let rest = line[start..].trim_start();
+    // the second form ends at a colon, set options are separated by spaces
+    let (rest, set) = match rest
+        .strip_prefix("set ")
+        .or_else(|| rest.strip_prefix("se "))
+    {
+        Some(rest) => (rest.split(':').next().unwrap_or(""), true),
+        None => (rest, false),
+    };
+    let options = rest
+        .split(|ch: char| ch.is_whitespace() || (!set && ch == ':'))
+        .map(|s| match s.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (s.to_string(), String::new()),
+        })
+        // skips what comes after, like the end of a comment
+        .filter(|(name, _)| !name.is_empty() && name.chars().all(|ch| ch.is_ascii_alphanumeric()))
+        .collect::<Vec<_>>();
+    match options.is_empty() {
+        true => None,
+        false => Some(options),
+    }
+}
+
+// the emacs variables we know, as options
+fn emacs_option(name: &str, value: &str) -> Option<(String, String)> {
+    let option = match name {
+        "mode" => {
+            let mode = value.to_lowercase();
+            let mode = mode.trim_end_matches("-mode");
+            let ft = match mode {
+                "c++" => "cpp",
+                "js" | "js2" => "javascript",
+                "shell-script" | "bash" => "sh",
+                "emacs-lisp" | "lisp-interaction" | "scheme" | "clojure" => "lisp",
+                "makefile" | "makefile-gmake" => "make",
+                "perl" | "cperl" => "perl",
+                _ => mode,
+            };
+            ("filetype", ft.to_string())
+        }
+        "indent-tabs-mode" => ("expandtab", (value == "nil").to_string()),
+        "tab-width" => ("tabstop", value.to_string()),
+        "fill-column" => ("textwidth", value.to_string()),
+        _ if name == "standard-indent"
+            || name.ends_with("-offset")
+            || name.ends_with("-indent-level") =>
+        {
+            ("shiftwidth", value.to_string())
+        }
+        _ => return None,
+    };
+    Some((option.0.to_string(), option.1))
+}
+
+// "-*- mode: yaml; tab-width: 2 -*-", or just "-*- yaml -*-"
+fn parse_emacs(line: &str) -> Option<Vec<(String, String)>> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    let vars = vars.trim();
+    if !vars.contains(':') {
+        return emacs_option("mode", vars).map(|option| vec![option]);
+    }
+    let options = vars
+        .split(';')
+        .filter_map(|var| var.split_once(':'))
+        .filter_map(|(name, value)| emacs_option(name.trim(), value.trim()))
+        .collect::<Vec<_>>();
+    match options.is_empty() {
+        true => None,
+        false => Some(options),
+    }
+}
+
+// the options from the modelines in a text, later lines win
+pub fn options(text: &Rope) -> Vec<(String, String)> {
+    let len = text.len_lines();
+    let first = 0..std::cmp::min(MODELINES, len);
+    let last = std::cmp::max(first.end, len.saturating_sub(MODELINES))..len;
+    first
+        .chain(last)
+        .filter_map(|line_inx| parse(&text.line(line_inx).to_string()))
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modeline() {
+        let pairs = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            parse("# vim: set ts=4 et: trailing"),
+            Some(pairs(&[("ts", "4"), ("et", "")]))
+        );
+        assert_eq!(
+            parse("// vi:noai:sw=2"),
+            Some(pairs(&[("noai", ""), ("sw", "2")]))
+        );
+        assert_eq!(parse("xvim: ts=2"), None);
+        assert_eq!(parse("no modeline"), None);
+        assert_eq!(
+            parse("# -*- mode: Python; indent-tabs-mode: nil; python-indent-offset: 2 -*-"),
+            Some(pairs(&[
+                ("filetype", "python"),
+                ("expandtab", "true"),
+                ("shiftwidth", "2")
+            ]))
+        );
+        assert_eq!(
+            parse("/* -*- c++ -*- */"),
+            Some(pairs(&[("filetype", "cpp")]))
+        );
+        assert_eq!(parse("-*- coding: utf-8 -*-"), None);
+
+        let text = Rope::from_str("a\n/* vim: ft=c */\nb\nc\nd\ne\nf\ng\n# vim: ft=sh\n");
+        assert_eq!(options(&text).last(), Some(&pairs(&[("ft", "sh")])[0]));
+    }
+}
@@ -0,0 +1,209 @@
+use ropey::Rope;
+
+// the number of lines, not counting the empty line after a final newline
+fn line_count(text: &Rope) -> usize {
+    let lines = text.len_lines();
+    if text.len_chars() > 0 && text.char(text.len_chars() - 1) == '\n' {
+        lines - 1
+    } else {
+        lines
+    }
+}
+
+fn is_empty_line(text: &Rope, line_inx: usize) -> bool {
+    text.line(line_inx)
+        .chars()
+        .all(|ch| ch == '\n' || ch == '\r')
+}
+
+// the last char a motion can stop on, before the final newline
+fn end_of_text(text: &Rope) -> usize {
+    let len = text.len_chars();
+    if len > 0 && text.char(len - 1) == '\n' {
+        len - 1
+    } else {
+        len
+    }
+}
+
+// } the next empty line after the paragraph, or the end of the text
+pub fn paragraph_forward(text: &Rope, c: usize, count: usize) -> usize {
+    let lines = line_count(text);
+    let mut line_inx = text.char_to_line(std::cmp::min(c, text.len_chars()));
+    for _ in 0..count {
+        while line_inx < lines && is_empty_line(text, line_inx) {
+            line_inx += 1;
+        }
+        while line_inx < lines && !is_empty_line(text, line_inx) {
+            line_inx += 1;
+        }
+    }
+    if line_inx < lines {
+        text.line_to_char(line_inx)
+    } else {
+        end_of_text(text)
+    }
+}
+
+// { the empty line before the paragraph, or the start of the text
+pub fn paragraph_backward(text: &Rope, c: usize, count: usize) -> usize {
+    let mut line_inx = text.char_to_line(std::cmp::min(c, text.len_chars())) as i64;
+    for _ in 0..count {
+        while line_inx >= 0 && is_empty_line(text, line_inx as usize) {
+            line_inx -= 1;
+        }
+        while line_inx >= 0 && !is_empty_line(text, line_inx as usize) {
+            line_inx -= 1;
+        }
+    }
+    if line_inx >= 0 {
+        text.line_to_char(line_inx as usize)
+    } else {
+        0
+    }
+}
+
+// a sentence starts after . ! or ? and whitespace, closing brackets and quotes can follow the
+// punctuation, empty lines are sentences of their own
+fn is_sentence_start(text: &Rope, c: usize) -> bool {
+    let ch = text.char(c);
+    if ch == '\n' && (c == 0 || text.char(c - 1) == '\n') {
+        return true;
+    }
+    if ch.is_whitespace() {
+        return false;
+    }
+    let mut inx = c;
+    while inx > 0 && text.char(inx - 1).is_whitespace() {
+        // the first word after an empty line
+        if inx >= 2 && text.char(inx - 1) == '\n' && text.char(inx - 2) == '\n' {
+            return true;
+        }
+        inx -= 1;
+    }
+    if inx == 0 {
+        return true;
+    }
+    if inx == c {
+        return false;
+    }
+    while inx > 0 && ")]\"'".contains(text.char(inx - 1)) {
+        inx -= 1;
+    }
+    inx > 0 && ".!?".contains(text.char(inx - 1))
+}
+
+// ) the start of the next sentence
+pub fn sentence_forward(text: &Rope, c: usize, count: usize) -> usize {
+    let end = end_of_text(text);
+    let mut c = c;
+    for _ in 0..count {
+        c += 1;
+        while c < end && !is_sentence_start(text, c) {
+            c += 1;
+        }
+        if c >= end {
+            return end;
+        }
+    }
+    c
+}
+
+// ( the start of this sentence, or the one before it
+pub fn sentence_backward(text: &Rope, c: usize, count: usize) -> usize {
+    let mut c = std::cmp::min(c, end_of_text(text));
+    for _ in 0..count {
+        if c == 0 {
+            break;
+        }
+        c -= 1;
+        while c > 0 && !is_sentence_start(text, c) {
+            c -= 1;
+        }
+    }
+    c
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// % the bracket matching the first bracket at or after the cursor on the line
+pub fn match_bracket(text: &Rope, c: usize) -> Option<usize> {
+    let len = text.len_chars();
+    let mut inx = c;
+    let (ch, forward, open, close) = loop {
+        if inx >= len || text.char(inx) == '\n' {
+            return None;
+        }
+        let ch = text.char(inx);
+        if let Some((open, close)) = BRACKETS.iter().find(|(o, c)| *o == ch || *c == ch) {
+            break (ch, ch == *open, *open, *close);
+        }
+        inx += 1;
+    };
+
+    let mut depth = 0;
+    let mut at = inx;
+    loop {
+        if forward {
+            at += 1;
+            if at >= len {
+                return None;
+            }
+        } else {
+            if at == 0 {
+                return None;
+            }
+            at -= 1;
+        }
+        let next = text.char(at);
+        if next == ch {
+            depth += 1;
+        } else if next == open || next == close {
+            if depth == 0 {
+                return Some(at);
+            }
+            depth -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paragraphs() {
+        let text = Rope::from("a\nb\n\n\nc\nd\n\ne\n");
+        assert_eq!(paragraph_forward(&text, 0, 1), 4);
+        assert_eq!(paragraph_forward(&text, 4, 1), 10);
+        assert_eq!(paragraph_forward(&text, 0, 2), 10);
+        assert_eq!(paragraph_forward(&text, 10, 1), 12);
+        assert_eq!(paragraph_backward(&text, 11, 1), 10);
+        assert_eq!(paragraph_backward(&text, 10, 1), 5);
+        assert_eq!(paragraph_backward(&text, 8, 2), 0);
+    }
+
+    #[test]
+    fn test_sentences() {
+        let text = Rope::from("One. (Two!) Three?\n\nFour\n");
+        assert_eq!(sentence_forward(&text, 0, 1), 5);
+        assert_eq!(sentence_forward(&text, 5, 1), 12);
+        assert_eq!(sentence_forward(&text, 12, 1), 19);
+        assert_eq!(sentence_forward(&text, 19, 1), 20);
+        assert_eq!(sentence_forward(&text, 0, 2), 12);
+        assert_eq!(sentence_backward(&text, 14, 1), 12);
+        assert_eq!(sentence_backward(&text, 12, 2), 0);
+        assert_eq!(sentence_backward(&text, 20, 1), 19);
+    }
+
+    #[test]
+    fn test_match_bracket() {
+        let text = Rope::from("f(a[1], {b}) x\n(\n");
+        assert_eq!(match_bracket(&text, 0), Some(11));
+        assert_eq!(match_bracket(&text, 11), Some(1));
+        assert_eq!(match_bracket(&text, 3), Some(5));
+        assert_eq!(match_bracket(&text, 8), Some(10));
+        assert_eq!(match_bracket(&text, 12), None);
+        assert_eq!(match_bracket(&text, 15), None);
+    }
+}
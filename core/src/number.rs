@@ -0,0 +1,189 @@
+// ctrl-a and ctrl-x, adding to the number at or after the cursor on a line
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Radix {
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+}
+
+// a number found on a line, as char offsets
+struct Token {
+    start: usize,
+    end: usize,
+    radix: Radix,
+}
+
+// the number or letter at or after col that nrformats allows, replaced by the result of adding
+// delta, as the char range it covered and the new text, None if there isn't one
+pub fn increment(
+    line: &str,
+    col: usize,
+    delta: i64,
+    nrformats: &str,
+) -> Option<(usize, usize, String)> {
+    let chars = line
+        .trim_end_matches(['\n', '\r'])
+        .chars()
+        .collect::<Vec<char>>();
+    let formats = nrformats.split(',').map(|s| s.trim()).collect::<Vec<_>>();
+    let has = |name: &str| formats.contains(&name);
+
+    if has("alpha") {
+        let first = (col..chars.len()).find(|i| chars[*i].is_ascii_alphanumeric());
+        if let Some(i) = first.filter(|i| chars[*i].is_ascii_alphabetic()) {
+            return Some((i, i + 1, add_alpha(chars[i], delta).to_string()));
+        }
+    }
+
+    let token = tokens(&chars, has("hex"), has("bin"), has("octal"))
+        .into_iter()
+        .find(|t| t.end > col)?;
+    let s = chars[token.start..token.end].iter().collect::<String>();
+    let replacement = match token.radix {
+        Radix::Decimal => add_decimal(&s, delta),
+        Radix::Hex => {
+            let (prefix, digits) = s.split_at(2);
+            let n = u64::from_str_radix(digits, 16)
+                .ok()?
+                .wrapping_add(delta as u64);
+            let upper = digits.chars().any(|ch| ch.is_ascii_uppercase());
+            let out = match upper {
+                true => format!("{:0width$X}", n, width = digits.len()),
+                false => format!("{:0width$x}", n, width = digits.len()),
+            };
+            format!("{}{}", prefix, out)
+        }
+        Radix::Binary => {
+            let (prefix, digits) = s.split_at(2);
+            let n = u64::from_str_radix(digits, 2)
+                .ok()?
+                .wrapping_add(delta as u64);
+            format!("{}{:0width$b}", prefix, n, width = digits.len())
+        }
+        Radix::Octal => {
+            let n = u64::from_str_radix(&s, 8).ok()?.wrapping_add(delta as u64);
+            format!("0{:0width$o}", n, width = s.len() - 1)
+        }
+    };
+    Some((token.start, token.end, replacement))
+}
+
+// the numbers on a line, in order, a - before a decimal makes it negative
+fn tokens(chars: &[char], hex: bool, bin: bool, octal: bool) -> Vec<Token> {
+    let run = |start: usize, f: fn(&char) -> bool| {
+        start + chars[start..].iter().take_while(|ch| f(ch)).count()
+    };
+    let mut out = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let prefix = chars.get(i + 1).map(|ch| ch.to_ascii_lowercase());
+        let token = match (chars[i], prefix) {
+            ('0', Some('x')) if hex && run(i + 2, char::is_ascii_hexdigit) > i + 2 => Token {
+                start: i,
+                end: run(i + 2, char::is_ascii_hexdigit),
+                radix: Radix::Hex,
+            },
+            ('0', Some('b')) if bin && run(i + 2, |ch| *ch == '0' || *ch == '1') > i + 2 => Token {
+                start: i,
+                end: run(i + 2, |ch| *ch == '0' || *ch == '1'),
+                radix: Radix::Binary,
+            },
+            _ => {
+                let end = run(i, char::is_ascii_digit);
+                let is_octal = octal
+                    && chars[i] == '0'
+                    && end - i > 1
+                    && chars[i..end].iter().all(|ch| ('0'..='7').contains(ch));
+                if is_octal {
+                    Token {
+                        start: i,
+                        end,
+                        radix: Radix::Octal,
+                    }
+                } else {
+                    let negative = i > 0 && chars[i - 1] == '-';
+                    Token {
+                        start: if negative { i - 1 } else { i },
+                        end,
+                        radix: Radix::Decimal,
+                    }
+                }
+            }
+        };
+        i = token.end;
+        out.push(token);
+    }
+    out
+}
+
+// leading zeros keep the number the same width
+fn add_decimal(s: &str, delta: i64) -> String {
+    let n = s.parse::<i128>().unwrap_or(i64::MAX as i128);
+    let n = (n + delta as i128).clamp(i64::MIN as i128, i64::MAX as i128);
+    let digits = s.trim_start_matches('-');
+    let width = match digits.starts_with('0') && digits.len() > 1 {
+        true => digits.len(),
+        false => 0,
+    };
+    let sign = if n < 0 { "-" } else { "" };
+    format!("{}{:0width$}", sign, n.unsigned_abs(), width = width)
+}
+
+// letters stop at a and z
+fn add_alpha(ch: char, delta: i64) -> char {
+    let (a, z) = match ch.is_ascii_uppercase() {
+        true => (b'A' as i64, b'Z' as i64),
+        false => (b'a' as i64, b'z' as i64),
+    };
+    ((ch as i64 + delta).clamp(a, z) as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inc(line: &str, col: usize, delta: i64, nrformats: &str) -> Option<String> {
+        increment(line, col, delta, nrformats).map(|(start, end, s)| {
+            let chars = line.chars().collect::<Vec<_>>();
+            let before = chars[..start].iter().collect::<String>();
+            let after = chars[end..].iter().collect::<String>();
+            before + &s + &after
+        })
+    }
+
+    #[test]
+    fn test_increment() {
+        let f = "bin,hex";
+        // at or after the cursor, with the sign
+        assert_eq!(inc("x = 9;", 0, 1, f).unwrap(), "x = 10;");
+        assert_eq!(inc("a 1 b 2", 2, 5, f).unwrap(), "a 6 b 2");
+        assert_eq!(inc("a 1 b 2", 3, 5, f).unwrap(), "a 1 b 7");
+        assert_eq!(inc("x-1", 0, 3, f).unwrap(), "x2");
+        assert_eq!(inc("0", 0, -2, f).unwrap(), "-2");
+        assert_eq!(inc("007", 0, 1, f).unwrap(), "008");
+        assert_eq!(inc("no number", 0, 1, f), None);
+        assert_eq!(inc("1 a", 2, 1, f), None);
+
+        // hex and binary keep their width and case
+        assert_eq!(inc("0x0f", 0, 1, f).unwrap(), "0x10");
+        assert_eq!(inc("0XFF", 3, 1, f).unwrap(), "0X100");
+        assert_eq!(inc("0x00", 0, -1, f).unwrap(), "0xffffffffffffffff");
+        assert_eq!(inc("0b0011", 0, 1, f).unwrap(), "0b0100");
+        assert_eq!(inc("0x10", 0, 1, "").unwrap(), "1x10");
+
+        // octal and letters only when nrformats has them
+        assert_eq!(inc("017", 0, 1, f).unwrap(), "018");
+        assert_eq!(inc("017", 0, 1, "octal").unwrap(), "020");
+        assert_eq!(inc("089", 0, 1, "octal").unwrap(), "090");
+        assert_eq!(inc("a1", 0, 1, f).unwrap(), "a2");
+        assert_eq!(inc("a1", 0, 2, "alpha").unwrap(), "c1");
+        assert_eq!(inc("Y", 0, 5, "alpha").unwrap(), "Z");
+        assert_eq!(inc(" 1a", 0, 1, "alpha").unwrap(), " 2a");
+    }
+}
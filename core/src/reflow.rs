@@ -0,0 +1,160 @@
+// gq and gw, filling paragraphs to a text width, the buffer equivalent of wrapping for display
+use unicode_width::UnicodeWidthStr;
+
+// comment leaders, longest first so /// isn't taken as //
+const LEADERS: [&str; 8] = ["///", "//!", "//", "#", "--", ";;", ";", ">"];
+
+// a line split into its prefix (indent, comment leader and the space after it), a list marker
+// and the text
+struct Parts<'a> {
+    prefix: &'a str,
+    marker: &'a str,
+    text: &'a str,
+}
+
+fn parts(line: &str) -> Parts<'_> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let leader = LEADERS
+        .iter()
+        .find(|l| rest.starts_with(*l))
+        .map(|l| l.len())
+        .unwrap_or(0);
+    let rest = &rest[leader..];
+    let prefix_len = indent + leader + rest.len() - rest.trim_start().len();
+    let body = &line[prefix_len..];
+    let marker_len = list_marker(body);
+    Parts {
+        prefix: &line[..prefix_len],
+        marker: &body[..marker_len],
+        text: &body[marker_len..],
+    }
+}
+
+// the length of a list marker and the space after it, -, *, + or a number with . or )
+fn list_marker(s: &str) -> usize {
+    let digits = s.len() - s.trim_start_matches(|ch: char| ch.is_ascii_digit()).len();
+    let len = match s[digits..].chars().next() {
+        Some('.' | ')') if digits > 0 => digits + 1,
+        Some('-' | '*' | '+') if digits == 0 => 1,
+        _ => return 0,
+    };
+    let rest = &s[len..];
+    match rest.starts_with([' ', '\t']) {
+        true => s.len() - rest.trim_start().len(),
+        false => 0,
+    }
+}
+
+// the display width of a prefix, with tabs taken to the next tab stop
+fn prefix_width(s: &str, tab_width: usize) -> usize {
+    s.chars().fold(0, |w, ch| match ch {
+        '\t' => w + tab_width - w % tab_width,
+        _ => w + ch.to_string().width(),
+    })
+}
+
+// a paragraph being filled, continuation lines must have the hanging prefix
+struct Paragraph {
+    first: String,
+    hanging: String,
+    words: Vec<String>,
+}
+
+impl Paragraph {
+    fn fill(&self, width: usize, tab_width: usize, out: &mut Vec<String>) {
+        let mut line = self.first.clone();
+        let mut line_width = prefix_width(&line, tab_width);
+        let mut empty = true;
+        for word in &self.words {
+            let w = word.width();
+            if !empty && line_width + 1 + w > width {
+                out.push(line.trim_end().to_string());
+                line = self.hanging.clone();
+                line_width = prefix_width(&line, tab_width);
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += w;
+            empty = false;
+        }
+        out.push(line.trim_end().to_string());
+    }
+}
+
+// lines without their line endings filled to width, lines with no text separate paragraphs, a
+// list marker starts a new one and the comment leaders are kept on every line
+pub fn reflow(lines: &[String], width: usize, tab_width: usize) -> Vec<String> {
+    let tab_width = std::cmp::max(1, tab_width);
+    let mut out = vec![];
+    let mut para: Option<Paragraph> = None;
+    for line in lines {
+        let p = parts(line);
+        let words = p.text.split_whitespace().map(|s| s.to_string());
+        match &mut para {
+            Some(para) if p.marker.is_empty() && p.prefix == para.hanging && !p.text.is_empty() => {
+                para.words.extend(words);
+                continue;
+            }
+            _ => (),
+        }
+        if let Some(para) = para.take() {
+            para.fill(width, tab_width, &mut out);
+        }
+        if p.text.trim().is_empty() && p.marker.is_empty() {
+            out.push(line.trim_end().to_string());
+            continue;
+        }
+        let first = format!("{}{}", p.prefix, p.marker);
+        let hanging = format!("{}{}", p.prefix, " ".repeat(p.marker.width()));
+        para = Some(Paragraph {
+            first,
+            hanging,
+            words: words.collect(),
+        });
+    }
+    if let Some(para) = para {
+        para.fill(width, tab_width, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<String> {
+        s.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_reflow() {
+        let text = "one two three\nfour five six seven\n\n  eight nine ten eleven twelve";
+        assert_eq!(
+            reflow(&lines(text), 14, 8),
+            lines("one two three\nfour five six\nseven\n\n  eight nine\n  ten eleven\n  twelve")
+        );
+
+        // comment leaders and list markers
+        let text = "// - alpha beta gamma\n//   delta\n// - epsilon\n//\n/// zeta eta theta iota";
+        assert_eq!(
+            reflow(&lines(text), 16, 8),
+            lines(
+                "// - alpha beta\n//   gamma delta\n// - epsilon\n//\n/// zeta eta\n/// theta iota"
+            )
+        );
+        let text = "1. a b c d\n10) e f";
+        assert_eq!(reflow(&lines(text), 7, 8), lines("1. a b\n   c d\n10) e f"));
+
+        // long words get a line of their own, tabs count to the tab stop
+        let text = "\tsupercalifragilistic x";
+        assert_eq!(
+            reflow(&lines(text), 20, 8),
+            lines("\tsupercalifragilistic\n\tx")
+        );
+    }
+}
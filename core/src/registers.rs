@@ -1,25 +1,270 @@
 use log::*;
+use parking_lot::RwLock;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Eq, Hash, PartialEq, Debug, Copy, Clone)]
 pub struct Register(pub char);
 
-pub struct Registers {
-    regs: HashMap<Register, String>,
+impl Register {
+    // the register used when none is given
+    pub const UNNAMED: Register = Register('"');
+    // the last yank
+    pub const YANK: Register = Register('0');
+    // deletes within a line
+    pub const SMALL_DELETE: Register = Register('-');
+    // writing here does nothing
+    pub const BLACKHOLE: Register = Register('_');
+    // read-only, the last inserted text, file name, command line and search
+    pub const LAST_INSERT: Register = Register('.');
+    pub const FILE_NAME: Register = Register('%');
+    pub const COMMAND: Register = Register(':');
+    pub const SEARCH: Register = Register('/');
+    // the system clipboard and the primary selection
+    pub const CLIPBOARD: Register = Register('+');
+    pub const SELECTION: Register = Register('*');
+
+    pub fn is_valid(&self) -> bool {
+        self.0.is_ascii_alphanumeric() || "\"-_.%:/+*".contains(self.0)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        ".%:/".contains(self.0)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.is_valid() && !self.is_read_only()
+    }
+
+    pub fn is_clipboard(&self) -> bool {
+        *self == Self::CLIPBOARD || *self == Self::SELECTION
+    }
+
+    // uppercase names append to the lowercase register
+    pub fn is_append(&self) -> bool {
+        self.0.is_ascii_uppercase()
+    }
 }
-impl Default for Registers {
-    fn default() -> Self {
-        Self {
-            regs: HashMap::new(),
+
+// how the text was taken, which decides how it gets put back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegisterKind {
+    #[default]
+    Charwise,
+    Linewise,
+    Blockwise, // one line of the block on each line, without a trailing newline
+}
+
+impl RegisterKind {
+    // the type column in :registers
+    pub fn letter(&self) -> char {
+        match self {
+            Self::Charwise => 'c',
+            Self::Linewise => 'l',
+            Self::Blockwise => 'b',
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RegisterValue {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl RegisterValue {
+    pub fn new(text: &str, kind: RegisterKind) -> Self {
+        let mut text = text.to_string();
+        // linewise text always ends a line, even from the last line of a buffer
+        if kind == RegisterKind::Linewise && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Self { text, kind }
+    }
+
+    // the result of appending other, linewise wins over charwise
+    fn append(&self, other: &RegisterValue) -> Self {
+        use RegisterKind::*;
+        match (self.kind, other.kind) {
+            (Charwise, Charwise) => Self::new(&(self.text.clone() + &other.text), Charwise),
+            (Blockwise, Blockwise) => {
+                Self::new(&format!("{}\n{}", self.text, other.text), Blockwise)
+            }
+            _ => {
+                let mut text = self.text.clone();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                Self::new(&(text + &other.text), Linewise)
+            }
+        }
+    }
+}
+
+// clones share the registers, the input reader records and plays macros from them
+#[derive(Default, Clone)]
+pub struct Registers {
+    regs: Arc<RwLock<HashMap<Register, RegisterValue>>>,
+}
+
 impl Registers {
-    pub fn update(&mut self, r: &Register, s: &String) {
-        info!("Reg[{:?}] = {}", r, &s);
-        self.regs.insert(*r, s.clone());
+    // yanks go in the named register, or in 0 if there isn't one
+    pub fn yank(&mut self, r: &Register, value: RegisterValue) {
+        match *r {
+            Register::BLACKHOLE => (),
+            Register::UNNAMED => self.write(&Register::YANK, value),
+            r => self.write(&r, value),
+        }
+    }
+
+    // deletes without a register shift the numbered registers,
+    // unless they are within a line, then they go in -
+    pub fn delete(&mut self, r: &Register, value: RegisterValue) {
+        match *r {
+            Register::BLACKHOLE => (),
+            Register::UNNAMED
+                if value.kind == RegisterKind::Charwise && !value.text.contains('\n') =>
+            {
+                self.write(&Register::SMALL_DELETE, value)
+            }
+            Register::UNNAMED => {
+                let mut regs = self.regs.write();
+                for n in (1..9).rev() {
+                    let from = Register(char::from_digit(n, 10).unwrap());
+                    let to = Register(char::from_digit(n + 1, 10).unwrap());
+                    if let Some(v) = regs.remove(&from) {
+                        regs.insert(to, v);
+                    }
+                }
+                drop(regs);
+                self.write(&Register('1'), value)
+            }
+            r => self.write(&r, value),
+        }
+    }
+
+    // a recorded macro, this leaves the unnamed register alone
+    pub fn record(&mut self, r: &Register, keys: &str) {
+        if r.is_writable() && *r != Register::UNNAMED {
+            self.insert(r, RegisterValue::new(keys, RegisterKind::Charwise));
+        }
+    }
+
+    // write a register, the unnamed register always has the last text written
+    fn write(&mut self, r: &Register, value: RegisterValue) {
+        if !r.is_writable() {
+            return;
+        }
+        let value = self.insert(r, value);
+        self.regs.write().insert(Register::UNNAMED, value);
+    }
+
+    // uppercase names append to the lowercase register, this returns the new value
+    fn insert(&mut self, r: &Register, value: RegisterValue) -> RegisterValue {
+        let mut regs = self.regs.write();
+        let (r, value) = if r.is_append() {
+            let r = Register(r.0.to_ascii_lowercase());
+            let value = match regs.get(&r) {
+                Some(prev) => prev.append(&value),
+                None => value,
+            };
+            (r, value)
+        } else {
+            (*r, value)
+        };
+        info!("Reg[{:?}] = {:?}", r, &value);
+        regs.insert(r, value.clone());
+        value
+    }
+
+    // set one of the read-only registers
+    pub fn set_read_only(&mut self, r: &Register, s: &str) {
+        if r.is_read_only() {
+            self.regs
+                .write()
+                .insert(*r, RegisterValue::new(s, RegisterKind::Charwise));
+        }
+    }
+
+    pub fn value(&self, r: &Register) -> Option<RegisterValue> {
+        let r = Register(r.0.to_ascii_lowercase());
+        self.regs.read().get(&r).cloned()
     }
+
     pub fn get(&self, r: &Register) -> String {
-        self.regs.get(r).unwrap_or(&String::from("")).clone()
+        self.value(r).map(|v| v.text.clone()).unwrap_or_default()
+    }
+
+    // the registers that have something in them, in the order :registers shows them
+    pub fn list(&self) -> Vec<(Register, RegisterValue)> {
+        let regs = self.regs.read();
+        "\"0123456789abcdefghijklmnopqrstuvwxyz-*+.:%/"
+            .chars()
+            .filter_map(|ch| {
+                let r = Register(ch);
+                regs.get(&r).map(|v| (r, v.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RegisterKind::*;
+
+    #[test]
+    fn test_registers() {
+        let mut regs = Registers::default();
+        regs.yank(&Register::UNNAMED, RegisterValue::new("one", Charwise));
+        assert_eq!(regs.get(&Register('0')), "one");
+        assert_eq!(regs.get(&Register::UNNAMED), "one");
+
+        // small deletes go to -, and leave 0 alone
+        regs.delete(&Register::UNNAMED, RegisterValue::new("x", Charwise));
+        assert_eq!(regs.get(&Register('-')), "x");
+        assert_eq!(regs.get(&Register::UNNAMED), "x");
+        assert_eq!(regs.get(&Register('0')), "one");
+
+        // line deletes shift the numbered registers
+        regs.delete(&Register::UNNAMED, RegisterValue::new("a", Linewise));
+        regs.delete(&Register::UNNAMED, RegisterValue::new("b\nc", Charwise));
+        assert_eq!(regs.get(&Register('1')), "b\nc");
+        assert_eq!(regs.get(&Register('2')), "a\n");
+        for n in 0..10 {
+            regs.delete(
+                &Register::UNNAMED,
+                RegisterValue::new(&n.to_string(), Linewise),
+            );
+        }
+        assert_eq!(regs.get(&Register('1')), "9\n");
+        assert_eq!(regs.get(&Register('9')), "1\n");
+
+        // uppercase appends, linewise wins
+        regs.yank(&Register('a'), RegisterValue::new("one", Charwise));
+        regs.yank(&Register('A'), RegisterValue::new(" two", Charwise));
+        assert_eq!(regs.get(&Register('a')), "one two");
+        regs.yank(&Register('A'), RegisterValue::new("three", Linewise));
+        let a = regs.value(&Register('a')).unwrap();
+        assert_eq!((a.text.as_str(), a.kind), ("one two\nthree\n", Linewise));
+        assert_eq!(regs.get(&Register::UNNAMED), "one two\nthree\n");
+
+        // the blackhole and read-only registers can't be written
+        regs.delete(&Register('_'), RegisterValue::new("gone", Linewise));
+        regs.yank(&Register('%'), RegisterValue::new("nope", Charwise));
+        assert_eq!(regs.get(&Register::UNNAMED), "one two\nthree\n");
+        assert_eq!(regs.get(&Register('%')), "");
+        regs.set_read_only(&Register('%'), "a.txt");
+        assert_eq!(regs.get(&Register('%')), "a.txt");
+
+        let names = regs.list().iter().map(|(r, _)| r.0).collect::<String>();
+        assert_eq!(names, "\"0123456789a-%");
+
+        // macros don't touch the unnamed register, and clones share the registers
+        let mut shared = regs.clone();
+        shared.record(&Register('q'), "dd");
+        shared.record(&Register('Q'), "j");
+        assert_eq!(regs.get(&Register('q')), "ddj");
+        assert_eq!(regs.get(&Register::UNNAMED), "one two\nthree\n");
     }
 }
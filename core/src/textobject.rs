@@ -0,0 +1,481 @@
+use crate::word;
+use regex::Regex;
+use ropey::Rope;
+
+// text objects look this many lines either side of the cursor, not at the whole buffer
+const WINDOW_LINES: usize = 1000;
+
+lazy_static::lazy_static! {
+    static ref TAG: Regex = Regex::new(r"<(/?)([A-Za-z][\w:.-]*)[^>]*?(/?)>").unwrap();
+}
+
+/// Text objects select a range of text around the cursor, rather than moving it
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TextObject {
+    Word,
+    BigWord,
+    Sentence,
+    Paragraph,
+    Quote(char),
+    Block(char, char), // open, close
+    Tag,
+}
+
+fn is_blank(ch: char) -> bool {
+    ch == ' ' || ch == '\t'
+}
+
+// the run of chars with the same class as the char at c
+fn class_run(classes: &[u8], c: usize) -> (usize, usize) {
+    let class = classes[c];
+    let mut start = c;
+    while start > 0 && classes[start - 1] == class {
+        start -= 1;
+    }
+    let mut end = c + 1;
+    while end < classes.len() && classes[end] == class {
+        end += 1;
+    }
+    (start, end)
+}
+
+fn word_range(
+    chars: &[char],
+    c: usize,
+    keyword: &str,
+    around: bool,
+    big: bool,
+    count: usize,
+) -> (usize, usize) {
+    let classes = word::classes(&chars.iter().collect::<String>(), keyword, big);
+    let (start, mut end) = class_run(&classes, c);
+    let on_blank = is_blank(chars[c]);
+    // each count adds another word, or the whitespace between words
+    for _ in 1..count {
+        if end < chars.len() && classes[end] != word::LINE_END {
+            end = class_run(&classes, end).1;
+        }
+    }
+    if !around {
+        return (start, end);
+    }
+    if on_blank {
+        // whitespace followed by the next word
+        if end < chars.len() && classes[end] != word::LINE_END && classes[end] != word::BLANK {
+            end = class_run(&classes, end).1;
+        }
+        (start, end)
+    } else if end < chars.len() && is_blank(chars[end]) {
+        (start, class_run(&classes, end).1)
+    } else if start > 0 && is_blank(chars[start - 1]) {
+        // no trailing whitespace, so take the leading whitespace instead
+        (class_run(&classes, start - 1).0, end)
+    } else {
+        (start, end)
+    }
+}
+
+fn is_sentence_end(chars: &[char], inx: usize) -> bool {
+    matches!(chars[inx], '.' | '!' | '?')
+        && (inx + 1 >= chars.len() || chars[inx + 1].is_whitespace())
+}
+
+fn is_paragraph_break(chars: &[char], inx: usize) -> bool {
+    chars[inx] == '\n' && inx + 1 < chars.len() && chars[inx + 1] == '\n'
+}
+
+fn sentence_range(chars: &[char], c: usize, around: bool, count: usize) -> (usize, usize) {
+    // back to the end of the previous sentence, then forward past whitespace
+    let mut start = c;
+    while start > 0 && !is_sentence_end(chars, start - 1) && !is_paragraph_break(chars, start - 1) {
+        start -= 1;
+    }
+    while start < c && chars[start].is_whitespace() {
+        start += 1;
+    }
+
+    let mut end = start;
+    for _ in 0..count {
+        while end < chars.len() && chars[end].is_whitespace() && !is_paragraph_break(chars, end) {
+            end += 1;
+        }
+        while end < chars.len() {
+            if is_sentence_end(chars, end) {
+                end += 1;
+                break;
+            }
+            if is_paragraph_break(chars, end) {
+                break;
+            }
+            end += 1;
+        }
+    }
+
+    if around {
+        while end < chars.len() && is_blank(chars[end]) {
+            end += 1;
+        }
+    }
+    (start, end)
+}
+
+fn is_blank_line(text: &Rope, line_inx: usize) -> bool {
+    text.line(line_inx).chars().all(|ch| ch.is_whitespace())
+}
+
+fn paragraph_range(text: &Rope, c: usize, around: bool, count: usize) -> (usize, usize) {
+    let lines = text.len_lines();
+    let line_inx = text.char_to_line(c);
+    let blank = is_blank_line(text, line_inx);
+
+    let mut start = line_inx;
+    while start > 0 && is_blank_line(text, start - 1) == blank {
+        start -= 1;
+    }
+
+    // a paragraph and the blank lines that follow count as two
+    let mut end = line_inx;
+    let mut blocks = if around { count * 2 } else { count };
+    let mut current = blank;
+    while blocks > 0 && end < lines {
+        while end < lines && is_blank_line(text, end) == current {
+            end += 1;
+        }
+        current = !current;
+        blocks -= 1;
+    }
+
+    // no blank lines after the paragraph, so include the blank lines before it
+    if around && !blank && end == lines && !is_blank_line(text, lines - 1) {
+        while start > 0 && is_blank_line(text, start - 1) {
+            start -= 1;
+        }
+    }
+
+    (
+        text.line_to_char(start),
+        text.line_to_char(std::cmp::min(end, lines)),
+    )
+}
+
+fn quote_range(text: &Rope, c: usize, quote: char, around: bool) -> Option<(usize, usize)> {
+    let line_inx = text.char_to_line(c);
+    let lc0 = text.line_to_char(line_inx);
+    let line = text.line(line_inx).chars().collect::<Vec<char>>();
+    let x = c - lc0;
+
+    // quotes pair up from the start of the line, skipping escaped quotes
+    let quotes = line
+        .iter()
+        .enumerate()
+        .filter(|(inx, ch)| **ch == quote && (*inx == 0 || line[inx - 1] != '\\'))
+        .map(|(inx, _)| inx)
+        .collect::<Vec<usize>>();
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(open, close)| x >= *open && x <= *close)
+        .or_else(|| {
+            quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(open, _)| *open > x)
+        })?;
+
+    if !around {
+        return Some((lc0 + open + 1, lc0 + close));
+    }
+    let mut start = open;
+    let mut end = close + 1;
+    if end < line.len() && is_blank(line[end]) {
+        while end < line.len() && is_blank(line[end]) {
+            end += 1;
+        }
+    } else {
+        while start > 0 && is_blank(line[start - 1]) {
+            start -= 1;
+        }
+    }
+    Some((lc0 + start, lc0 + end))
+}
+
+// the closest open bracket before inx that isn't closed before inx
+fn unmatched_open(chars: &[char], inx: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..inx).rev() {
+        if chars[i] == close {
+            depth += 1;
+        } else if chars[i] == open {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+fn block_range(
+    chars: &[char],
+    c: usize,
+    open: char,
+    close: char,
+    around: bool,
+    count: usize,
+) -> Option<(usize, usize)> {
+    let mut start = if chars[c] == open {
+        c
+    } else {
+        unmatched_open(chars, c, open, close)?
+    };
+    for _ in 1..count {
+        start = unmatched_open(chars, start, open, close)?;
+    }
+
+    let mut end = start + 1;
+    let mut depth = 1;
+    while end < chars.len() {
+        if chars[end] == open {
+            depth += 1;
+        } else if chars[end] == close {
+            depth -= 1;
+            if depth == 0 {
+                break;
+            }
+        }
+        end += 1;
+    }
+    if end >= chars.len() {
+        return None;
+    }
+
+    if around {
+        return Some((start, end + 1));
+    }
+
+    // a block that spans lines keeps the brackets on their own lines
+    let mut inner_start = start + 1;
+    let mut inner_end = end;
+    if chars.get(inner_start) == Some(&'\n') {
+        let mut line_start = end;
+        while line_start > inner_start && is_blank(chars[line_start - 1]) {
+            line_start -= 1;
+        }
+        if line_start > inner_start && chars[line_start - 1] == '\n' {
+            inner_start += 1;
+            inner_end = line_start;
+        }
+    }
+    Some((inner_start, std::cmp::max(inner_start, inner_end)))
+}
+
+fn tag_range(text: &str, c: usize, around: bool, count: usize) -> Option<(usize, usize)> {
+    // matches come in order, so count chars from the last one
+    let mut last = (0, 0);
+    let mut char_inx = |byte: usize| {
+        last = (byte, last.1 + text[last.0..byte].chars().count());
+        last.1
+    };
+
+    // pair up open and close tags, as (open start, open end, close start, close end)
+    let mut stack: Vec<(String, usize, usize)> = vec![];
+    let mut pairs = vec![];
+    for cap in TAG.captures_iter(text) {
+        let m = cap.get(0).unwrap();
+        let name = cap[2].to_string();
+        if !cap[3].is_empty() {
+            continue;
+        }
+        if cap[1].is_empty() {
+            stack.push((name, char_inx(m.start()), char_inx(m.end())));
+        } else if let Some(pos) = stack.iter().rposition(|(open, _, _)| *open == name) {
+            let (_, s0, s1) = stack[pos].clone();
+            stack.truncate(pos);
+            pairs.push((s0, s1, char_inx(m.start()), char_inx(m.end())));
+        }
+    }
+
+    // the tags containing the cursor, innermost first
+    let mut containing = pairs
+        .into_iter()
+        .filter(|(s0, _, _, e1)| c >= *s0 && c < *e1)
+        .collect::<Vec<_>>();
+    containing.sort_by_key(|(s0, _, _, e1)| e1 - s0);
+    let (s0, s1, e0, e1) = *containing.get(count - 1)?;
+    if around {
+        Some((s0, e1))
+    } else {
+        Some((s1, e0))
+    }
+}
+
+// the char range of a text object at char position c, or None if there isn't one, keyword lists
+// the chars besides letters, digits and _ that are part of a word
+pub fn text_object_range(
+    text: &Rope,
+    c: usize,
+    obj: &TextObject,
+    keyword: &str,
+    around: bool,
+    count: usize,
+) -> Option<(usize, usize)> {
+    let count = std::cmp::max(1, count);
+    if text.len_chars() == 0 {
+        return None;
+    }
+    let c = std::cmp::min(c, text.len_chars() - 1);
+    use TextObject::*;
+    match obj {
+        Paragraph => Some(paragraph_range(text, c, around, count)),
+        Quote(quote) => quote_range(text, c, *quote, around),
+        _ => {
+            let line_inx = text.char_to_line(c);
+            let w0 = text.line_to_char(line_inx.saturating_sub(WINDOW_LINES));
+            let w1 =
+                text.line_to_char(std::cmp::min(line_inx + WINDOW_LINES + 1, text.len_lines()));
+            let window = text.slice(w0..w1);
+            let c = c - w0;
+            let range = match obj {
+                Tag => tag_range(&window.to_string(), c, around, count),
+                _ => {
+                    let chars = window.chars().collect::<Vec<char>>();
+                    match obj {
+                        Word => Some(word_range(&chars, c, keyword, around, false, count)),
+                        BigWord => Some(word_range(&chars, c, keyword, around, true, count)),
+                        Sentence => Some(sentence_range(&chars, c, around, count)),
+                        Block(open, close) => block_range(&chars, c, *open, *close, around, count),
+                        _ => None,
+                    }
+                }
+            };
+            range.map(|(start, end)| (w0 + start, w0 + end))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(s: &str, c: usize, obj: TextObject, around: bool, count: usize) -> Option<String> {
+        let text = Rope::from_str(s);
+        text_object_range(&text, c, &obj, "", around, count)
+            .map(|(start, end)| text.slice(start..end).to_string())
+    }
+
+    #[test]
+    fn test_word() {
+        use TextObject::*;
+        let s = "let foo.bar = 1;\n";
+        assert_eq!(select(s, 5, Word, false, 1).unwrap(), "foo");
+        // no whitespace after the word, so the whitespace before it is included
+        assert_eq!(select(s, 5, Word, true, 1).unwrap(), " foo");
+        assert_eq!(select(s, 1, Word, true, 1).unwrap(), "let ");
+        assert_eq!(select(s, 5, BigWord, false, 1).unwrap(), "foo.bar");
+        assert_eq!(select(s, 5, BigWord, true, 1).unwrap(), "foo.bar ");
+        assert_eq!(select(s, 9, Word, true, 1).unwrap(), "bar ");
+        assert_eq!(select(s, 9, Word, false, 3).unwrap(), "bar =");
+        assert_eq!(select(s, 3, Word, true, 1).unwrap(), " foo");
+
+        // the keyword chars of the filetype are part of a word
+        let text = Rope::from_str("(foo-bar baz)\n");
+        let range = text_object_range(&text, 2, &Word, "-?!", false, 1);
+        assert_eq!(range, Some((1, 8)));
+    }
+
+    #[test]
+    fn test_sentence() {
+        use TextObject::*;
+        let s = "One two. Three four! Five\n\nSix.\n";
+        assert_eq!(select(s, 12, Sentence, false, 1).unwrap(), "Three four!");
+        assert_eq!(select(s, 12, Sentence, true, 1).unwrap(), "Three four! ");
+        assert_eq!(
+            select(s, 2, Sentence, false, 2).unwrap(),
+            "One two. Three four!"
+        );
+        assert_eq!(select(s, 22, Sentence, false, 1).unwrap(), "Five");
+    }
+
+    #[test]
+    fn test_paragraph() {
+        use TextObject::*;
+        let s = "a\nb\n\n\nc\nd\n";
+        assert_eq!(select(s, 2, Paragraph, false, 1).unwrap(), "a\nb\n");
+        assert_eq!(select(s, 2, Paragraph, true, 1).unwrap(), "a\nb\n\n\n");
+        assert_eq!(select(s, 6, Paragraph, false, 1).unwrap(), "c\nd\n");
+        assert_eq!(select(s, 4, Paragraph, false, 1).unwrap(), "\n\n");
+    }
+
+    #[test]
+    fn test_quote() {
+        use TextObject::*;
+        let s = "x = \"a \\\"b\\\"\" + 'c' ;\n";
+        assert_eq!(select(s, 6, Quote('"'), false, 1).unwrap(), "a \\\"b\\\"");
+        assert_eq!(select(s, 0, Quote('\''), false, 1).unwrap(), "c");
+        assert_eq!(select(s, 17, Quote('\''), true, 1).unwrap(), "'c' ");
+    }
+
+    #[test]
+    fn test_block() {
+        use TextObject::*;
+        let s = "f(a, (b), c)\n";
+        assert_eq!(
+            select(s, 3, Block('(', ')'), false, 1).unwrap(),
+            "a, (b), c"
+        );
+        assert_eq!(select(s, 6, Block('(', ')'), true, 1).unwrap(), "(b)");
+        assert_eq!(
+            select(s, 6, Block('(', ')'), true, 2).unwrap(),
+            "(a, (b), c)"
+        );
+        assert_eq!(
+            select(s, 1, Block('(', ')'), false, 1).unwrap(),
+            "a, (b), c"
+        );
+        assert_eq!(
+            select(s, 11, Block('(', ')'), false, 1).unwrap(),
+            "a, (b), c"
+        );
+        assert_eq!(select(s, 0, Block('(', ')'), false, 1), None);
+
+        let s = "fn x() {\n    a;\n}\n";
+        assert_eq!(
+            select(s, 13, Block('{', '}'), false, 1).unwrap(),
+            "    a;\n"
+        );
+        assert_eq!(
+            select(s, 13, Block('{', '}'), true, 1).unwrap(),
+            "{\n    a;\n}"
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        use TextObject::*;
+        // only the lines near the cursor are searched
+        let s = format!(
+            "({}a\n{})\n",
+            "\n".repeat(WINDOW_LINES + 1),
+            "\n".repeat(10)
+        );
+        let c = WINDOW_LINES + 2;
+        assert_eq!(select(&s, c, Block('(', ')'), false, 1), None);
+        let s = format!("x\n{}(a\n)\n", "\n".repeat(WINDOW_LINES + 1));
+        let c = WINDOW_LINES + 4;
+        assert_eq!(select(&s, c, Block('(', ')'), false, 1).unwrap(), "a\n");
+        assert_eq!(select(&s, c, Word, false, 1).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_tag() {
+        use TextObject::*;
+        let s = "<div><b>x</b> <br/>y</div>\n";
+        assert_eq!(select(s, 8, Tag, false, 1).unwrap(), "x");
+        assert_eq!(select(s, 8, Tag, true, 1).unwrap(), "<b>x</b>");
+        assert_eq!(select(s, 8, Tag, false, 2).unwrap(), "<b>x</b> <br/>y");
+        assert_eq!(
+            select(s, 20, Tag, true, 1).unwrap(),
+            "<div><b>x</b> <br/>y</div>"
+        );
+    }
+}
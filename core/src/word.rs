@@ -0,0 +1,260 @@
+use crate::grapheme_iter::ensure_grapheme_boundary_prev;
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+// classes for word motions, a word is a run of chars with the same class
+pub const LINE_END: u8 = 0;
+pub const BLANK: u8 = 1;
+pub const PUNCTUATION: u8 = 2;
+// letters and digits switch between these two classes at a UAX #29 word boundary, so "漢字" is two
+// words, while the punctuation in "can't" still splits it
+pub const KEYWORD: u8 = 3;
+pub const KEYWORD_NEXT: u8 = 4;
+
+// the class of each char in s, keyword lists the chars besides letters, digits and _ that are part
+// of a word, for big words everything that isn't blank is the same class
+pub fn classes(s: &str, keyword: &str, big: bool) -> Vec<u8> {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let mut out = Vec::with_capacity(s.len());
+    let mut word = KEYWORD;
+    let mut after_word = false;
+    for (_, segment) in s.split_word_bound_indices() {
+        let starts_word = segment.chars().next().is_some_and(is_word);
+        if starts_word && after_word {
+            word = if word == KEYWORD {
+                KEYWORD_NEXT
+            } else {
+                KEYWORD
+            };
+        }
+        after_word = starts_word;
+        // combining marks and joiners take the class of the char they follow
+        for g in segment.graphemes(true) {
+            let ch = g.chars().next().unwrap();
+            let class = if ch == '\n' || ch == '\r' {
+                LINE_END
+            } else if ch.is_whitespace() {
+                BLANK
+            } else if big {
+                KEYWORD
+            } else if is_word(ch) || keyword.contains(ch) {
+                word
+            } else {
+                PUNCTUATION
+            };
+            out.extend(std::iter::repeat_n(class, g.chars().count()));
+        }
+    }
+    out
+}
+
+// the classes of the chars on a line of the text, worked out again when a motion leaves the line
+struct Classes<'a> {
+    text: &'a Rope,
+    keyword: &'a str,
+    big: bool,
+    start: usize,
+    classes: Vec<u8>,
+}
+
+impl<'a> Classes<'a> {
+    fn new(text: &'a Rope, keyword: &'a str, big: bool) -> Self {
+        Self {
+            text,
+            keyword,
+            big,
+            start: 0,
+            classes: vec![],
+        }
+    }
+
+    fn at(&mut self, c: usize) -> u8 {
+        if c < self.start || c >= self.start + self.classes.len() {
+            let line_inx = self.text.char_to_line(c);
+            self.start = self.text.line_to_char(line_inx);
+            let line = self.text.line(line_inx).to_string();
+            self.classes = classes(&line, self.keyword, self.big);
+        }
+        self.classes[c - self.start]
+    }
+}
+
+fn is_space(class: u8) -> bool {
+    class == BLANK || class == LINE_END
+}
+
+// an empty line stops word motions, as if it were a word
+fn is_empty_line(text: &Rope, c: usize) -> bool {
+    text.char(c) == '\n' && (c == 0 || text.char(c - 1) == '\n')
+}
+
+fn last_char(text: &Rope) -> usize {
+    text.len_chars().saturating_sub(1)
+}
+
+// w and W, the start of the next word
+pub fn word_forward(text: &Rope, c: usize, count: usize, keyword: &str, big: bool) -> usize {
+    if text.len_chars() == 0 {
+        return 0;
+    }
+    let last = last_char(text);
+    let mut c = std::cmp::min(c, last);
+    let mut classes = Classes::new(text, keyword, big);
+    for _ in 0..count {
+        let class = classes.at(c);
+        if !is_space(class) {
+            while c < last && classes.at(c + 1) == class {
+                c += 1;
+            }
+        }
+        if c < last {
+            c += 1;
+        }
+        while c < last && is_space(classes.at(c)) && !is_empty_line(text, c) {
+            c += 1;
+        }
+    }
+    c
+}
+
+// b and B, the start of this word or the one before it
+pub fn word_backward(text: &Rope, c: usize, count: usize, keyword: &str, big: bool) -> usize {
+    if text.len_chars() == 0 {
+        return 0;
+    }
+    let mut c = std::cmp::min(c, last_char(text));
+    let mut classes = Classes::new(text, keyword, big);
+    for _ in 0..count {
+        if c == 0 {
+            break;
+        }
+        c -= 1;
+        while c > 0 && is_space(classes.at(c)) && !is_empty_line(text, c) {
+            c -= 1;
+        }
+        let class = classes.at(c);
+        if !is_space(class) {
+            while c > 0 && classes.at(c - 1) == class {
+                c -= 1;
+            }
+        }
+    }
+    c
+}
+
+// e and E, the end of this word or the one after it
+pub fn word_end_forward(text: &Rope, c: usize, count: usize, keyword: &str, big: bool) -> usize {
+    if text.len_chars() == 0 {
+        return 0;
+    }
+    let last = last_char(text);
+    let mut c = std::cmp::min(c, last);
+    let mut classes = Classes::new(text, keyword, big);
+    for _ in 0..count {
+        if c >= last {
+            break;
+        }
+        c += 1;
+        while c < last && is_space(classes.at(c)) {
+            c += 1;
+        }
+        let class = classes.at(c);
+        while c < last && classes.at(c + 1) == class {
+            c += 1;
+        }
+    }
+    c
+}
+
+// ge and gE, the end of the word before this one
+pub fn word_end_backward(text: &Rope, c: usize, count: usize, keyword: &str, big: bool) -> usize {
+    if text.len_chars() == 0 {
+        return 0;
+    }
+    let mut c = std::cmp::min(c, last_char(text));
+    let mut classes = Classes::new(text, keyword, big);
+    for _ in 0..count {
+        let class = classes.at(c);
+        if !is_space(class) {
+            while c > 0 && classes.at(c - 1) == class {
+                c -= 1;
+            }
+        }
+        if c == 0 {
+            break;
+        }
+        c -= 1;
+        while c > 0 && is_space(classes.at(c)) && !is_empty_line(text, c) {
+            c -= 1;
+        }
+        // land on the first char of a grapheme, not a combining mark
+        c = ensure_grapheme_boundary_prev(text.slice(..), c);
+    }
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words<F>(text: &Rope, mut c: usize, f: F) -> Vec<usize>
+    where
+        F: Fn(&Rope, usize) -> usize,
+    {
+        let mut out = vec![];
+        loop {
+            let next = f(text, c);
+            if next == c {
+                return out;
+            }
+            out.push(next);
+            c = next;
+        }
+    }
+
+    #[test]
+    fn test_word_motions() {
+        let text = Rope::from("foo.bar baz\n\n  qux\n");
+        let w = words(&text, 0, |t, c| word_forward(t, c, 1, "", false));
+        assert_eq!(w, vec![3, 4, 8, 12, 15, 18]);
+        let b = words(&text, 15, |t, c| word_backward(t, c, 1, "", false));
+        assert_eq!(b, vec![12, 8, 4, 3, 0]);
+        let e = words(&text, 0, |t, c| word_end_forward(t, c, 1, "", false));
+        assert_eq!(e, vec![2, 3, 6, 10, 17, 18]);
+        let ge = words(&text, 15, |t, c| word_end_backward(t, c, 1, "", false));
+        assert_eq!(ge, vec![12, 10, 6, 3, 2, 0]);
+
+        // big words only break on whitespace
+        assert_eq!(word_forward(&text, 0, 1, "", true), 8);
+        assert_eq!(word_end_forward(&text, 0, 1, "", true), 6);
+        assert_eq!(word_end_backward(&text, 10, 1, "", true), 6);
+        assert_eq!(word_forward(&text, 0, 3, "", false), 8);
+    }
+
+    #[test]
+    fn test_word_classes() {
+        // accented letters are part of the word, combining marks stay with their letter
+        let text = Rope::from("café nai\u{308}ve x\n");
+        assert_eq!(word_forward(&text, 0, 1, "", false), 5);
+        assert_eq!(word_end_forward(&text, 5, 1, "", false), 10);
+
+        // scripts without spaces break at the UAX #29 word boundaries, punctuation still splits
+        let text = Rope::from("漢字かなカナabc\n");
+        let w = words(&text, 0, |t, c| word_forward(t, c, 1, "", false));
+        assert_eq!(w, vec![1, 2, 3, 4, 6, 9]);
+        let text = Rope::from("can't 3.14\n");
+        let w = words(&text, 0, |t, c| word_forward(t, c, 1, "", false));
+        assert_eq!(w, vec![3, 4, 6, 7, 8, 10]);
+
+        // an emoji with a modifier is a single word
+        let text = Rope::from("a \u{1F44D}\u{1F3FD}\u{1F44D} b\n");
+        assert_eq!(word_forward(&text, 0, 1, "", false), 2);
+        assert_eq!(word_end_forward(&text, 2, 1, "", false), 4);
+        assert_eq!(word_forward(&text, 2, 1, "", false), 6);
+
+        // keyword chars join words
+        let text = Rope::from("foo-bar baz\n");
+        assert_eq!(word_forward(&text, 0, 1, "", false), 3);
+        assert_eq!(word_forward(&text, 0, 1, "-", false), 8);
+    }
+}
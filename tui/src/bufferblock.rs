@@ -1,9 +1,23 @@
 use crate::lineworker::LineWorker;
+use crate::visual::{column, column_char, line_selection, text_width, Selection};
 use crate::*;
+use editor_core::ex;
+use editor_core::indent;
+use editor_core::marks;
+use editor_core::motion::{
+    match_bracket, paragraph_backward, paragraph_forward, sentence_backward, sentence_forward,
+};
+use editor_core::number;
+use editor_core::textobject::text_object_range;
 use editor_core::Buffer;
-use editor_core::{BufferConfig, Command, Motion, ViewPos};
+use editor_core::{
+    nth_next_grapheme_boundary, nth_prev_grapheme_boundary, BufferConfig, Case, Command,
+    IndentStyle, LineOp, Mode, Motion, RegisterKind, RegisterValue, ViewPos,
+};
 use log::*;
 use ropey::Rope;
+use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Debug, Clone)]
 pub struct BufferBlock {
@@ -18,6 +32,12 @@ pub struct BufferBlock {
     pub cache_render_rows: Vec<Cursor>,
     search_results: SearchResults,
     is_focused: bool,
+    visual: Option<Selection>,
+    last_visual: Option<Selection>,
+    mode: Mode,
+    pub jumps: JumpList,
+    change_pos: Option<(usize, usize)>, // length of the change list and the position in it
+    replaced: Vec<(usize, String)>, // in replace mode, the chars typed and the text they overwrote
 }
 
 impl BufferBlock {
@@ -39,6 +59,12 @@ impl BufferBlock {
             rc: RenderCursor::default(),
             buf,
             is_focused: false,
+            visual: None,
+            last_visual: None,
+            mode: Mode::Normal,
+            jumps: JumpList::default(),
+            change_pos: None,
+            replaced: vec![],
         }
     }
 }
@@ -62,6 +88,18 @@ impl BufferBlock {
         self
     }
 
+    // change an option for :set, the cursors cache the config so they are refreshed
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let mut config = self.buf.get_config();
+        config.set_option(name, value)?;
+        self.buf.set_config(config.clone());
+        let text = self.buf.get_text();
+        let sx = self.view.w;
+        self.start = cursor_from_char(&text, sx, &config, self.start.c, self.start.x_hint);
+        self.cursor = cursor_from_char(&text, sx, &config, self.cursor.c, self.cursor.x_hint);
+        Ok(())
+    }
+
     pub fn get_path(&self) -> String {
         self.buf.get_path()
     }
@@ -82,25 +120,41 @@ impl BufferBlock {
         self
     }
 
+    // keep the cursor on the rows shown, outside of the scrolloff margins, moving it if needed
     pub fn locate_cursor_pos_in_window(&self, rows: &Vec<Cursor>) -> (u16, u16, Cursor) {
+        let text = self.buf.get_text();
+        let sx = self.view.w;
         let end = rows.len() - 1;
-        if self.cursor < rows[0] {
-            (0, 0, rows[0].clone())
-        } else if self.cursor.c >= rows[end].lc1 {
-            (0, end as u16, rows[end].clone())
+        let scrolloff = std::cmp::min(self.buf.get_config().scrolloff, end / 2);
+        // the margins don't apply at the start and end of the buffer
+        let top = if rows[0].line_inx == 0 && rows[0].wrap0 == 0 {
+            0
         } else {
-            let (rx, mut ry) = (0, 0);
-            (0..rows.len()).for_each(|i| {
-                if self.cursor.line_inx == rows[i].line_inx && self.cursor.wrap0 == rows[i].wrap0 {
-                    ry = i;
-                }
-            });
-            (
-                rx + self.block.view.x0 as u16,
-                (ry + self.block.view.y0) as u16,
-                rows[ry].clone(),
-            )
-        }
+            scrolloff
+        };
+        let bottom = match cursor_visual_next_line(&text, sx, &rows[end]) {
+            Some(_) => end - scrolloff,
+            None => end,
+        };
+        let found = rows
+            .iter()
+            .position(|row| row.line_inx == self.cursor.line_inx && row.wrap0 == self.cursor.wrap0);
+        let ry = match found {
+            Some(ry) => ry.clamp(top, bottom),
+            None if self.cursor < rows[0] => top,
+            None => bottom,
+        };
+        let cursor = if found == Some(ry) {
+            self.cursor.clone()
+        } else {
+            let row = &rows[ry];
+            cursor_to_line_relative(&text, sx, row, row.wrap0, self.cursor.x_hint)
+        };
+        (
+            (cursor.rx(sx) + self.block.view.x0) as u16,
+            (ry + self.block.view.y0) as u16,
+            cursor,
+        )
     }
 
     pub fn update(&mut self) -> &mut Self {
@@ -112,12 +166,13 @@ impl BufferBlock {
         self.cursor = cursor_update(&text, self.view.w, &self.cursor);
 
         // render the view, so we know how long the line is on screen
-        let (cx, cy, row_cursors) = LineWorker::screen_from_cursor(
+        let (cx, cy, row_cursors) = LineWorker::screen_from_cursor_scrolloff(
             &text,
             self.view.w,
             self.view.h,
             &self.start,
             &self.cursor,
+            config.scrolloff,
         );
         // update start based on render
         debug!("buffer update: {:?}", (cx, cy, row_cursors.len()));
@@ -130,6 +185,10 @@ impl BufferBlock {
         );
 
         // generate updates
+        let selection = self
+            .selection()
+            .map(|s| s.ranges(&text, &config))
+            .unwrap_or_default();
         let mut updates = row_cursors
             .iter()
             .map(|r| {
@@ -137,6 +196,7 @@ impl BufferBlock {
                     &config,
                     self.view.w,
                     self.block.highlight.clone(),
+                    line_selection(&text, r.line_inx, &selection),
                 ))
             })
             .collect::<Vec<RowUpdate>>();
@@ -262,6 +322,195 @@ impl BufferBlock {
         self
     }
 
+    // typing in insert mode, enter indents the new line and a closing bracket typed on a line
+    // with only an indent before it goes back a level
+    pub fn insert_indented(&mut self, s: &str) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let c = self.cursor.c;
+        let line_inx = text.char_to_line(c);
+        let lc0 = text.line_to_char(line_inx);
+        let before = text.slice(lc0..c).to_string();
+        match s {
+            "\n" => {
+                let after = text.slice(c..line_end(&text, line_inx)).to_string();
+                let indent = indent::new_line_indent(&before, &after, &config);
+                let len = self
+                    .buf
+                    .replace_range(c, c, &(config.line_sep().to_string() + &indent));
+                self.move_to_char(c + len)
+            }
+            ")" | "]" | "}" if config.smartindent && before.trim().is_empty() => {
+                let above = (0..line_inx)
+                    .rev()
+                    .map(|line_inx| text.line(line_inx).to_string())
+                    .find(|line| !line.trim().is_empty());
+                let width = indent::indent_for(above.as_deref(), s, &config);
+                if width < config.leading_indent(&before).0 {
+                    let len = self.buf.replace_range(lc0, c, &config.indent_to(width));
+                    self.move_to_char(lc0 + len);
+                }
+                self.insert_string(s)
+            }
+            _ => self.insert_string(s),
+        }
+    }
+
+    // backspace in insert mode, in an indent made of spaces it goes back to the previous level
+    pub fn backspace(&mut self) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let c = self.cursor.c;
+        let before = text
+            .slice(text.line_to_char(text.char_to_line(c))..c)
+            .to_string();
+        let spaces = before.chars().count();
+        match config.indent_style {
+            IndentStyle::Space if spaces > 0 && before.chars().all(|ch| ch == ' ') => {
+                let width = std::cmp::max(1, config.indent_width());
+                self.remove_range(-(((spaces - 1) % width + 1) as i32))
+            }
+            _ => self.remove_range(-1),
+        }
+    }
+
+    // o and O, the new line gets the indent enter would give it
+    pub fn open_line(&mut self, below: bool) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let line_inx = text.char_to_line(self.cursor.c);
+        let lc0 = text.line_to_char(line_inx);
+        let eol = line_end(&text, line_inx);
+        let line = text.slice(lc0..eol).to_string();
+        let sep = config.line_sep();
+        if below {
+            let indent = indent::new_line_indent(&line, "", &config);
+            let len = self
+                .buf
+                .replace_range(eol, eol, &(sep.to_string() + &indent));
+            self.move_to_char(eol + len)
+        } else {
+            let indent = indent::above_line_indent(&line, &config);
+            self.buf.replace_range(lc0, lc0, &(indent.clone() + sep));
+            self.move_to_char(lc0 + indent.chars().count())
+        }
+    }
+
+    // replace mode, each grapheme typed overwrites the one under the cursor,
+    // line endings are never overwritten, new lines are inserted
+    pub fn overwrite(&mut self, s: &str) -> &mut Self {
+        let config = self.buf.get_config();
+        let s = s.replace('\t', &config.indent());
+        for g in s.graphemes(true) {
+            let text = self.buf.get_text();
+            let c = self.cursor.c;
+            let end = match text.get_char(c) {
+                Some(ch) if ch != '\n' && ch != '\r' && g != "\n" => {
+                    nth_next_grapheme_boundary(text.slice(..), c, 1)
+                }
+                _ => c,
+            };
+            let g = match g {
+                "\n" => config.line_sep(),
+                _ => g,
+            };
+            let original = text.slice(c..end).to_string();
+            let len = self.buf.replace_range(c, end, g);
+            self.replaced.push((len, original));
+            self.move_to_char(c + len);
+        }
+        self
+    }
+
+    // backspace in replace mode puts back what the last char typed overwrote,
+    // before the replace started it only moves the cursor
+    pub fn overwrite_back(&mut self) -> &mut Self {
+        let c = self.cursor.c;
+        match self.replaced.pop() {
+            Some((len, original)) => {
+                let start = c.saturating_sub(len);
+                self.buf.replace_range(start, c, &original);
+                self.move_to_char(start);
+            }
+            None => {
+                let text = self.buf.get_text();
+                let start = nth_prev_grapheme_boundary(text.slice(..), c, 1);
+                if !text.slice(start..c).to_string().ends_with('\n') {
+                    self.move_to_char(start);
+                }
+            }
+        }
+        self
+    }
+
+    // r and gr, replace reps graphemes with ch, leaving the cursor on the last one,
+    // nothing changes if the line is too short, and a new line replaces them all with one,
+    // gr replaces screen columns, the rest of a tab becomes spaces so the text after it stays put
+    pub fn replace_chars(&mut self, reps: usize, ch: char, virtual_cols: bool) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let c = self.cursor.c;
+        let eol = line_end(&text, text.char_to_line(c));
+        let mut end = c;
+        for _ in 0..reps {
+            if end >= eol {
+                if virtual_cols {
+                    break;
+                }
+                return self;
+            }
+            end = nth_next_grapheme_boundary(text.slice(..), end, 1);
+        }
+        // gr on an empty line
+        if end == c {
+            return self;
+        }
+        if ch == '\n' {
+            let len = self.buf.replace_range(c, end, config.line_sep());
+            self.move_to_char(c + len);
+            return self;
+        }
+        let s = ch.to_string();
+        if !virtual_cols {
+            let len = self.buf.replace_range(c, end, &s.repeat(reps));
+            self.move_to_char(c + len - 1);
+            return self;
+        }
+        let mut pos = c;
+        for _ in 0..reps {
+            let text = self.buf.get_text();
+            if pos >= line_end(&text, text.char_to_line(pos)) {
+                break;
+            }
+            let (col0, col1) = column(&text, &config, pos);
+            let next = nth_next_grapheme_boundary(text.slice(..), pos, 1);
+            let replacement = match text.char(pos) {
+                '\t' => format!("{}{}", s, " ".repeat(col1 - col0 - 1)),
+                _ => s.clone(),
+            };
+            self.buf.replace_range(pos, next, &replacement);
+            pos += s.chars().count();
+        }
+        self.move_to_char(pos - 1);
+        self
+    }
+
+    // ctrl-a and ctrl-x add to the number at or after the cursor, and leave the cursor on its
+    // last char
+    pub fn increment(&mut self, delta: i64) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let line_inx = text.char_to_line(self.cursor.c);
+        let lc0 = text.line_to_char(line_inx);
+        let line = text.line(line_inx).to_string();
+        let found = number::increment(&line, self.cursor.c - lc0, delta, &config.nrformats);
+        if let Some((start, end, s)) = found {
+            let len = self.buf.replace_range(lc0 + start, lc0 + end, &s);
+            self.move_to_char(lc0 + start + len - 1);
+        }
+        self
+    }
+
     // remove trailing newlines, to join with the next line
     pub fn join_line(&mut self) -> &mut Self {
         let text = self.buf.get_text();
@@ -278,6 +527,22 @@ impl BufferBlock {
         self
     }
 
+    // zt zz zb, move the start so the cursor row is at the top, middle or bottom of the view
+    pub fn scroll_cursor(&mut self, m: &Motion) -> &mut Self {
+        let text = self.get_text();
+        let sx = self.view.w;
+        let h = self.view.h;
+        let scrolloff = std::cmp::min(self.buf.get_config().scrolloff, h.saturating_sub(1) / 2);
+        let row = cursor_to_line_relative(&text, sx, &self.cursor, self.cursor.wrap0, 0);
+        let dy = match m {
+            Motion::ScreenTop => scrolloff,
+            Motion::ScreenBottom => h.saturating_sub(1) - scrolloff,
+            _ => h.saturating_sub(1) / 2,
+        };
+        self.start = cursor_move_to_y(&text, sx, &row, -(dy as i32));
+        self
+    }
+
     pub fn cursor_from_xy(&self, mx: usize, my: usize) -> Option<Cursor> {
         let x0 = self.block.view.x0;
         let y0 = self.block.view.y0;
@@ -315,10 +580,104 @@ impl BufferBlock {
         self
     }
 
-    pub fn motion_slice(&mut self, m: &Motion) -> String {
-        let (c1, c2) = self.cursor_motion(m, 1);
-        let r = if c1.c > c2.c { c2.c..c1.c } else { c1.c..c2.c };
-        self.buf.get_text().slice(r).to_string()
+    // the text a motion covers, for a register, with how it was taken
+    pub fn motion_value(&self, m: &Motion, repeat: usize) -> RegisterValue {
+        let text = self.buf.get_text();
+        match self.selection() {
+            Some(s) if *m == Motion::Selection => {
+                let slices = s
+                    .ranges(&text, &self.buf.get_config())
+                    .iter()
+                    .map(|(start, end)| text.slice(*start..*end).to_string())
+                    .collect::<Vec<String>>();
+                match s.mode {
+                    // each line of a block is yanked on its own line
+                    Mode::VisualBlock => {
+                        RegisterValue::new(&slices.join("\n"), RegisterKind::Blockwise)
+                    }
+                    Mode::VisualLine => {
+                        RegisterValue::new(&slices.concat(), RegisterKind::Linewise)
+                    }
+                    _ => RegisterValue::new(&slices.concat(), RegisterKind::Charwise),
+                }
+            }
+            _ => {
+                let (start, end) = self.motion_range(m, repeat);
+                if start == end {
+                    return RegisterValue::default();
+                }
+                let kind = if *m == Motion::Line || m.is_linewise() {
+                    RegisterKind::Linewise
+                } else {
+                    RegisterKind::Charwise
+                };
+                RegisterValue::new(&text.slice(start..end).to_string(), kind)
+            }
+        }
+    }
+
+    // the char ranges an operator acts on, blockwise selections have one for each line
+    fn motion_ranges(&self, m: &Motion, repeat: usize) -> Vec<(usize, usize)> {
+        match self.selection() {
+            Some(s) if *m == Motion::Selection => {
+                s.ranges(&self.buf.get_text(), &self.buf.get_config())
+            }
+            _ => vec![self.motion_range(m, repeat)],
+        }
+    }
+
+    // the char range covered by a motion or text object, start is always before end
+    pub fn motion_range(&self, m: &Motion, repeat: usize) -> (usize, usize) {
+        let text = self.buf.get_text();
+        let c = self.cursor.c;
+        let keyword = self.buf.get_config().keyword_chars;
+        match m {
+            Motion::Inner(obj) => {
+                text_object_range(&text, c, obj, &keyword, false, repeat).unwrap_or((c, c))
+            }
+            Motion::Around(obj) => {
+                text_object_range(&text, c, obj, &keyword, true, repeat).unwrap_or((c, c))
+            }
+            Motion::Selection => {
+                let ranges = self
+                    .selection()
+                    .map(|s| s.ranges(&text, &self.buf.get_config()))
+                    .unwrap_or_default();
+                match (ranges.first(), ranges.last()) {
+                    (Some((start, _)), Some((_, end))) => (*start, *end),
+                    _ => (c, c),
+                }
+            }
+            m if m.is_linewise() => {
+                let (c1, c2) = self.cursor_motion(m, repeat);
+                // j and k fail at the ends of the buffer rather than taking the current line
+                let failed = match m {
+                    Motion::Up | Motion::Down => c1.line_inx == c2.line_inx,
+                    Motion::MarkLine(ch) => self.mark_position(*ch).is_none(),
+                    _ => false,
+                };
+                if failed {
+                    return (c, c);
+                }
+                let line0 = std::cmp::min(c1.line_inx, c2.line_inx);
+                let line1 = std::cmp::max(c1.line_inx, c2.line_inx) + 1;
+                let end = if line1 < text.len_lines() {
+                    text.line_to_char(line1)
+                } else {
+                    text.len_chars()
+                };
+                (text.line_to_char(line0), end)
+            }
+            _ => {
+                let (c1, c2) = self.cursor_motion(m, repeat);
+                let (start, end) = (std::cmp::min(c1.c, c2.c), std::cmp::max(c1.c, c2.c));
+                if m.is_inclusive() && start != end && end < text.len_chars() {
+                    (start, nth_next_grapheme_boundary(text.slice(..), end, 1))
+                } else {
+                    (start, end)
+                }
+            }
+        }
     }
 
     pub fn cursor_motion(&self, m: &Motion, repeat: usize) -> (Cursor, Cursor) {
@@ -330,6 +689,7 @@ impl BufferBlock {
         let c1 = cursor.clone();
         let c2 = cursor.clone();
         let config = c1.config.clone();
+        let to_char = |c| cursor_from_char(&text, sx, &config, c, 0).save_x_hint(sx);
         match m {
             OnCursor => (c1, c2),
             AfterCursor => (c1, cursor_move_to_x(&text, sx, cursor, 1)),
@@ -350,22 +710,67 @@ impl BufferBlock {
             SOLT => (c1, cursor_move_to_lc(&text, sx, cursor, 0)),
             Left => (c1, cursor_move_to_x(&text, sx, cursor, -r)),
             Right => (c1, cursor_move_to_x(&text, sx, cursor, r)),
-            Up => (c1, cursor_move_to_y(&text, sx, cursor, -r)),
-            Down => (c1, cursor_move_to_y(&text, sx, cursor, r)),
+            Up => (c1, cursor_move_to_line(&text, sx, cursor, -r)),
+            Down => (c1, cursor_move_to_line(&text, sx, cursor, r)),
+            DisplayUp => (c1, cursor_move_to_y(&text, sx, cursor, -r)),
+            DisplayDown => (c1, cursor_move_to_y(&text, sx, cursor, r)),
             BackWord1 => (c1, cursor_move_to_word(&text, sx, cursor, -r, false)),
             BackWord2 => (c1, cursor_move_to_word(&text, sx, cursor, -r, true)),
             ForwardWord1 => (c1, cursor_move_to_word(&text, sx, cursor, r, false)),
             ForwardWord2 => (c1, cursor_move_to_word(&text, sx, cursor, r, true)),
-            ForwardWordEnd1 => (c1, cursor_move_to_word(&text, sx, cursor, r, false)),
-            ForwardWordEnd2 => (c1, cursor_move_to_word(&text, sx, cursor, r, true)),
+            ForwardWordEnd1 => (c1, cursor_move_to_word_end(&text, sx, cursor, r, false)),
+            ForwardWordEnd2 => (c1, cursor_move_to_word_end(&text, sx, cursor, r, true)),
+            BackWordEnd1 => (c1, cursor_move_to_word_end(&text, sx, cursor, -r, false)),
+            BackWordEnd2 => (c1, cursor_move_to_word_end(&text, sx, cursor, -r, true)),
             NextSearch => (c1, self.search_results.next_cursor(&text, sx, cursor, r)),
             PrevSearch => (c1, self.search_results.next_cursor(&text, sx, cursor, -r)),
-            Til1(ch) => (c1, cursor_move_to_char(&text, sx, cursor, r, *ch, false)),
-            Til2(ch) => (c1, cursor_move_to_char(&text, sx, cursor, r, *ch, true)),
+            Find(search) => (c1, cursor_move_to_char(&text, sx, cursor, repeat, search)),
+            ParagraphForward => (c1, to_char(paragraph_forward(&text, cursor.c, repeat))),
+            ParagraphBack => (c1, to_char(paragraph_backward(&text, cursor.c, repeat))),
+            SentenceForward => (c1, to_char(sentence_forward(&text, cursor.c, repeat))),
+            SentenceBack => (c1, to_char(sentence_backward(&text, cursor.c, repeat))),
+            MatchBracket => match match_bracket(&text, cursor.c) {
+                Some(c) => (c1, to_char(c)),
+                None => (c1, c2),
+            },
+            Mark(ch) => match self.mark_position(*ch) {
+                Some(c) => (c1, to_char(c)),
+                None => (c1, c2),
+            },
+            MarkLine(ch) => match self.mark_position(*ch) {
+                Some(c) => {
+                    let line_inx = text.char_to_line(c);
+                    (c1, cursor_first_non_blank(&text, sx, &config, line_inx))
+                }
+                None => (c1, c2),
+            },
+            ScreenTop | ScreenMiddle | ScreenBottom => {
+                let line_inx = self.screen_line(m, repeat);
+                (c1, cursor_first_non_blank(&text, sx, &config, line_inx))
+            }
             _ => (c1, c2),
         }
     }
 
+    // the line for H, M and L, counts are from the top or bottom of the screen
+    fn screen_line(&self, m: &Motion, repeat: usize) -> usize {
+        let mut lines = self
+            .cache_render_rows
+            .iter()
+            .map(|row| row.line_inx)
+            .collect::<Vec<usize>>();
+        lines.dedup();
+        if lines.is_empty() {
+            return self.cursor.line_inx;
+        }
+        let n = std::cmp::min(std::cmp::max(1, repeat), lines.len()) - 1;
+        match m {
+            Motion::ScreenTop => lines[n],
+            Motion::ScreenBottom => lines[lines.len() - 1 - n],
+            _ => lines[(lines.len() - 1) / 2],
+        }
+    }
+
     pub fn cursor_move(&mut self, cursor: Cursor) -> &mut Self {
         self.cursor = cursor;
         self
@@ -384,15 +789,86 @@ impl BufferBlock {
             Some(sub) => cursor_from_char(&text, self.view.w, &cursor.config, sub.start(), 0),
             None => cursor,
         };
+        self.jump_from();
         self.cursor = cursor;
         self
     }
 
-    pub fn paste_motion(&mut self, m: &Motion, s: &String, reps: usize) -> &mut Self {
-        let (_, c) = self.cursor_motion(m, 1);
-        (0..reps).for_each(|_| {
-            self.buf.insert_string(c.c, s.as_str());
-        });
+    // p and P, linewise text goes on lines of its own, blockwise text into the lines from the cursor column
+    pub fn paste(&mut self, value: &RegisterValue, after: bool, reps: usize) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        let (c, line_inx) = (self.cursor.c, self.cursor.line_inx);
+        let on_char = c < text.len_chars() && text.char(c) != '\n';
+        let s = value.text.repeat(reps);
+        self.buf.change_start();
+        match value.kind {
+            RegisterKind::Charwise => {
+                let c = if after && on_char {
+                    nth_next_grapheme_boundary(text.slice(..), c, 1)
+                } else {
+                    c
+                };
+                let len = self.buf.insert_text(c, &s);
+                // the cursor ends on the last char pasted, or the first if there's more than one line
+                if s.contains('\n') || len == 0 {
+                    self.move_to_char(c);
+                } else {
+                    let text = self.buf.get_text();
+                    self.move_to_char(nth_prev_grapheme_boundary(text.slice(..), c + len, 1));
+                }
+            }
+            RegisterKind::Linewise => {
+                // the empty line after a final newline isn't a line to paste after
+                let line_inx = line_inx.min(ex::last_line(&text));
+                let next = line_inx + 1;
+                let first = if !after {
+                    self.buf.insert_text(text.line_to_char(line_inx), &s);
+                    line_inx
+                } else if next < text.len_lines() {
+                    self.buf.insert_text(text.line_to_char(next), &s);
+                    next
+                } else {
+                    // the last line doesn't end with a newline
+                    let s = format!("\n{}", s.strip_suffix('\n').unwrap_or(&s));
+                    self.buf.insert_text(text.len_chars(), &s);
+                    next
+                };
+                let text = self.buf.get_text();
+                self.cursor = cursor_first_non_blank(&text, self.view.w, &config, first);
+            }
+            RegisterKind::Blockwise => {
+                let (r0, r1) = column(&text, &config, c);
+                let col = if after && on_char { r1 } else { r0 };
+                let lines = value.text.split('\n').collect::<Vec<&str>>();
+                let width = lines
+                    .iter()
+                    .map(|line| text_width(line, &config))
+                    .max()
+                    .unwrap_or(0);
+                for (i, line) in lines.iter().enumerate() {
+                    // add lines at the end for the block to go in
+                    while full_lines(&self.buf.get_text()) <= line_inx + i {
+                        let end = self.buf.get_text().len_chars();
+                        self.buf.insert_text(end, "\n");
+                    }
+                    let text = self.buf.get_text();
+                    let (pos, short) = column_char(&text, &config, line_inx + i, col);
+                    // pad each copy to the width of the block, unless nothing follows it
+                    let piece =
+                        format!("{}{}", line, " ".repeat(width - text_width(line, &config)));
+                    let mut s = " ".repeat(short) + &piece.repeat(reps);
+                    if pos >= text.len_chars() || text.char(pos) == '\n' {
+                        s = s.trim_end_matches(' ').to_string();
+                    }
+                    self.buf.insert_text(pos, &s);
+                }
+                let text = self.buf.get_text();
+                let (pos, _) = column_char(&text, &config, line_inx, col);
+                self.move_to_char(pos);
+            }
+        }
+        self.buf.change_end();
         self
     }
 
@@ -405,6 +881,12 @@ impl BufferBlock {
                 let config = self.buf.get_config();
                 self.cursor = cursor_from_char(&text, self.view.w, &config, start.lc0, 0);
             }
+            Motion::Inner(_) | Motion::Around(_) | Motion::Selection => {
+                self.delete_ranges(m, repeat);
+            }
+            m if m.is_inclusive() || m.is_linewise() => {
+                self.delete_ranges(m, repeat);
+            }
             _ => {
                 let (_, cursor) = self.cursor_motion(m, repeat);
                 let dx = cursor.c as i32 - self.cursor.c as i32;
@@ -414,8 +896,266 @@ impl BufferBlock {
         self
     }
 
+    fn delete_ranges(&mut self, m: &Motion, repeat: usize) -> &mut Self {
+        let ranges = self.motion_ranges(m, repeat);
+        self.visual_end();
+        ranges.iter().rev().for_each(|(start, end)| {
+            self.buf.remove_range(*start, *end);
+        });
+        if let Some((start, _)) = ranges.first() {
+            self.move_to_char(*start);
+        }
+        self
+    }
+
+    // ~ goes over count chars on the line and leaves the cursor after them, the others leave it
+    // at the start of the text they changed
+    pub fn change_case(&mut self, m: &Motion, repeat: usize, case: Case) -> &mut Self {
+        let text = self.buf.get_text();
+        let c = self.cursor.c;
+        let line_inx = text.char_to_line(c);
+        let ranges = match m {
+            Motion::Right => {
+                let eol = line_end(&text, line_inx);
+                let mut end = c;
+                for _ in 0..repeat {
+                    if end >= eol {
+                        break;
+                    }
+                    end = nth_next_grapheme_boundary(text.slice(..), end, 1);
+                }
+                vec![(c, end)]
+            }
+            Motion::Line => {
+                let line1 = std::cmp::min(line_inx + repeat, text.len_lines());
+                vec![(text.line_to_char(line_inx), text.line_to_char(line1))]
+            }
+            _ => self.motion_ranges(m, repeat),
+        };
+        let len0 = text.len_chars();
+        self.visual_end();
+        // work backwards, the length can change
+        self.buf.change_start();
+        ranges.iter().rev().for_each(|(start, end)| {
+            let before = text.slice(*start..*end).to_string();
+            let after = case.apply(&before);
+            if after != before {
+                self.buf.replace_range(*start, *end, &after);
+            }
+        });
+        self.buf.change_end();
+        match (m, ranges.first()) {
+            (Motion::Right, _) => {
+                let text = self.buf.get_text();
+                let eol = line_end(&text, line_inx);
+                // the case change can make the text longer
+                let end = ranges[0].1 + text.len_chars() - len0;
+                let end = match end >= eol && eol > text.line_to_char(line_inx) {
+                    true => nth_prev_grapheme_boundary(text.slice(..), eol, 1),
+                    false => end,
+                };
+                self.move_to_char(end);
+            }
+            (_, Some((start, _))) => {
+                self.move_to_char(*start);
+            }
+            _ => (),
+        }
+        self
+    }
+
+    // the first and last lines an operator on whole lines acts on
+    fn motion_lines(&self, m: &Motion, repeat: usize) -> (usize, usize) {
+        let text = self.buf.get_text();
+        let line_inx = text.char_to_line(self.cursor.c);
+        match m {
+            Motion::Line => {
+                let last = text.len_lines() - 1;
+                (line_inx, std::cmp::min(line_inx + repeat - 1, last))
+            }
+            _ => {
+                let (start, end) = self.motion_range(m, repeat);
+                let end = std::cmp::max(start + 1, end) - 1;
+                (text.char_to_line(start), text.char_to_line(end))
+            }
+        }
+    }
+
+    // > < = gq gw and gc, the cursor goes to the first non-blank of the first line, or the last line
+    // for gq, gw leaves it where it was
+    pub fn line_op(&mut self, op: LineOp, m: &Motion, repeat: usize) -> &mut Self {
+        let (line0, line1) = self.motion_lines(m, repeat);
+        let c = self.cursor.c;
+        self.visual_end();
+        self.buf.change_start();
+        let line = match op {
+            LineOp::Shift(levels) => {
+                (line0..=line1).for_each(|line_inx| {
+                    self.buf.shift_line(line_inx, levels);
+                });
+                line0
+            }
+            LineOp::Reindent => {
+                self.buf.reindent_lines(line0, line1 + 1);
+                line0
+            }
+            LineOp::Comment => {
+                self.buf.comment_lines(line0, line1 + 1);
+                line0
+            }
+            LineOp::Format(_) => line0 + self.buf.format_lines(line0, line1 + 1) - 1,
+        };
+        self.buf.change_end();
+        match op {
+            LineOp::Format(true) => self.move_to_char(c),
+            _ => self.cursor_move_line(line as i64).motion(&Motion::SOLT, 1),
+        }
+    }
+
+    pub fn move_to_char(&mut self, c: usize) -> &mut Self {
+        let text = self.buf.get_text();
+        let config = self.buf.get_config();
+        self.cursor = cursor_from_char(
+            &text,
+            self.view.w,
+            &config,
+            std::cmp::min(c, text.len_chars()),
+            0,
+        )
+        .save_x_hint(self.view.w);
+        self
+    }
+
+    // the visual selection, from the anchor to the cursor
+    pub fn selection(&self) -> Option<Selection> {
+        self.visual
+            .map(|s| Selection::new(s.mode, s.anchor, self.cursor.c))
+    }
+
+    // start, change or end the visual selection to match the mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) -> &mut Self {
+        if self.mode.is_insert() && !mode.is_insert() {
+            self.buf.set_mark(marks::INSERT, self.cursor.c);
+        }
+        if mode == Mode::Replace && self.mode != Mode::Replace {
+            self.replaced.clear();
+        }
+        self.mode = mode;
+        match (self.visual.as_mut(), mode.is_visual()) {
+            (Some(s), true) => s.mode = mode,
+            (None, true) => self.visual = Some(Selection::new(mode, self.cursor.c, self.cursor.c)),
+            (Some(_), false) => {
+                self.visual_end();
+            }
+            (None, false) => (),
+        }
+        self
+    }
+
+    // end the visual selection, keeping it for gv
+    pub fn visual_end(&mut self) -> &mut Self {
+        if self.visual.is_some() {
+            self.last_visual = self.selection();
+            self.visual = None;
+        }
+        self
+    }
+
+    // move the cursor to the other end of the selection
+    pub fn visual_swap(&mut self) -> &mut Self {
+        if let Some(s) = self.visual.as_mut() {
+            let c = s.anchor;
+            s.anchor = self.cursor.c;
+            self.move_to_char(c);
+        }
+        self
+    }
+
+    // select the last visual selection again
+    pub fn visual_restore(&mut self) -> &mut Self {
+        if let Some(s) = self.last_visual.map(|s| s.clamp(&self.buf.get_text())) {
+            self.visual = Some(s);
+            self.move_to_char(s.head);
+        }
+        self
+    }
+
+    // end the selection, with the cursor at its start
+    pub fn visual_start(&mut self) -> &mut Self {
+        if let Some(s) = self.selection() {
+            self.visual_end().move_to_char(s.start());
+        }
+        self
+    }
+
+    // remember the cursor before a jump, in the '' mark and the jump list
+    pub fn jump_from(&mut self) -> &mut Self {
+        self.buf.set_mark(marks::PREVIOUS, self.cursor.c);
+        self.jumps.push(Jump::new(&self.buf, self.cursor.c));
+        self
+    }
+
+    // g; and g, move through the change list, a new change starts again from the newest
+    pub fn change_jump(&mut self, count: i32) -> &mut Self {
+        let changes = self.buf.get_changes();
+        if changes.is_empty() {
+            return self;
+        }
+        let len = changes.len() as i64;
+        let inx = match self.change_pos {
+            Some((n, inx)) if n == changes.len() => inx as i64,
+            _ => len,
+        };
+        let next = (inx + count as i64).clamp(0, len - 1);
+        if next != inx {
+            self.change_pos = Some((changes.len(), next as usize));
+            self.move_to_char(changes[next as usize]);
+        }
+        self
+    }
+
+    pub fn set_mark(&mut self, ch: char) -> &mut Self {
+        self.buf.set_mark(ch, self.cursor.c);
+        self
+    }
+
+    // the char position of a mark, the visual marks come from the selection
+    pub fn mark_position(&self, ch: char) -> Option<usize> {
+        match ch {
+            '<' | '>' => self.selection().or(self.last_visual).map(|s| match ch {
+                '<' => std::cmp::min(s.anchor, s.head),
+                _ => std::cmp::max(s.anchor, s.head),
+            }),
+            _ => self.buf.get_mark(ch),
+        }
+    }
+
+    // marks for ex addresses, as line indexes
+    pub fn marks(&self) -> HashMap<char, usize> {
+        let text = self.buf.get_text();
+        let mut marks = self
+            .buf
+            .get_marks()
+            .into_iter()
+            .map(|(ch, c)| (ch, text.char_to_line(c)))
+            .collect::<HashMap<char, usize>>();
+        if let Some(s) = self.selection().or(self.last_visual) {
+            let (l0, l1) = s.lines(&self.buf.get_text());
+            marks.insert('<', l0);
+            marks.insert('>', l1);
+        }
+        marks
+    }
+
     pub fn motion(&mut self, m: &Motion, repeat: usize) -> &mut Self {
         let (_, cursor) = self.cursor_motion(m, repeat);
+        if m.is_jump() {
+            self.jump_from();
+        }
         self.cursor = cursor;
         //info!("Motion: {:?}", &self.cursor.simple_format());
         self
@@ -446,8 +1186,19 @@ impl BufferBlock {
         use Command::*;
         debug!("command {:?}", c);
         match c {
+            Insert(x) if self.mode == editor_core::Mode::Replace => self.overwrite(x).update(),
+            RemoveChar(-1) if self.mode == editor_core::Mode::Replace => {
+                self.overwrite_back().update()
+            }
+            Insert(x) if self.mode == editor_core::Mode::Insert => self.insert_indented(x).update(),
+            RemoveChar(-1) if self.mode == editor_core::Mode::Insert => self.backspace().update(),
             Insert(x) => self.insert_string(x).update(),
             RemoveChar(dx) => self.remove_range(*dx).update(),
+            OpenLine(below) => self.open_line(*below).update(),
+            ReplaceChar(reps, ch, virtual_cols) => {
+                self.replace_chars(*reps, *ch, *virtual_cols).update()
+            }
+            Increment(reps, sign) => self.increment(*reps as i64 * *sign as i64).update(),
             Undo => self.undo().update(),
             Redo => self.redo().update(),
             _ => self,
@@ -455,6 +1206,22 @@ impl BufferBlock {
     }
 }
 
+// the char position of the line ending, or the end of the text on the last line
+fn line_end(text: &Rope, line_inx: usize) -> usize {
+    let line = text.line(line_inx).to_string();
+    let ending = line.len() - line.trim_end_matches(['\n', '\r']).len();
+    text.line_to_char(line_inx) + line.chars().count() - ending
+}
+
+// the number of lines with something on them, not counting the empty line after a final newline
+fn full_lines(text: &Rope) -> usize {
+    match text.len_chars() {
+        0 => 0,
+        n if text.char(n - 1) == '\n' => text.len_lines() - 1,
+        _ => text.len_lines(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -513,4 +1280,356 @@ mod tests {
         block.cursor.print();
         log::info!("rc:{:?}", &block.rc);
     }
+
+    #[test]
+    fn text_objects() {
+        use editor_core::TextObject;
+        let view = ViewPos {
+            w: 40,
+            h: 4,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"f(\"a b\", c);\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.motion(&Motion::Right, 4).update();
+        assert_eq!(
+            block
+                .motion_value(&Motion::Inner(TextObject::Quote('"')), 1)
+                .text,
+            "a b"
+        );
+        block.delete_motion(&Motion::Inner(TextObject::Quote('"')), 1);
+        assert_eq!(block.get_text().to_string(), "f(\"\", c);\n");
+        assert_eq!(block.cursor.c, 3);
+        block.delete_motion(&Motion::Around(TextObject::Block('(', ')')), 1);
+        assert_eq!(block.get_text().to_string(), "f;\n");
+    }
+
+    #[test]
+    fn char_search() {
+        use editor_core::CharSearch;
+        let view = ViewPos {
+            w: 40,
+            h: 4,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"a(b, c, d) e\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        let f = |ch, forward, till| Motion::Find(CharSearch::new(ch, forward, till));
+
+        block.motion(&f(',', true, false), 2).update();
+        assert_eq!(block.cursor.c, 6);
+        block.motion(&f('(', false, true), 1).update();
+        assert_eq!(block.cursor.c, 2);
+        // a till stops next to the char, a repeated till moves on to the next one
+        block.motion(&f(',', true, true), 1).update();
+        assert_eq!(block.cursor.c, 2);
+        let search = CharSearch::new(',', true, true);
+        block
+            .motion(&Motion::Find(search.repeat(false)), 1)
+            .update();
+        assert_eq!(block.cursor.c, 5);
+        // no match leaves the cursor where it is
+        block.motion(&f('x', true, false), 1).update();
+        assert_eq!(block.cursor.c, 5);
+
+        // f is inclusive, t stops before the char
+        block.delete_motion(&f(',', true, false), 1);
+        assert_eq!(block.get_text().to_string(), "a(b,  d) e\n");
+        block.delete_motion(&f(')', true, true), 1);
+        assert_eq!(block.get_text().to_string(), "a(b, ) e\n");
+        block.delete_motion(&f('(', false, false), 1);
+        assert_eq!(block.get_text().to_string(), "a) e\n");
+    }
+
+    #[test]
+    fn word_end() {
+        let view = ViewPos {
+            w: 40,
+            h: 4,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"one two.three four\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.motion(&Motion::ForwardWordEnd1, 2).update();
+        assert_eq!(block.cursor.c, 6);
+        block.motion(&Motion::ForwardWordEnd2, 1).update();
+        assert_eq!(block.cursor.c, 12);
+        block.motion(&Motion::BackWordEnd1, 1).update();
+        assert_eq!(block.cursor.c, 7);
+        block.motion(&Motion::BackWordEnd2, 1).update();
+        assert_eq!(block.cursor.c, 2);
+
+        // e is inclusive
+        block.motion(&Motion::Right, 2).update();
+        block.delete_motion(&Motion::ForwardWordEnd1, 1);
+        assert_eq!(block.get_text().to_string(), "one .three four\n");
+    }
+
+    #[test]
+    fn line_motions() {
+        let view = ViewPos {
+            w: 10,
+            h: 6,
+            x0: 0,
+            y0: 0,
+        };
+        let text = "a(b[c])\n0123456789abcdef\nxy\n\n  last\n";
+        let buf = Buffer::from_string(&text.to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.update();
+
+        // j and k move by buffer line, gj and gk by display line
+        block.motion(&Motion::Down, 1).update();
+        assert_eq!(block.cursor.line_inx, 1);
+        block.motion(&Motion::Down, 1).update();
+        assert_eq!(block.cursor.line_inx, 2);
+        block.motion(&Motion::DisplayUp, 1).update();
+        assert_eq!((block.cursor.line_inx, block.cursor.wrap0), (1, 1));
+        block.motion(&Motion::ScreenTop, 1).update();
+        assert_eq!(block.cursor.c, 0);
+
+        block.motion(&Motion::MatchBracket, 1).update();
+        assert_eq!(block.cursor.c, 6);
+        block.motion(&Motion::ParagraphForward, 1).update();
+        assert_eq!(block.cursor.line_inx, 3);
+        block.motion(&Motion::ScreenBottom, 1).update();
+        assert_eq!(block.cursor.c, 31);
+
+        // operators take whole lines for linewise motions and the bracket for %
+        block.motion(&Motion::Up, 10).update();
+        block.motion(&Motion::SOL, 1).update();
+        block.delete_motion(&Motion::MatchBracket, 1).update();
+        assert_eq!(block.get_text().line(0).to_string(), "\n");
+        block.delete_motion(&Motion::Down, 1).update();
+        assert_eq!(block.get_text().to_string(), "xy\n\n  last\n");
+        block.delete_motion(&Motion::ParagraphForward, 1).update();
+        assert_eq!(block.get_text().to_string(), "\n  last\n");
+    }
+
+    #[test]
+    fn scrolling() {
+        let view = ViewPos {
+            w: 20,
+            h: 5,
+            x0: 0,
+            y0: 0,
+        };
+        let text = (0..30).map(|i| format!("line {}\n", i)).collect::<String>();
+        let buf = Buffer::from_string(&text);
+        let mut block = BufferBlock::new(buf, view);
+        block.set_option("scrolloff", "1").unwrap();
+        assert!(block.set_option("scrolloff", "x").is_err());
+        block.update();
+
+        // the view scrolls before the cursor reaches the last row
+        block.motion(&Motion::Down, 3).update();
+        assert_eq!(block.start.line_inx, 0);
+        block.motion(&Motion::Down, 1).update();
+        assert_eq!(block.start.line_inx, 1);
+
+        block.scroll_cursor(&Motion::ScreenTop).update();
+        assert_eq!(block.start.line_inx, 3);
+        block.scroll_cursor(&Motion::ScreenBottom).update();
+        assert_eq!(block.start.line_inx, 1);
+        block.scroll_cursor(&Motion::ScreenMiddle).update();
+        assert_eq!(block.start.line_inx, 2);
+
+        // scrolling by lines keeps the cursor on screen, outside of the margins
+        block.motion(&Motion::Right, 2).update();
+        block.scroll(2).update_from_start();
+        block.update();
+        assert_eq!((block.start.line_inx, block.cursor.line_inx), (4, 5));
+        assert_eq!(block.cursor.c - block.cursor.lc0, 2);
+        block.scroll(-3).update_from_start();
+        block.update();
+        assert_eq!((block.start.line_inx, block.cursor.line_inx), (1, 4));
+    }
+
+    #[test]
+    fn marks() {
+        let view = ViewPos {
+            w: 40,
+            h: 10,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"one\ntwo\n  three\nfour\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.motion(&Motion::Down, 2).update();
+        block.motion(&Motion::Right, 3).update();
+        block.set_mark('a');
+
+        // marks move with lines inserted and removed above them
+        block.buf.insert_string(0, "zero\n");
+        block.motion(&Motion::MarkLine('a'), 1).update();
+        assert_eq!((block.cursor.line_inx, block.cursor.c), (3, 15));
+        block.buf.delete_line_range(0, 2);
+        block.motion(&Motion::SOL, 1).update();
+        block.motion(&Motion::Mark('a'), 1).update();
+        assert_eq!((block.cursor.line_inx, block.cursor.c), (1, 7));
+        assert_eq!(block.marks().get(&'.'), Some(&0));
+
+        // '' goes back to where the last jump started
+        block.motion(&Motion::Up, 1).update();
+        block.motion(&Motion::MarkLine('a'), 1).update();
+        block.motion(&Motion::MarkLine('\''), 1).update();
+        assert_eq!(block.cursor.line_inx, 0);
+        block.motion(&Motion::Mark('\''), 1).update();
+        assert_eq!(block.cursor.c, 6);
+
+        // unset marks don't move the cursor or delete lines
+        block.delete_motion(&Motion::MarkLine('b'), 1).update();
+        assert_eq!(block.get_text().to_string(), "two\n  three\nfour\n");
+        block.motion(&Motion::Up, 1).update();
+        block.delete_motion(&Motion::MarkLine('a'), 1).update();
+        assert_eq!(block.get_text().to_string(), "four\n");
+
+        block.set_mode(Mode::Insert);
+        block.set_mode(Mode::Normal);
+        assert_eq!(block.buf.get_mark('^'), Some(0));
+    }
+
+    #[test]
+    fn change_list() {
+        let view = ViewPos {
+            w: 40,
+            h: 10,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"one\ntwo\nthree\nfour\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.buf.insert_string(0, "a");
+        block.buf.insert_string(1, "b");
+        block.buf.insert_string(10, "c");
+        block.buf.insert_string(17, "d");
+        assert_eq!(block.buf.get_changes(), vec![1, 10, 17]);
+
+        // g; walks back from the newest change, g, forward again
+        block.change_jump(-1).update();
+        assert_eq!(block.cursor.c, 17);
+        block.change_jump(-2).update();
+        assert_eq!(block.cursor.c, 1);
+        block.change_jump(-1).update();
+        assert_eq!(block.cursor.c, 1);
+        block.change_jump(1).update();
+        assert_eq!(block.cursor.c, 10);
+
+        // a new change starts again from the newest
+        block.buf.insert_string(0, "e\n");
+        block.change_jump(-1).update();
+        assert_eq!(block.cursor.c, 0);
+    }
+
+    #[test]
+    fn replace() {
+        let view = ViewPos {
+            w: 40,
+            h: 10,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"a\u{754c}e\u{301}d\nxy\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+
+        // a wide char and a combining sequence are each overwritten by one char,
+        // and past the end of the line the text is added
+        block.set_mode(Mode::Replace);
+        block.command(&Command::Insert("123".into()));
+        assert_eq!(block.get_text().to_string(), "123d\nxy\n");
+        block.command(&Command::Insert("45".into()));
+        assert_eq!(block.get_text().to_string(), "12345\nxy\n");
+
+        // backspace puts back what was overwritten, then only moves
+        for _ in 0..6 {
+            block.command(&Command::RemoveChar(-1));
+        }
+        assert_eq!(block.get_text().to_string(), "a\u{754c}e\u{301}d\nxy\n");
+        assert_eq!(block.cursor.c, 0);
+        block.set_mode(Mode::Normal);
+
+        // r needs enough chars on the line, and leaves the cursor on the last one
+        block.command(&Command::ReplaceChar(5, 'z', false));
+        assert_eq!(block.get_text().to_string(), "a\u{754c}e\u{301}d\nxy\n");
+        block.command(&Command::ReplaceChar(3, 'z', false));
+        assert_eq!(block.get_text().to_string(), "zzzd\nxy\n");
+        assert_eq!(block.cursor.c, 2);
+        block.command(&Command::ReplaceChar(1, '\n', false));
+        assert_eq!(block.get_text().to_string(), "zz\nd\nxy\n");
+        assert_eq!(block.cursor.c, 3);
+
+        // gr keeps the text after a tab where it was
+        block.replace_buffer("\tx\n");
+        block.move_to_char(0);
+        let (col0, col1) = column(&block.get_text(), &block.get_config(), 0);
+        let width = col1 - col0;
+        block.command(&Command::ReplaceChar(2, '-', true));
+        let spaces = " ".repeat(width - 2);
+        assert_eq!(block.get_text().to_string(), format!("--{}x\n", spaces));
+        assert_eq!(block.cursor.c, 1);
+        block.move_to_char(0);
+        block.command(&Command::ReplaceChar(width + 2, '=', true));
+        assert_eq!(block.get_text().to_string(), "=".repeat(width + 1) + "\n");
+
+        // gr on an empty line does nothing
+        block.replace_buffer("\nabc\n\n");
+        block.move_to_char(0);
+        block.command(&Command::ReplaceChar(1, 'x', true));
+        block.command(&Command::ReplaceChar(1, '\n', true));
+        assert_eq!(block.get_text().to_string(), "\nabc\n\n");
+        assert_eq!(block.cursor.c, 0);
+        block.move_to_char(5);
+        block.command(&Command::ReplaceChar(1, 'x', true));
+        assert_eq!(block.cursor.c, 5);
+    }
+
+    #[test]
+    fn auto_indent() {
+        let view = ViewPos {
+            w: 40,
+            h: 10,
+            x0: 0,
+            y0: 0,
+        };
+        let buf = Buffer::from_string(&"  if a {\n".to_string());
+        let mut block = BufferBlock::new(buf, view);
+        block.set_option("smartindent", "false").unwrap();
+
+        // autoindent copies the indent, smart indent follows the bracket
+        block.set_mode(Mode::Insert);
+        block.move_to_char(3);
+        block.command(&Command::Insert("\n".into()));
+        assert_eq!(block.get_text().to_string(), "  i\n  f a {\n");
+        block.command(&Command::Undo);
+        block.set_option("smartindent", "true").unwrap();
+        block.move_to_char(8);
+        block.command(&Command::Insert("\n".into()));
+        assert_eq!(block.get_text().to_string(), "  if a {\n      \n");
+
+        // backspace takes away a level, a closing bracket goes back one
+        block.command(&Command::RemoveChar(-1));
+        assert_eq!(block.get_text().to_string(), "  if a {\n    \n");
+        block.command(&Command::Insert("    ".into()));
+        block.command(&Command::Insert("}".into()));
+        assert_eq!(block.get_text().to_string(), "  if a {\n  }\n");
+
+        // o and O
+        block.set_mode(Mode::Normal);
+        block.move_to_char(0);
+        block.set_mode(Mode::Insert);
+        block.command(&Command::OpenLine(true));
+        block.command(&Command::Insert("x".into()));
+        assert_eq!(block.get_text().to_string(), "  if a {\n      x\n  }\n");
+        block.move_to_char(17);
+        block.command(&Command::OpenLine(false));
+        assert_eq!(
+            block.get_text().to_string(),
+            "  if a {\n      x\n      \n  }\n"
+        );
+        assert_eq!(block.cursor.c, 23);
+    }
 }
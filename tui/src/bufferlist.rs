@@ -35,6 +35,20 @@ where
         self
     }
 
+    // rotate until the first element matches, returns false if nothing matched
+    pub fn find<F>(&mut self, f: F) -> bool
+    where
+        F: Fn(&T) -> bool,
+    {
+        match self.elements.iter().position(f) {
+            Some(inx) => {
+                self.elements.rotate_left(inx);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn prev(&mut self) -> &mut Self {
         if let Some(b) = self.elements.pop_back() {
             self.elements.push_front(b);
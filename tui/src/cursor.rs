@@ -1,7 +1,10 @@
 use super::ViewChar;
 use super::*;
 use ::num::Integer;
-use editor_core::{nth_next_grapheme_boundary, nth_prev_grapheme_boundary, BufferConfig};
+use editor_core::word::{word_backward, word_end_backward, word_end_forward, word_forward};
+use editor_core::{
+    nth_next_grapheme_boundary, nth_prev_grapheme_boundary, BufferConfig, CharSearch,
+};
 use log::*;
 use ropey::Rope;
 use unicode_segmentation::UnicodeSegmentation;
@@ -88,10 +91,12 @@ impl Cursor {
         config: &BufferConfig,
         sx: usize,
         highlight: String,
+        select: (usize, usize),
     ) -> Vec<LineFormat> {
         //debug!("to_line_format: {}: {:?}", self.simple_format(), sx);
         // get the current row of the wrapped line
-        let mut out = match format_wrapped(&self.line, sx, highlight, config).get(self.wrap0) {
+        let rows = format_wrapped_select(&self.line, sx, highlight, select, config);
+        let mut out = match rows.get(self.wrap0) {
             Some(row) => row.clone(),
             None => vec![],
         };
@@ -282,6 +287,32 @@ pub fn cursor_move_to_y(text: &Rope, sx: usize, cursor: &Cursor, dy: i32) -> Cur
     c
 }
 
+// move by buffer lines, keeping the column, j and k
+pub fn cursor_move_to_line(text: &Rope, sx: usize, cursor: &Cursor, dy: i32) -> Cursor {
+    let last = text.len_lines().saturating_sub(1) as i64;
+    let line_inx = (cursor.line_inx as i64 + dy as i64).clamp(0, last) as usize;
+    if line_inx == cursor.line_inx {
+        return cursor.clone();
+    }
+    let r = cursor.wrap0 * sx + cursor.x_hint;
+    cursor_line_relative(text, sx, &cursor.config, line_inx, r / sx, r % sx)
+        .unwrap_or_else(|| cursor.clone())
+}
+
+// the first char on a line that isn't whitespace
+pub fn cursor_first_non_blank(
+    text: &Rope,
+    sx: usize,
+    config: &BufferConfig,
+    line_inx: usize,
+) -> Cursor {
+    let line_inx = std::cmp::min(line_inx, text.len_lines().saturating_sub(1));
+    let line = text.line(line_inx).to_string();
+    let (_, chars) = config.leading_indent(&line);
+    let c = text.line_to_char(line_inx) + chars;
+    cursor_from_char(text, sx, config, c, 0).save_x_hint(sx)
+}
+
 pub fn cursor_move_to_x(text: &Rope, sx: usize, cursor: &Cursor, dx: i32) -> Cursor {
     info!(
         "cursor_move_to_x: {:?}",
@@ -407,8 +438,9 @@ pub fn cursor_from_char(
     let eof = line_inx + 1 == text.len_lines();
     let elements = string_to_elements(&line, config);
 
-    // must be >= 1
-    let wraps = (elements.unicode_width() + 1).div_ceil(sx);
+    // must be >= 1, and leave room for the cursor at the end of a line without a newline
+    let end_width = if line.ends_with('\n') { 0 } else { 1 };
+    let wraps = std::cmp::max(1, (elements.unicode_width() + end_width).div_ceil(sx));
 
     let r = elements.lc_to_r(c - lc0);
     let wrap0 = r / sx;
@@ -431,113 +463,70 @@ pub fn cursor_from_char(
     }
 }
 
-struct TextIterator<'a> {
-    text: &'a Rope,
-    reverse: bool,
-    c: usize,
-}
-
-impl<'a> Iterator for TextIterator<'a> {
-    type Item = char;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.text.len_chars() == 0 {
-            None
-        } else if self.reverse {
-            if self.c == 0 {
-                None
-            } else {
-                self.c -= 1;
-                let ch = self.text.char(self.c);
-                Some(ch)
-            }
-        } else {
-            if self.c >= self.text.len_chars() - 1 {
-                None
-            } else {
-                let ch = self.text.char(self.c);
-                self.c += 1;
-                Some(ch)
-            }
-        }
-    }
-}
-
-impl<'a> TextIterator<'a> {
-    fn new(text: &'a Rope, c: usize, reverse: bool) -> Self {
-        Self { text, reverse, c }
-    }
-
-    fn take_while1(&'a mut self, p: impl FnMut(&char) -> bool) -> &'a mut TextIterator {
-        let start = self.c;
-        let count = self.take_while(p).count();
-        if self.reverse {
-            self.c = start - count;
-        } else {
-            self.c = start + count;
-        }
-        self
-    }
-}
-
-fn is_special(ch: &char) -> bool {
-    ":;'\"(){}[]".contains(*ch)
-}
-
+// move to the nth match of a char search on the current line, the cursor stays if there isn't one
 pub fn cursor_move_to_char(
     text: &Rope,
     sx: usize,
     cursor: &Cursor,
-    d: i32,
-    ch: char,
-    _flag: bool,
+    count: usize,
+    search: &CharSearch,
 ) -> Cursor {
-    let start = cursor.c - cursor.lc0;
-    match cursor
+    let chars = cursor
         .line
         .chars()
-        .skip(1)
-        .skip(start)
-        .position(|c| c == ch)
-    {
+        .take_while(|c| *c != '\n' && *c != '\r')
+        .collect::<Vec<char>>();
+    let lc = cursor.c - cursor.lc0;
+    // a repeated till would match the char it stopped next to, so skip it
+    let skip = if search.till && search.repeat { 2 } else { 1 };
+    let n = std::cmp::max(1, count) - 1;
+    let matches = (0..chars.len()).filter(|inx| chars[*inx] == search.ch);
+    let found = if search.forward {
+        matches.filter(|inx| *inx >= lc + skip).nth(n)
+    } else {
+        matches.filter(|inx| inx + skip <= lc).rev().nth(n)
+    };
+    match found {
         Some(inx) => {
-            debug!("cursor_move_to_char: {:?}", (d, ch, inx, start));
-            cursor_move_to_lc(&text, sx, cursor, (start + inx + 1) as i32)
+            let target = match (search.till, search.forward) {
+                (true, true) => inx - 1,
+                (true, false) => inx + 1,
+                _ => inx,
+            };
+            debug!("cursor_move_to_char: {:?}", (search, count, lc, target));
+            cursor_move_to_lc(text, sx, cursor, target as i32)
         }
         None => cursor.clone(),
     }
 }
 
+// w, W, b and B, a negative count moves backwards
 pub fn cursor_move_to_word(text: &Rope, sx: usize, cursor: &Cursor, d: i32, cap: bool) -> Cursor {
-    if d == 0 {
-        return cursor.clone();
-    }
-    let mut c = cursor.c;
-    let mut count = 0;
-    let d_abs = i32::abs(d);
-    while count < d_abs {
-        let mut it = TextIterator::new(text, c, d < 0);
-
-        if d < 0 {
-            let mut it2 = it.take_while1(|ch: &char| ch.is_whitespace() || is_special(ch));
-            if cap {
-                it2 = it2.take_while1(|ch: &char| !ch.is_whitespace());
-            } else {
-                it2 = it2.take_while1(|ch: &char| ch.is_alphanumeric() || is_special(ch));
-            }
-            c = it2.c;
-        } else {
-            let mut it2;
-            if cap {
-                it2 = it.take_while1(|ch: &char| !ch.is_whitespace());
-            } else {
-                it2 = it.take_while1(|ch: &char| ch.is_alphanumeric() || is_special(ch));
-            }
-            it2 = it2.take_while1(|ch: &char| ch.is_whitespace() || is_special(ch));
-            c = it2.c;
-        }
-        count += 1;
-    }
+    let keyword = &cursor.config.keyword_chars;
+    let count = d.unsigned_abs() as usize;
+    let c = if d < 0 {
+        word_backward(text, cursor.c, count, keyword, cap)
+    } else {
+        word_forward(text, cursor.c, count, keyword, cap)
+    };
+    cursor_from_char(text, sx, &cursor.config, c, 0).save_x_hint(sx)
+}
 
+// e, E, ge and gE, a negative count moves backwards
+pub fn cursor_move_to_word_end(
+    text: &Rope,
+    sx: usize,
+    cursor: &Cursor,
+    d: i32,
+    cap: bool,
+) -> Cursor {
+    let keyword = &cursor.config.keyword_chars;
+    let count = d.unsigned_abs() as usize;
+    let c = if d < 0 {
+        word_end_backward(text, cursor.c, count, keyword, cap)
+    } else {
+        word_end_forward(text, cursor.c, count, keyword, cap)
+    };
     cursor_from_char(text, sx, &cursor.config, c, 0).save_x_hint(sx)
 }
 
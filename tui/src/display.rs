@@ -8,6 +8,7 @@ pub enum DrawCommand {
     Format(usize, usize, usize, Vec<LineFormat>),
     SavePosition,
     RestorePosition,
+    Clipboard(char, String), // set a terminal selection with OSC 52
 }
 
 #[derive(Debug, Clone)]
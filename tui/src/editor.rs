@@ -1,8 +1,14 @@
 use super::*;
 use crate::layout::*;
+use crate::quickfix::Quickfix;
+use crate::replace::ReplacePreview;
 use editor_bindings::command_parse;
-use editor_core::{Buffer, Command, Registers, Variable, Variables, ViewPos};
+use editor_core::ex::{compile_pattern, expand_replacement};
+use editor_core::{
+    Buffer, BufferConfig, Clipboard, Command, Registers, Variable, Variables, ViewPos,
+};
 use log::*;
+use std::collections::HashMap;
 use std::path::Path;
 
 pub trait EditorLayout {
@@ -13,6 +19,12 @@ pub trait EditorLayout {
     fn command(&mut self, c: &Command) -> Vec<Command>;
     fn get_buffer(&mut self) -> &BufferBlock;
     fn get_buffer_mut(&mut self) -> &mut BufferBlock;
+    fn output(&mut self, s: &str);
+    fn show_buffer(&mut self, buf: &Buffer);
+    fn jump_to_buffer(&mut self, buf: &Buffer);
+    fn find_buffer(&mut self, path: &str) -> Option<Buffer>;
+    // the command line being run, with the : / or ? it started with
+    fn command_line(&self) -> String;
 }
 
 pub struct EditorSimpleLayout {
@@ -49,6 +61,26 @@ impl EditorLayout for EditorSimpleLayout {
         &mut self.layout.get_buffer_mut().main
     }
 
+    fn output(&mut self, s: &str) {
+        info!("output: {}", s);
+    }
+
+    fn show_buffer(&mut self, buf: &Buffer) {
+        self.layout.show(buf);
+    }
+
+    fn jump_to_buffer(&mut self, buf: &Buffer) {
+        self.layout.jump_to(buf);
+    }
+
+    fn find_buffer(&mut self, path: &str) -> Option<Buffer> {
+        self.layout.find_buffer(path)
+    }
+
+    fn command_line(&self) -> String {
+        String::new()
+    }
+
     fn command(&mut self, c: &Command) -> Vec<Command> {
         use Command::*;
         self.layout.get_buffer_mut().main.command(c);
@@ -69,6 +101,14 @@ impl EditorLayout for EditorSimpleLayout {
     }
 }
 
+// compare paths by the file they refer to, falling back to the path when the file doesn't exist
+pub(crate) fn same_file(a: &str, b: &str) -> bool {
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
 pub struct EditorComplexLayout {
     header: RenderBlock,
     cmd_block: BufferBlock,
@@ -329,6 +369,36 @@ impl EditorLayout for EditorComplexLayout {
         &mut self.layout.get_buffer_mut().main
     }
 
+    fn output(&mut self, s: &str) {
+        self.command_output(&s.to_string()).update();
+    }
+
+    fn show_buffer(&mut self, buf: &Buffer) {
+        self.layout.show(buf);
+        self.layout
+            .get_buffer_mut()
+            .main
+            .block
+            .set_highlight(self.highlight.clone());
+    }
+
+    fn jump_to_buffer(&mut self, buf: &Buffer) {
+        self.layout.jump_to(buf);
+        self.layout
+            .get_buffer_mut()
+            .main
+            .block
+            .set_highlight(self.highlight.clone());
+    }
+
+    fn find_buffer(&mut self, path: &str) -> Option<Buffer> {
+        self.layout.find_buffer(path)
+    }
+
+    fn command_line(&self) -> String {
+        self.get_command_line()
+    }
+
     fn command(&mut self, c: &Command) -> Vec<Command> {
         use Command::*;
         self.layout.get_buffer_mut().main.command(c);
@@ -388,30 +458,50 @@ impl EditorLayout for EditorComplexLayout {
             }
 
             Open(filename) => {
-                let path = Path::new(filename);
-                match path.canonicalize() {
-                    Ok(c_path) => {
-                        let buf = Buffer::from_path_or_empty(&c_path.to_str().unwrap().to_string());
-                        self.add_window(buf);
-                    }
+                let path = match Path::new(filename).canonicalize() {
+                    Ok(c_path) => c_path.to_str().unwrap().to_string(),
                     Err(err) => {
                         error!("Error opening file: {:?}", (filename, err));
-                        let buf = Buffer::from_path_or_empty(&filename.to_string());
-                        self.add_window(buf);
+                        filename.to_string()
                     }
+                };
+                // opening a file is a jump, the jump list stays with the window
+                let block = &mut self.layout.get_buffer_mut().main;
+                block.jump_from();
+                let jumps = std::mem::take(&mut block.jumps);
+                // switch to the file if it's already open
+                if self
+                    .layout
+                    .buffers
+                    .find(|w| same_file(&w.main.get_path(), &path))
+                {
+                    self.layout.get_buffer_mut().clear();
+                } else {
+                    self.add_window(Buffer::from_path_or_empty(&path));
                 }
+                self.layout.get_buffer_mut().main.jumps = jumps;
                 vec![]
             }
 
-            VarGet(s) => {
-                //let v = self.variables.get(&Variable(s.clone()));
-                //self.command_output(&format!("get {} = {}", s, v)).update();
-                vec![]
-            }
+            // :set ai and :set noai switch flags, :set ai? shows them
+            VarGet(s) => match BufferConfig::flag_option(s) {
+                Some((name, on)) => self.command(&VarSet(name.to_string(), on.to_string())),
+                None => {
+                    let name = s.trim_end_matches('?');
+                    let config = self.layout.get_buffer().main.get_config();
+                    let out = match config.get_option(name) {
+                        Some(v) => format!("{}={}", name, v),
+                        None => format!("Unknown option: {}", name),
+                    };
+                    self.command_output(&out).update();
+                    vec![]
+                }
+            },
             VarSet(a, b) => {
-                //let k = Variable(a.clone());
-                //let _v = self.variables.update(&k, b);
-                //self.command_output(&format!("set {} = {}", a, b)).update();
+                let result = self.layout.get_buffer_mut().main.set_option(a, b);
+                if let Err(err) = result {
+                    self.command_output(&err).update();
+                }
                 vec![]
             }
 
@@ -424,9 +514,15 @@ impl EditorLayout for EditorComplexLayout {
 
 pub struct Editor {
     config: EditorConfig,
-    registers: Registers,
+    pub(crate) registers: Registers,
     variables: Variables,
-    layout: Box<dyn EditorLayout + Send>,
+    pub(crate) layout: Box<dyn EditorLayout + Send>,
+    quickfix: Quickfix,
+    pub(crate) replace: ReplacePreview,
+    pub(crate) file_marks: HashMap<char, Buffer>, // the buffer each uppercase mark is in
+    pub(crate) inserted: String,                  // text typed since insert mode started, for .
+    pub(crate) clipboard: Clipboard,
+    search_id: usize,
     pub is_quit: bool,
 }
 
@@ -441,6 +537,12 @@ impl Editor {
             layout: layout,
             registers: Registers::default(),
             variables: Variables::default(),
+            quickfix: Quickfix::default(),
+            replace: ReplacePreview::default(),
+            file_marks: HashMap::new(),
+            inserted: String::new(),
+            clipboard: Clipboard::from_env(),
+            search_id: 0,
             is_quit: false,
         }
     }
@@ -456,7 +558,16 @@ impl Editor {
     }
 
     pub fn generate_commands(&mut self) -> Vec<DrawCommand> {
-        self.layout.generate_commands()
+        let mut out = self.layout.generate_commands();
+        for (selection, s) in self.clipboard.take_pending() {
+            out.push(DrawCommand::Clipboard(selection, s));
+        }
+        out
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) -> &mut Self {
+        self.clipboard = clipboard;
+        self
     }
 
     pub fn resize(&mut self, view: ViewPos) {
@@ -466,37 +577,59 @@ impl Editor {
     pub fn command(&mut self, c: &Command) -> Vec<Command> {
         use Command::*;
 
+        if *c == CliExec {
+            self.command_history();
+        }
+        self.insert_record(c);
+
         // pass the command to the layout
-        self.layout.get_buffer_mut().command(c);
+        let mut out = self.layout.command(c);
 
-        match c {
+        let mut result = match c {
             Join => {
                 self.layout.get_buffer_mut().join_line().update();
                 vec![]
             }
-            Delete(reps, m) => {
-                self.layout
-                    .get_buffer_mut()
-                    .delete_motion(m, *reps)
-                    .update();
+            Delete(reps, reg, m) => self.register_delete(*reps, reg, m),
+            Yank(reg, m) => self.register_yank(reg, m),
+            ChangeCase(reps, case, m) => {
+                let block = self.layout.get_buffer_mut();
+                block.change_case(m, *reps, *case).update();
                 vec![]
             }
-            Yank(reg, m) => {
-                self.registers
-                    .update(reg, &self.layout.get_buffer_mut().motion_slice(m));
-                self.update();
+            LineOp(reps, op, m) => {
+                let block = self.layout.get_buffer_mut();
+                block.line_op(*op, m, *reps).update();
                 vec![]
             }
-            Paste(reps, reg, m) => {
-                let s = self.registers.get(reg);
-                self.layout
-                    .get_buffer_mut()
-                    .paste_motion(m, &s, *reps)
-                    .update();
+            VisualSwap => {
+                self.layout.get_buffer_mut().visual_swap().update();
+                vec![]
+            }
+            VisualRestore => {
+                self.layout.get_buffer_mut().visual_restore().update();
                 vec![]
             }
+            Paste(reps, reg, m) => self.register_paste(*reps, reg, m),
+            Registers(names) => self.registers_list(names),
+            Macro(commands) => self.macro_play(commands),
             Motion(reps, m) => {
-                self.layout.get_buffer_mut().motion(m, *reps).update();
+                if self.mark_jump(m) {
+                    let block = self.layout.get_buffer_mut();
+                    let (_, cursor) = block.cursor_motion(m, *reps);
+                    block.cursor_move(cursor).update();
+                } else {
+                    self.layout.get_buffer_mut().motion(m, *reps).update();
+                }
+                vec![]
+            }
+            MarkSet(ch) => self.mark_set(*ch),
+            DelMarks(marks) => self.mark_delete(marks),
+            Marks => self.marks_list(),
+            Jump(count) => self.jump(*count),
+            Jumps => self.jumps_list(),
+            ChangeList(count) => {
+                self.layout.get_buffer_mut().change_jump(*count).update();
                 vec![]
             }
             ScrollPage(ratio) => {
@@ -515,10 +648,15 @@ impl Editor {
                     .update_from_start();
                 vec![]
             }
+            ScrollCursor(m) => {
+                self.layout.get_buffer_mut().scroll_cursor(m).update();
+                vec![]
+            }
             Line(line_number) => {
                 let line_inx = line_number - 1;
                 self.layout
                     .get_buffer_mut()
+                    .jump_from()
                     .cursor_move_line(line_inx)
                     .update();
                 vec![]
@@ -544,6 +682,74 @@ impl Editor {
                 vec![]
             }
 
+            Grep(pattern, paths) => match compile_pattern(pattern) {
+                Ok(_) => {
+                    self.search_id += 1;
+                    let id = self.search_id;
+                    self.quickfix.reset(id);
+                    self.layout.show_buffer(&self.quickfix.buf);
+                    self.layout.output(&format!("grep: {}", pattern));
+                    vec![GrepSearch(id, pattern.clone(), paths.clone())]
+                }
+                Err(err) => {
+                    self.layout.output(&format!("{:?}", err));
+                    vec![]
+                }
+            },
+            Replace(pattern, replacement, all, paths) => match compile_pattern(pattern) {
+                Ok(re) => {
+                    self.search_id += 1;
+                    let id = self.search_id;
+                    self.replace
+                        .reset(id, re, &expand_replacement(replacement), *all);
+                    self.layout.show_buffer(&self.replace.buf);
+                    self.layout.output(&format!("replace: {}", pattern));
+                    vec![GrepSearch(id, pattern.clone(), paths.clone())]
+                }
+                Err(err) => {
+                    self.layout.output(&format!("{:?}", err));
+                    vec![]
+                }
+            },
+            ReplaceApply => self.replace_apply(),
+            GrepMatch(id, m) => {
+                if self.quickfix.add(*id, m) || self.replace.add(*id, m) {
+                    self.layout.get_buffer_mut().update();
+                }
+                vec![]
+            }
+            GrepDone(id, count) => {
+                if *id == self.quickfix.id {
+                    self.layout.output(&format!("grep: {} matches", count));
+                } else if *id == self.replace.id {
+                    self.layout.output(&format!(
+                        "replace: {} lines, enter toggles, :replace! applies",
+                        count
+                    ));
+                }
+                vec![]
+            }
+            QuickfixNext(dx) => {
+                let commands = self.quickfix.next(*dx);
+                if commands.is_empty() {
+                    self.layout.output("no matches");
+                }
+                commands
+            }
+            Select => {
+                let block = self.layout.get_buffer();
+                let line_inx = block.cursor.line_inx;
+                if block.buf.is_same(&self.quickfix.buf) {
+                    self.quickfix.open(line_inx)
+                } else if block.buf.is_same(&self.replace.buf) {
+                    self.replace.toggle(line_inx);
+                    self.layout.get_buffer_mut().update();
+                    vec![]
+                } else {
+                    self.command(&Motion(1, editor_core::Motion::NextLine))
+                }
+            }
+
             Save => {
                 let block = self.layout.get_buffer();
                 let text = block.get_text();
@@ -573,14 +779,19 @@ impl Editor {
                 vec![]
             }
 
-            Mode(_) => {
+            Mode(m) => {
+                self.layout.get_buffer_mut().set_mode(*m).update();
                 vec![]
             }
 
+            Ex(ex) => self.ex_command(ex),
+
             _ => {
                 vec![]
             }
-        }
+        };
+        out.append(&mut result);
+        out
     }
 }
 
@@ -637,4 +848,170 @@ mod tests {
         info!("C: {:?}", &commands);
         println!("C: {:?}", &commands);
     }
+
+    #[test]
+    fn test_grep_list() {
+        use crate::testing::editor;
+        let (mut e, fb) = editor("");
+
+        use Command::*;
+        let search = e.command(&Grep("foo".into(), vec![]));
+        assert_eq!(search, vec![GrepSearch(1, "foo".into(), vec![])]);
+        let m = |path: &str, line| editor_core::GrepMatch {
+            path: path.into(),
+            line,
+            col: 1,
+            text: "foo".into(),
+        };
+        e.command(&GrepMatch(1, m("a.rs", 3)));
+        e.command(&GrepMatch(1, m("b.rs", 5)));
+        // results from an old search are ignored
+        e.command(&GrepMatch(0, m("c.rs", 1)));
+        assert_eq!(
+            e.layout.get_buffer().get_text().to_string(),
+            "a.rs:3:1: foo\nb.rs:5:1: foo\n"
+        );
+        // the list isn't undoable
+        e.command(&Undo);
+        assert_eq!(
+            e.layout.get_buffer().get_text().to_string(),
+            "a.rs:3:1: foo\nb.rs:5:1: foo\n"
+        );
+
+        e.command(&Motion(1, editor_core::Motion::Down));
+        assert_eq!(e.command(&Select), vec![Open("b.rs".into()), Line(5)]);
+        assert_eq!(
+            e.command(&QuickfixNext(-1)),
+            vec![Open("a.rs".into()), Line(3)]
+        );
+        assert_eq!(
+            e.command(&QuickfixNext(-1)),
+            vec![Open("a.rs".into()), Line(3)]
+        );
+
+        // enter moves to the next line outside of the list
+        e.command(&BufferNext);
+        assert!(e.layout.get_buffer().buf.is_same(&fb));
+        assert_eq!(e.command(&Select), vec![]);
+    }
+
+    #[test]
+    fn test_change_repeat() {
+        use crate::testing::{editor, keys};
+        use editor_bindings::range::{range_enter, range_string, Elem};
+        use editor_bindings::InputReader;
+        let (mut e, buf) = editor("a b c d e f\na b\na b\n");
+        let mut reader = InputReader::default();
+        reader.registers = e.registers();
+
+        // a count on . replaces the count of the operator
+        keys(&mut e, &mut reader, range_string("dw."));
+        assert_eq!(buf.get_text(), "c d e f\na b\na b\n");
+        keys(&mut e, &mut reader, range_string("2."));
+        assert_eq!(buf.get_text(), "e f\na b\na b\n");
+
+        // a whole insert session, played count times
+        keys(&mut e, &mut reader, range_string("ix-"));
+        keys(&mut e, &mut reader, vec![Elem::Esc]);
+        keys(&mut e, &mut reader, range_string("2."));
+        assert_eq!(buf.get_text(), "x-x-x-e f\na b\na b\n");
+
+        // ex commands that change the buffer
+        keys(&mut e, &mut reader, range_enter("j:s/a/A/"));
+        keys(&mut e, &mut reader, range_string("j."));
+        assert_eq!(buf.get_text(), "x-x-x-e f\nA b\nA b\n");
+    }
+
+    #[test]
+    fn test_case_increment() {
+        use crate::testing::{editor, keys};
+        use editor_bindings::range::{range_string, Elem};
+        use editor_bindings::InputReader;
+        let (mut e, buf) = editor("stra\u{df}e x9 y\nab\n");
+        let mut reader = InputReader::default();
+
+        // full unicode mapping can make the word longer, and it's one undo
+        keys(&mut e, &mut reader, range_string("gUiw"));
+        assert_eq!(buf.get_text(), "STRASSE x9 y\nab\n");
+        keys(&mut e, &mut reader, range_string("u"));
+        assert_eq!(buf.get_text(), "stra\u{df}e x9 y\nab\n");
+        keys(&mut e, &mut reader, range_string("gUiw"));
+        assert_eq!(buf.get_text(), "STRASSE x9 y\nab\n");
+        keys(&mut e, &mut reader, range_string("2guu"));
+        assert_eq!(buf.get_text(), "strasse x9 y\nab\n");
+
+        // ~ moves past what it changed
+        keys(&mut e, &mut reader, range_string("3~"));
+        assert_eq!(buf.get_text(), "STRasse x9 y\nab\n");
+
+        // the number after the cursor, with a count
+        keys(&mut e, &mut reader, range_string("5"));
+        keys(&mut e, &mut reader, vec![Elem::Control('a')]);
+        assert_eq!(buf.get_text(), "STRasse x14 y\nab\n");
+        // a count for . replaces the count
+        keys(&mut e, &mut reader, range_string("3."));
+        assert_eq!(buf.get_text(), "STRasse x17 y\nab\n");
+        keys(&mut e, &mut reader, range_string("20"));
+        keys(&mut e, &mut reader, vec![Elem::Control('x')]);
+        keys(&mut e, &mut reader, range_string("ix"));
+        keys(&mut e, &mut reader, vec![Elem::Esc]);
+        assert_eq!(buf.get_text(), "STRasse x-x3 y\nab\n");
+        keys(&mut e, &mut reader, range_string("j^"));
+        keys(&mut e, &mut reader, range_string("2~"));
+        assert_eq!(buf.get_text(), "STRasse x-x3 y\nAB\n");
+        keys(&mut e, &mut reader, range_string("u"));
+        assert_eq!(buf.get_text(), "STRasse x-x3 y\nab\n");
+    }
+
+    #[test]
+    fn test_line_ops() {
+        use crate::testing::{editor, keys};
+        use editor_bindings::range::{range_enter, range_string};
+        use editor_bindings::InputReader;
+        let (mut e, buf) = editor("fn a() {\nb();\n}\n// one two three four\n");
+        let mut reader = InputReader::default();
+
+        // shifts are a single undo, = follows the brackets
+        keys(&mut e, &mut reader, range_string("2>>"));
+        assert_eq!(
+            buf.get_text(),
+            "    fn a() {\n    b();\n}\n// one two three four\n"
+        );
+        keys(&mut e, &mut reader, range_string("u=ip"));
+        assert_eq!(
+            buf.get_text(),
+            "fn a() {\n    b();\n}\n// one two three four\n"
+        );
+
+        // gq keeps the comment leader on every line
+        keys(&mut e, &mut reader, range_enter(":set tw=12"));
+        keys(&mut e, &mut reader, range_string("Ggqq"));
+        assert_eq!(
+            buf.get_text(),
+            "fn a() {\n    b();\n}\n// one two\n// three\n// four\n"
+        );
+
+        // gc uses the comment string of the filetype, and toggles back
+        keys(&mut e, &mut reader, range_enter(":set ft=rust"));
+        keys(&mut e, &mut reader, range_string("gggcj"));
+        assert_eq!(
+            buf.get_text(),
+            "// fn a() {\n//     b();\n}\n// one two\n// three\n// four\n"
+        );
+        keys(&mut e, &mut reader, range_string("Vjgc"));
+        assert_eq!(
+            buf.get_text(),
+            "fn a() {\n    b();\n}\n// one two\n// three\n// four\n"
+        );
+        keys(&mut e, &mut reader, range_string("3gcc"));
+        assert_eq!(
+            buf.get_text(),
+            "// fn a() {\n//     b();\n// }\n// one two\n// three\n// four\n"
+        );
+        keys(&mut e, &mut reader, range_string("u"));
+        assert_eq!(
+            buf.get_text(),
+            "fn a() {\n    b();\n}\n// one two\n// three\n// four\n"
+        );
+    }
 }
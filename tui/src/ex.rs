@@ -0,0 +1,370 @@
+use crate::editor::Editor;
+use editor_bindings::range::{range_string, Elem};
+use editor_bindings::InputReader;
+use editor_core::ex::{compile_pattern, expand_replacement};
+use editor_core::{
+    Address, Command, ExCommand, ExContext, ExError, ExOp, LineRange, Register, RegisterKind,
+    RegisterValue,
+};
+use log::*;
+use ropey::Rope;
+
+// lines removed and inserted at a line index, so pending line numbers can be adjusted
+struct LineSplice {
+    at: usize,
+    removed: usize,
+    inserted: usize,
+}
+
+impl LineSplice {
+    fn new(at: usize, removed: usize, inserted: usize) -> Self {
+        Self {
+            at,
+            removed,
+            inserted,
+        }
+    }
+
+    // remove lines that were deleted, and shift the lines that follow
+    fn apply(&self, lines: &mut Vec<usize>) {
+        let end = self.at + self.removed;
+        lines.retain(|line| *line < self.at || *line >= end);
+        lines
+            .iter_mut()
+            .filter(|line| **line >= end)
+            .for_each(|line| *line = *line - self.removed + self.inserted);
+    }
+}
+
+fn default_range(op: &ExOp) -> LineRange {
+    match op {
+        ExOp::Global(_, _, _) | ExOp::Retab(_, _) | ExOp::Reindent(_) => LineRange::All,
+        _ => LineRange::Single(Address::Current),
+    }
+}
+
+impl Editor {
+    // run an ex command against the current buffer, the whole command is a single undo
+    pub fn ex_command(&mut self, ex: &ExCommand) -> Vec<Command> {
+        let mut out = vec![];
+        // the marks for a visual selection stay, the selection itself ends
+        self.layout.get_buffer_mut().visual_end().buf.change_start();
+        let result = self.ex_run(ex, &mut out);
+        self.layout.get_buffer_mut().buf.change_end();
+        self.layout.get_buffer_mut().update();
+        if let Err(err) = result {
+            error!("ex: {:?}", (ex, &err));
+            self.layout.output(&format!("{:?}", err));
+        }
+        out
+    }
+
+    fn ex_run(
+        &mut self,
+        ex: &ExCommand,
+        out: &mut Vec<Command>,
+    ) -> Result<Vec<LineSplice>, ExError> {
+        let block = self.layout.get_buffer_mut();
+        let text = block.get_text();
+        let current = block.cursor.line_inx;
+        let mut ctx = ExContext::new(&text, current);
+        ctx.marks = block.marks();
+        let range = ex.range.clone().unwrap_or_else(|| default_range(&ex.op));
+        let (start, end) = range.resolve(&ctx)?;
+        let count = end - start + 1;
+        let lines = text.slice(text.line_to_char(start)..text.line_to_char(end + 1));
+
+        match &ex.op {
+            ExOp::Goto => {
+                block.jump_from().cursor_move_line(end as i64);
+                Ok(vec![])
+            }
+
+            ExOp::Delete(reg) => {
+                self.registers.delete(
+                    &Register(reg.unwrap_or('"')),
+                    RegisterValue::new(&lines.to_string(), RegisterKind::Linewise),
+                );
+                let block = self.layout.get_buffer_mut();
+                block.buf.delete_line_range(start, end + 1);
+                let last = editor_core::ex::last_line(&block.get_text());
+                block.cursor_move_line(std::cmp::min(start, last) as i64);
+                Ok(vec![LineSplice::new(start, count, 0)])
+            }
+
+            ExOp::Yank(reg) => {
+                self.registers.yank(
+                    &Register(reg.unwrap_or('"')),
+                    RegisterValue::new(&lines.to_string(), RegisterKind::Linewise),
+                );
+                Ok(vec![])
+            }
+
+            ExOp::Join => {
+                // a single line joins with the line after it
+                let joins = std::cmp::max(1, end - start);
+                if start + joins > editor_core::ex::last_line(&text) {
+                    return Err(ExError::InvalidRange);
+                }
+                for _ in 0..joins {
+                    block.buf.join_line(start);
+                }
+                block.cursor_move_line(start as i64);
+                Ok(vec![LineSplice::new(start, joins + 1, 1)])
+            }
+
+            ExOp::Shift(levels) => {
+                for line_inx in start..=end {
+                    block.buf.shift_line(line_inx, *levels);
+                }
+                block.cursor_move_line(end as i64);
+                Ok(vec![])
+            }
+
+            ExOp::Retab(bang, tab_width) => {
+                let old = block.get_config().tab_width as usize;
+                if let Some(n) = tab_width {
+                    block
+                        .set_option("tabstop", &n.to_string())
+                        .map_err(|_| ExError::InvalidArgument(n.to_string()))?;
+                }
+                block.buf.retab_lines(start, end + 1, old, *bang);
+                Ok(vec![])
+            }
+
+            ExOp::Reindent(from) => {
+                let from = from.unwrap_or_else(|| block.get_config().indent_width());
+                block.buf.relevel_lines(start, end + 1, from);
+                Ok(vec![])
+            }
+
+            ExOp::Write(path) => {
+                let path = path.clone().unwrap_or_else(|| block.get_path());
+                out.push(Command::SaveBuffer(path, Rope::from(lines)));
+                Ok(vec![])
+            }
+
+            ExOp::Substitute(pattern, replacement, all) => {
+                let re = compile_pattern(pattern)?;
+                let replacement = expand_replacement(replacement);
+                let block = self.layout.get_buffer_mut();
+                let mut last = None;
+                for line_inx in start..=end {
+                    if block.buf.substitute_line(line_inx, &re, &replacement, *all) {
+                        last = Some(line_inx);
+                    }
+                }
+                match last {
+                    Some(line_inx) => {
+                        block.cursor_move_line(line_inx as i64);
+                        Ok(vec![])
+                    }
+                    None => Err(ExError::PatternNotFound(pattern.clone())),
+                }
+            }
+
+            ExOp::Move(address) => {
+                let dest = address.resolve_dest(&ctx)?;
+                if dest > start && dest <= end {
+                    return Err(ExError::InvalidRange);
+                }
+                let block = self.layout.get_buffer_mut();
+                block.buf.move_line_range(start, end + 1, dest);
+                let at = if dest > end { dest - count } else { dest };
+                block.cursor_move_line((at + count - 1) as i64);
+                Ok(vec![
+                    LineSplice::new(start, count, 0),
+                    LineSplice::new(at, 0, count),
+                ])
+            }
+
+            ExOp::Copy(address) => {
+                let dest = address.resolve_dest(&ctx)?;
+                let block = self.layout.get_buffer_mut();
+                block.buf.copy_line_range(start, end + 1, dest);
+                block.cursor_move_line((dest + count - 1) as i64);
+                Ok(vec![LineSplice::new(dest, 0, count)])
+            }
+
+            ExOp::Normal(keys) => {
+                let mut lines = (start..=end).collect::<Vec<usize>>();
+                let mut splices = vec![];
+                while !lines.is_empty() {
+                    let line_inx = lines.remove(0);
+                    let splice = self.ex_normal(line_inx, keys, out);
+                    splice.apply(&mut lines);
+                    splices.push(splice);
+                }
+                Ok(splices)
+            }
+
+            ExOp::Global(pattern, invert, command) => {
+                let re = compile_pattern(pattern)?;
+                let mut lines = (start..=end)
+                    .filter(|line_inx| re.is_match(&text.line(*line_inx).to_string()) != *invert)
+                    .collect::<Vec<usize>>();
+                if lines.is_empty() {
+                    return Err(ExError::PatternNotFound(pattern.clone()));
+                }
+
+                let mut splices = vec![];
+                while !lines.is_empty() {
+                    let line_inx = lines.remove(0);
+                    self.layout
+                        .get_buffer_mut()
+                        .cursor_move_line(line_inx as i64);
+                    match self.ex_run(command, out) {
+                        Ok(result) => {
+                            result.iter().for_each(|splice| splice.apply(&mut lines));
+                            splices.extend(result);
+                        }
+                        // not every marked line has to match the command
+                        Err(ExError::PatternNotFound(_)) => (),
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(splices)
+            }
+        }
+    }
+
+    // run normal mode keys with the cursor at the start of a line
+    fn ex_normal(&mut self, line_inx: usize, keys: &str, out: &mut Vec<Command>) -> LineSplice {
+        let lines_before = self.layout.get_buffer_mut().get_text().len_lines();
+        self.layout
+            .get_buffer_mut()
+            .cursor_move_line(line_inx as i64)
+            .update();
+
+        // a fresh reader starts in normal mode, and escape leaves any pending mode
+        let mut reader = InputReader::default();
+        let mut elements = range_string(keys);
+        elements.push(Elem::Esc);
+        for e in elements {
+            for c in reader.add(e) {
+                out.append(&mut self.command(&c));
+            }
+        }
+
+        // normal mode can do anything, assume the change happened at the current line
+        let lines_after = self.layout.get_buffer_mut().get_text().len_lines();
+        if lines_after >= lines_before {
+            LineSplice::new(line_inx, 1, 1 + lines_after - lines_before)
+        } else {
+            LineSplice::new(line_inx, 1 + lines_before - lines_after, 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::*;
+    use crate::testing::editor;
+    use editor_bindings::command_parse;
+    use editor_core::{Command, Register};
+    use ropey::Rope;
+
+    fn ex(e: &mut Editor, s: &str) {
+        for c in command_parse(s).unwrap() {
+            e.command(&c);
+        }
+    }
+
+    #[test]
+    fn test_global_delete() {
+        let (mut e, buf) = editor("a1\nb\na2\na3\nc\n");
+        ex(&mut e, "g/a/d");
+        assert_eq!(buf.get_text().to_string(), "b\nc\n");
+        e.command(&Command::Undo);
+        assert_eq!(buf.get_text().to_string(), "a1\nb\na2\na3\nc\n");
+
+        ex(&mut e, "v/a/d");
+        assert_eq!(buf.get_text().to_string(), "a1\na2\na3\n");
+    }
+
+    #[test]
+    fn test_global_move() {
+        // reverse the lines in the buffer
+        let (mut e, buf) = editor("1\n2\n3\n4\n");
+        ex(&mut e, "g/^/m0");
+        assert_eq!(buf.get_text().to_string(), "4\n3\n2\n1\n");
+        e.command(&Command::Undo);
+        assert_eq!(buf.get_text().to_string(), "1\n2\n3\n4\n");
+    }
+
+    #[test]
+    fn test_global_copy_substitute() {
+        let (mut e, buf) = editor("x a\ny\nx b\n");
+        ex(&mut e, "g/x/t.");
+        assert_eq!(buf.get_text().to_string(), "x a\nx a\ny\nx b\nx b\n");
+        ex(&mut e, "2,$g/x/s/x (\\w)/[\\1]/");
+        assert_eq!(buf.get_text().to_string(), "x a\n[a]\ny\n[b]\n[b]\n");
+    }
+
+    #[test]
+    fn test_global_normal() {
+        let (mut e, buf) = editor("a\nb\na\n");
+        ex(&mut e, "g/a/normal dd");
+        assert_eq!(buf.get_text().to_string(), "b\n");
+        e.command(&Command::Undo);
+        assert_eq!(buf.get_text().to_string(), "a\nb\na\n");
+        ex(&mut e, "%norm ix");
+        assert_eq!(buf.get_text().to_string(), "xa\nxb\nxa\n");
+    }
+
+    #[test]
+    fn test_ranges() {
+        let (mut e, buf) = editor("a\nb\nc\nd\ne\n");
+        ex(&mut e, "3");
+        ex(&mut e, "-,.d");
+        assert_eq!(buf.get_text().to_string(), "a\nd\ne\n");
+        e.command(&Command::Undo);
+        ex(&mut e, "/d/,$j");
+        assert_eq!(buf.get_text().to_string(), "a\nb\nc\nde\n");
+        ex(&mut e, "1,2>");
+        ex(&mut e, "2<");
+        assert_eq!(buf.get_text().to_string(), "    a\nb\nc\nde\n");
+        ex(&mut e, "1j");
+        assert_eq!(buf.get_text().to_string(), "    ab\nc\nde\n");
+        ex(&mut e, "2,$m0");
+        assert_eq!(buf.get_text().to_string(), "c\nde\n    ab\n");
+    }
+
+    #[test]
+    fn test_yank_write() {
+        let (mut e, _buf) = editor("a\nb\nc\n");
+        ex(&mut e, "2,3y");
+        assert_eq!(e.registers.get(&Register('"')), "b\nc\n");
+        let commands = command_parse("$-1,$w out.txt").unwrap();
+        assert_eq!(
+            e.command(&commands[0]),
+            vec![Command::SaveBuffer("out.txt".into(), Rope::from("b\nc\n"))]
+        );
+    }
+
+    #[test]
+    fn test_retab_reindent() {
+        let (mut e, buf) = editor("\tif a {\n\t\tb  \tc\n\t}\n");
+        ex(&mut e, "retab 2");
+        assert_eq!(
+            buf.get_text().to_string(),
+            "    if a {\n        b   c\n    }\n"
+        );
+        e.command(&Command::Undo);
+        assert_eq!(buf.get_text().to_string(), "\tif a {\n\t\tb  \tc\n\t}\n");
+
+        // only the indent, then levels of eight columns become levels of four
+        ex(&mut e, "set ts=4");
+        ex(&mut e, "set rtl");
+        ex(&mut e, "retab");
+        assert_eq!(
+            buf.get_text().to_string(),
+            "    if a {\n        b  \tc\n    }\n"
+        );
+        ex(&mut e, "2reindent 8");
+        assert_eq!(
+            buf.get_text().to_string(),
+            "    if a {\n    b  \tc\n    }\n"
+        );
+    }
+}
@@ -8,6 +8,7 @@ pub struct FormatIterator<'a> {
     grapheme_iter: Graphemes<'a>,
     inx: usize,
     highlight: String,
+    select: (usize, usize),
     config: &'a BufferConfig,
 }
 
@@ -41,11 +42,19 @@ impl<'a> Iterator for FormatIterator<'a> {
                 }
             }
 
+            // graphemes in the visual selection
+            let selected = self.inx >= self.select.0 && self.inx < self.select.1;
+
             match self.grapheme_iter.next() {
                 Some(ch) => {
                     self.inx += 1;
                     let mut items = grapheme_to_format_item(ch, &self.config, highlight);
-                    items.pop()
+                    items.pop().map(|mut item| {
+                        if selected {
+                            item.format = LineFormatType::Select;
+                        }
+                        item
+                    })
                 }
                 None => None,
             }
@@ -60,6 +69,7 @@ impl<'a> FormatIterator<'a> {
             grapheme_iter: line.graphemes(true),
             inx,
             highlight,
+            select: (0, 0),
             config,
         }
     }
@@ -70,8 +80,20 @@ pub fn format_wrapped(
     sx: usize,
     highlight: String,
     config: &BufferConfig,
+) -> Vec<Vec<LineFormat>> {
+    format_wrapped_select(line, sx, highlight, (0, 0), config)
+}
+
+// format a wrapped line, with the graphemes in select shown as selected
+pub fn format_wrapped_select(
+    line: &String,
+    sx: usize,
+    highlight: String,
+    select: (usize, usize),
+    config: &BufferConfig,
 ) -> Vec<Vec<LineFormat>> {
     let mut it = FormatIterator::new(line, 0, highlight, config);
+    it.select = select;
     let mut out = vec![];
     let mut format = LineFormatType::Normal;
     let mut acc = String::from("");
@@ -0,0 +1,189 @@
+use crate::editor::Editor;
+use editor_core::{Buffer, Command};
+
+const MAX_JUMPS: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct Jump {
+    pub buf: Buffer,
+    pub c: usize,
+}
+
+impl Jump {
+    pub fn new(buf: &Buffer, c: usize) -> Self {
+        Self {
+            buf: buf.clone(),
+            c: std::cmp::min(c, buf.get_text().len_chars()),
+        }
+    }
+
+    pub fn line_inx(&self) -> usize {
+        let text = self.buf.get_text();
+        text.char_to_line(std::cmp::min(self.c, text.len_chars()))
+    }
+
+    fn same_line(&self, other: &Jump) -> bool {
+        self.buf.is_same(&other.buf) && self.line_inx() == other.line_inx()
+    }
+}
+
+// positions before large jumps, walked with ctrl-o and ctrl-i
+#[derive(Debug, Clone, Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    current: usize, // equal to the length when not walking the list
+}
+
+impl JumpList {
+    // a jump to the same line as an earlier one replaces it
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps.retain(|j| !j.same_line(&jump));
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    // go back count jumps, from is where the cursor is now so ctrl-i can return to it
+    pub fn back(&mut self, from: Jump, count: usize) -> Option<Jump> {
+        if self.current >= self.jumps.len() {
+            self.push(from);
+            self.current = self.jumps.len() - 1;
+        }
+        if count == 0 || count > self.current {
+            return None;
+        }
+        self.current -= count;
+        self.jumps.get(self.current).cloned()
+    }
+
+    pub fn forward(&mut self, count: usize) -> Option<Jump> {
+        if count == 0 || self.current + count >= self.jumps.len() {
+            return None;
+        }
+        self.current += count;
+        self.jumps.get(self.current).cloned()
+    }
+
+    // the jumps, and the index of the current one
+    pub fn list(&self) -> (&[Jump], usize) {
+        (&self.jumps, self.current)
+    }
+}
+
+impl Editor {
+    // ctrl-o and ctrl-i, negative counts go back, a jump into another buffer switches to it
+    pub fn jump(&mut self, count: i32) -> Vec<Command> {
+        let block = self.layout.get_buffer_mut();
+        let from = Jump::new(&block.buf, block.cursor.c);
+        let jump = if count < 0 {
+            block.jumps.back(from, count.unsigned_abs() as usize)
+        } else {
+            block.jumps.forward(count as usize)
+        };
+        if let Some(j) = jump {
+            if !j.buf.is_same(&self.layout.get_buffer().buf) {
+                self.layout.jump_to_buffer(&j.buf);
+            }
+            self.layout.get_buffer_mut().move_to_char(j.c).update();
+        }
+        vec![]
+    }
+
+    // :jumps, with > at the current position in the list
+    pub fn jumps_list(&mut self) -> Vec<Command> {
+        let block = self.layout.get_buffer();
+        let current = block.buf.clone();
+        let (jumps, inx) = block.jumps.list();
+        let mut out = String::from(" jump line  col file/text\n");
+        for (i, j) in jumps.iter().enumerate() {
+            let text = j.buf.get_text();
+            let line_inx = j.line_inx();
+            let col = j.c - text.line_to_char(line_inx);
+            let detail = if j.buf.is_same(&current) {
+                text.line(line_inx).to_string().trim_end().to_string()
+            } else {
+                j.buf.get_path()
+            };
+            out.push_str(&format!(
+                "{}{:>4} {:>4} {:>4} {}\n",
+                if i == inx { ">" } else { " " },
+                (i as i64 - inx as i64).abs(),
+                line_inx + 1,
+                col,
+                detail
+            ));
+        }
+        if inx >= jumps.len() {
+            out.push_str(">\n");
+        }
+        self.show_list("[jumps]", &out);
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jumplist() {
+        let a = Buffer::from_string(&"a\nb\nc\nd\n".to_string());
+        let b = Buffer::from_string(&"x\ny\n".to_string());
+        let mut jumps = JumpList::default();
+        jumps.push(Jump::new(&a, 0));
+        jumps.push(Jump::new(&a, 4));
+        jumps.push(Jump::new(&b, 2));
+        // the same line again moves the jump to the end
+        jumps.push(Jump::new(&a, 0));
+        assert_eq!(jumps.list().0.len(), 3);
+
+        let back = jumps.back(Jump::new(&b, 0), 1).unwrap();
+        assert_eq!(back.c, 0);
+        assert!(back.buf.is_same(&a));
+        let back = jumps.back(Jump::new(&a, 0), 1).unwrap();
+        assert!(back.buf.is_same(&b));
+        assert!(jumps.back(Jump::new(&b, 2), 5).is_none());
+
+        let forward = jumps.forward(2).unwrap();
+        assert!(forward.buf.is_same(&b));
+        assert_eq!(forward.c, 0);
+        assert!(jumps.forward(1).is_none());
+    }
+
+    #[test]
+    fn test_jump_buffers() {
+        use crate::testing::editor_with;
+        use editor_core::Motion;
+        let a = Buffer::from_string(&"a1\na2\na3\n".to_string());
+        let b = Buffer::from_string(&"b1\nb2\n".to_string());
+        let mut e = editor_with(&[b.clone(), a.clone()]);
+
+        use Command as C;
+        e.command(&C::Line(0));
+        e.command(&C::Jump(-1));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 0);
+        e.command(&C::Jump(1));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 2);
+
+        // a jump to a file mark can be walked back across buffers
+        e.command(&C::Line(2));
+        e.command(&C::MarkSet('A'));
+        e.command(&C::BufferNext);
+        assert!(e.layout.get_buffer().buf.is_same(&b));
+        e.command(&C::Motion(1, Motion::Down));
+        e.command(&C::Motion(1, Motion::MarkLine('A')));
+        assert!(e.layout.get_buffer().buf.is_same(&a));
+        e.command(&C::Jump(-1));
+        assert!(e.layout.get_buffer().buf.is_same(&b));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 1);
+        e.command(&C::Jump(1));
+        assert!(e.layout.get_buffer().buf.is_same(&a));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 1);
+
+        e.command(&C::Jumps);
+        let list = e.layout.get_buffer().get_text().to_string();
+        assert!(list.contains(">   0    2    0 a2\n"), "{}", list);
+    }
+}
@@ -1,8 +1,10 @@
 use super::*;
-use crate::editor::Editor;
+use crate::editor::{same_file, Editor};
 use crossbeam::channel;
 use crossbeam::thread;
 use editor_bindings::InputReader;
+use editor_core::ex::compile_pattern;
+use editor_core::grep::grep_paths;
 use editor_core::{Buffer, BufferConfig, Command, ViewPos};
 use log::*;
 use ropey::Rope;
@@ -43,14 +45,17 @@ impl BufferWindow {
         self.main.update();
 
         let path = self.main.get_path();
+        let config = self.main.get_config();
         // update status
         let s = format!(
-            "DEBUG: [{},{}] xh:{} S:{} {} {:?}{:width$}",
+            "DEBUG: [{},{}] xh:{} S:{} {} {} {} {:?}{:width$}",
             self.main.rc.cx,
             self.main.rc.cy,
             self.main.cursor.x_hint,
             &self.main.cursor.simple_format(),
             path,
+            config.filetype,
+            config.indent_status(),
             (
                 self.main.view.w,
                 self.main.view.h,
@@ -159,12 +164,41 @@ impl WindowLayout {
         b
     }
 
+    // switch to the window showing the buffer, adding a window if there isn't one
+    pub fn show(&mut self, buf: &Buffer) -> &mut Self {
+        if !self.buffers.find(|w| w.main.buf.is_same(buf)) {
+            let mut bufwin = BufferWindow::new(buf.clone(), self.view.clone());
+            bufwin.main.set_focus(true);
+            self.buffers.add(bufwin);
+        }
+        self.buffers.get_mut().clear();
+        self
+    }
+
+    // switch windows for a jump, the jump list moves with it as if the window changed buffers
+    pub fn jump_to(&mut self, buf: &Buffer) -> &mut Self {
+        let jumps = std::mem::take(&mut self.buffers.get_mut().main.jumps);
+        self.show(buf);
+        self.buffers.get_mut().main.jumps = jumps;
+        self
+    }
+
+    // the buffer for a file, if it's open in a window
+    pub fn find_buffer(&self, path: &str) -> Option<Buffer> {
+        self.buffers
+            .elements
+            .iter()
+            .find(|w| same_file(&w.main.get_path(), path))
+            .map(|w| w.main.buf.clone())
+    }
+
     pub fn resize(&mut self, view: ViewPos) {
         // each buffer needs to be resized on resize event
         // because each one caches things that depend on the size
         self.buffers.elements.iter_mut().for_each(|e| {
             e.resize(view.clone());
         });
+        self.view = view;
     }
 
     pub fn clear(&mut self) -> &mut Self {
@@ -178,6 +212,8 @@ use signal_hook::consts::TERM_SIGNALS;
 use signal_hook::flag;
 
 pub fn event_loop(editor: Editor, reader: &mut InputReader) {
+    // macros are recorded in the editor's registers
+    reader.registers = editor.registers();
     use std::sync::atomic::AtomicBool;
     let term_now = Arc::new(AtomicBool::new(false));
     for sig in TERM_SIGNALS {
@@ -242,12 +278,13 @@ pub fn event_loop(editor: Editor, reader: &mut InputReader) {
 
         (0..3).for_each(|i| {
             let i = i.clone();
+            let tx = tx.clone();
             let tx_background = tx_background.clone();
             let rx_background = rx_background.clone();
-            // save thread
+            // save and search thread
             s.spawn(move |_| {
                 info!("background thread {} start", i);
-                background_thread(tx_background, rx_background);
+                background_thread(tx, tx_background, rx_background);
                 info!("background thread {} exit", i);
             });
         });
@@ -285,7 +322,7 @@ fn display_thread(
                 match c {
                     Ok(c) => {
                         match c {
-                            Command::SaveBuffer(_,_) => {
+                            Command::SaveBuffer(_,_) | Command::GrepSearch(_,_,_) => {
                                 info!("background: {:?}", c);
                                 tx_background.send(c).unwrap();
                             }
@@ -376,7 +413,27 @@ pub fn save_text(path: &String, text: &Rope) {
     info!("Wrote: {} bytes to {}", text.len_bytes(), path);
 }
 
-fn background_thread(tx: channel::Sender<Command>, rx: channel::Receiver<Command>) {
+// search files and send the matches to the display thread as they are found
+fn grep_search(tx: &channel::Sender<Command>, id: usize, pattern: &str, paths: &[String]) {
+    match compile_pattern(pattern) {
+        Ok(re) => {
+            let count = grep_paths(&re, paths, |m| {
+                tx.send(Command::GrepMatch(id, m)).unwrap();
+            });
+            tx.send(Command::GrepDone(id, count)).unwrap();
+        }
+        Err(err) => {
+            error!("grep: {:?}", err);
+            tx.send(Command::GrepDone(id, 0)).unwrap();
+        }
+    }
+}
+
+fn background_thread(
+    tx: channel::Sender<Command>,
+    tx_background: channel::Sender<Command>,
+    rx: channel::Receiver<Command>,
+) {
     loop {
         channel::select! {
             recv(rx) -> c => {
@@ -384,9 +441,12 @@ fn background_thread(tx: channel::Sender<Command>, rx: channel::Receiver<Command
                     Ok(Command::SaveBuffer(path, text)) => {
                         save_text(&path, &text);
                     }
+                    Ok(Command::GrepSearch(id, pattern, paths)) => {
+                        grep_search(&tx, id, &pattern, &paths);
+                    }
                     Ok(Command::Quit) => {
                         // repeat until all threads have quit
-                        tx.send(Command::Quit).unwrap();
+                        tx_background.send(Command::Quit).unwrap();
                         break;
                     }
                     Ok(c) => {
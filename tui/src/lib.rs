@@ -4,18 +4,29 @@ mod bufferlist;
 mod cursor;
 mod display;
 mod editor;
+mod ex;
 pub mod format;
+mod jumplist;
 mod layout;
 mod lineworker;
+mod macros;
+mod marks;
+mod quickfix;
+mod registers;
+mod replace;
 mod row;
 mod search;
 mod terminal;
+#[cfg(test)]
+mod testing;
 mod viewchar;
+mod visual;
 
 use bufferlist::*;
 use cursor::*;
 use display::*;
 use format::*;
+use jumplist::*;
 use row::*;
 use search::*;
 use terminal::*;
@@ -29,12 +29,25 @@ impl LineWorker {
         out
     }
 
+    #[cfg(test)]
     pub fn screen_from_cursor(
         text: &Rope,
         sx: usize,
         sy: usize,
         start: &Cursor,
         cursor: &Cursor,
+    ) -> (u16, u16, Vec<Cursor>) {
+        Self::screen_from_cursor_scrolloff(text, sx, sy, start, cursor, 0)
+    }
+
+    // like screen_from_cursor, but keeps scrolloff rows visible above and below the cursor
+    pub fn screen_from_cursor_scrolloff(
+        text: &Rope,
+        sx: usize,
+        sy: usize,
+        start: &Cursor,
+        cursor: &Cursor,
+        scrolloff: usize,
     ) -> (u16, u16, Vec<Cursor>) {
         // start with the current position, iterate back until we find the start, or we fill up the
         // screen
@@ -42,12 +55,15 @@ impl LineWorker {
         let mut out = Vec::new();
         let rx = cursor.rx(sx);
         let mut ry = 0;
+        // the cursor can't be in the middle of both margins on a small screen
+        let scrolloff = std::cmp::min(scrolloff, sy.saturating_sub(1) / 2);
 
         out.push(cursor.clone());
 
         let mut cp = cursor.clone();
         while out.len() < sy {
-            if cp.c <= start.c {
+            let at_start = (cp.line_inx, cp.wrap0) <= (start.line_inx, start.wrap0);
+            if at_start && ry >= scrolloff {
                 break;
             }
             match cursor_visual_prev_line(text, sx, &cp) {
@@ -61,16 +77,21 @@ impl LineWorker {
         }
 
         let mut cn = cursor.clone();
-        while out.len() < sy {
+        while out.len() < sy || out.len() - 1 - ry < scrolloff {
             match cursor_visual_next_line(text, sx, &cn) {
                 Some(x) => {
                     cn = x;
                     out.push(cn.clone());
+                    // scroll down to keep the rows below the cursor visible
+                    if out.len() > sy {
+                        out.remove(0);
+                        ry -= 1;
+                    }
                 }
                 None => break,
             }
         }
-        (rx as u16, ry, out)
+        (rx as u16, ry as u16, out)
     }
 }
 
@@ -0,0 +1,96 @@
+use crate::editor::Editor;
+use editor_core::{Buffer, Command, Registers};
+use log::*;
+
+impl Editor {
+    // the registers, shared so the input reader can record and play macros
+    pub fn registers(&self) -> Registers {
+        self.registers.clone()
+    }
+
+    fn macro_position(&mut self) -> (Buffer, usize) {
+        let block = self.layout.get_buffer();
+        (block.buf.clone(), block.cursor.c)
+    }
+
+    // play the commands of a macro, stopping at the first motion that can't move,
+    // saves and searches still go out to the background threads
+    pub(crate) fn macro_play(&mut self, commands: &[Command]) -> Vec<Command> {
+        let mut out = vec![];
+        let mut queue = commands.to_vec();
+        while !queue.is_empty() {
+            let c = queue.remove(0);
+            // a macro played from a macro stops with it
+            if let Command::Macro(inner) = c {
+                queue.splice(0..0, inner);
+                continue;
+            }
+            let (buf, cursor) = self.macro_position();
+            let mut result = self.command(&c);
+            if let Command::Motion(_, m) = &c {
+                let (after_buf, after_cursor) = self.macro_position();
+                if m.can_fail() && buf.is_same(&after_buf) && cursor == after_cursor {
+                    info!("Macro stopped: {:?}", c);
+                    break;
+                }
+            }
+            // run what the command returns before the rest of the macro
+            result.retain(|r| match r {
+                Command::SaveBuffer(_, _) | Command::GrepSearch(_, _, _) => {
+                    out.push(r.clone());
+                    false
+                }
+                _ => true,
+            });
+            queue.splice(0..0, result);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{editor, keys};
+    use editor_bindings::range::{range_string, Elem};
+    use editor_bindings::InputReader;
+    use editor_core::Register;
+
+    #[test]
+    fn test_macros() {
+        let (mut e, buf) = editor("ab\nab\nab\nab\n");
+        let mut reader = InputReader::default();
+        reader.registers = e.registers();
+
+        // the keys go in the register as text
+        keys(&mut e, &mut reader, range_string("qaxjq"));
+        assert_eq!(buf.get_text(), "b\nab\nab\nab\n");
+        assert_eq!(e.registers.get(&Register('a')), "xj");
+        assert_eq!(e.registers.get(&Register('"')), "a");
+
+        keys(&mut e, &mut reader, range_string("@a"));
+        assert_eq!(buf.get_text(), "b\nb\nab\nab\n");
+
+        // the count stops at the first motion that fails, on the last line
+        keys(&mut e, &mut reader, range_string("5@a"));
+        assert_eq!(buf.get_text(), "b\nb\nb\nb\n");
+
+        // a macro edited as text, with an escape in it
+        keys(&mut e, &mut reader, range_string("ggiX"));
+        keys(&mut e, &mut reader, vec![Elem::Esc]);
+        keys(&mut e, &mut reader, range_string("qbq"));
+        e.registers.yank(
+            &Register('b'),
+            editor_core::RegisterValue::new("j^iY\x1b", editor_core::RegisterKind::Charwise),
+        );
+        keys(&mut e, &mut reader, range_string("@b@@"));
+        assert_eq!(buf.get_text(), "Xb\nYb\nYb\nb\n");
+
+        // . repeats the last change the macro made
+        keys(&mut e, &mut reader, range_string("."));
+        assert_eq!(buf.get_text(), "Xb\nYb\nYYb\nb\n");
+
+        // the macro pastes like any register
+        keys(&mut e, &mut reader, range_string("G\"ap"));
+        assert_eq!(buf.get_text(), "Xb\nYb\nYYb\nbxj\n");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::editor::Editor;
+use editor_core::marks::is_file_mark;
+use editor_core::{Buffer, Command, Motion};
+
+impl Editor {
+    // uppercase marks can only be in one buffer, setting one removes it from the others
+    pub fn mark_set(&mut self, ch: char) -> Vec<Command> {
+        let block = self.layout.get_buffer_mut();
+        if is_file_mark(ch) {
+            if let Some(prev) = self.file_marks.get_mut(&ch) {
+                if !prev.is_same(&block.buf) {
+                    prev.remove_mark(ch);
+                }
+            }
+            self.file_marks.insert(ch, block.buf.clone());
+        }
+        block.set_mark(ch);
+        vec![]
+    }
+
+    // jumping to an uppercase mark switches to the buffer that has it,
+    // returns true if it did, the jump is then already recorded in the old buffer
+    pub fn mark_jump(&mut self, m: &Motion) -> bool {
+        let ch = match m {
+            Motion::Mark(ch) | Motion::MarkLine(ch) if is_file_mark(*ch) => *ch,
+            _ => return false,
+        };
+        match self.file_marks.get(&ch).cloned() {
+            Some(buf) if !buf.is_same(&self.layout.get_buffer().buf) => {
+                self.layout.get_buffer_mut().jump_from();
+                self.layout.jump_to_buffer(&buf);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn mark_delete(&mut self, marks: &[char]) -> Vec<Command> {
+        let block = self.layout.get_buffer_mut();
+        for ch in marks {
+            match self.file_marks.remove(ch) {
+                Some(mut buf) => {
+                    buf.remove_mark(*ch);
+                }
+                None => {
+                    block.buf.remove_mark(*ch);
+                }
+            }
+        }
+        vec![]
+    }
+
+    // :marks, the marks in this buffer and the uppercase marks in other buffers
+    pub fn marks_list(&mut self) -> Vec<Command> {
+        let block = self.layout.get_buffer();
+        let current = block.buf.clone();
+        let mut marks = current
+            .get_marks()
+            .into_iter()
+            .map(|(ch, c)| (ch, current.clone(), c))
+            .collect::<Vec<(char, Buffer, usize)>>();
+        for ch in ['<', '>'] {
+            if let Some(c) = block.mark_position(ch) {
+                marks.push((ch, current.clone(), c));
+            }
+        }
+        for (ch, buf) in self.file_marks.iter() {
+            if !buf.is_same(&current) {
+                if let Some(c) = buf.get_mark(*ch) {
+                    marks.push((*ch, buf.clone(), c));
+                }
+            }
+        }
+        marks.sort_by_key(|(ch, _, _)| *ch);
+
+        let mut out = String::from("mark line  col file/text\n");
+        for (ch, buf, c) in marks {
+            let text = buf.get_text();
+            let line_inx = text.char_to_line(c);
+            let col = c - text.line_to_char(line_inx);
+            let detail = if buf.is_same(&current) {
+                text.line(line_inx).to_string().trim_end().to_string()
+            } else {
+                buf.get_path()
+            };
+            out.push_str(&format!(
+                " {} {:>6} {:>4} {}\n",
+                ch,
+                line_inx + 1,
+                col,
+                detail
+            ));
+        }
+        self.show_list("[marks]", &out);
+        vec![]
+    }
+
+    // show text in a scratch buffer, reusing the buffer if it's already open
+    pub fn show_list(&mut self, name: &str, s: &str) {
+        let mut buf = match self.layout.find_buffer(name) {
+            Some(buf) => buf,
+            None => {
+                let mut buf = Buffer::default();
+                buf.set_path(name);
+                buf
+            }
+        };
+        buf.replace_buffer(s);
+        self.layout.show_buffer(&buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::editor_with;
+    use editor_core::{Buffer, Command, Motion};
+
+    #[test]
+    fn test_file_marks() {
+        let mut a = Buffer::from_string(&"a1\na2\na3\n".to_string());
+        a.set_path("a.txt");
+        let mut b = Buffer::from_string(&"b1\nb2\n".to_string());
+        b.set_path("b.txt");
+        let mut e = editor_with(&[b.clone(), a.clone()]);
+
+        use Command as C;
+        e.command(&C::Motion(2, Motion::Down));
+        e.command(&C::MarkSet('A'));
+        e.command(&C::MarkSet('a'));
+        e.command(&C::BufferNext);
+        assert!(e.layout.get_buffer().buf.is_same(&b));
+
+        // lowercase marks are local, uppercase marks switch buffers
+        e.command(&C::Motion(1, Motion::MarkLine('a')));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 0);
+        e.command(&C::Motion(1, Motion::MarkLine('A')));
+        assert!(e.layout.get_buffer().buf.is_same(&a));
+        assert_eq!(e.layout.get_buffer().cursor.line_inx, 2);
+
+        // setting the mark in another buffer moves it
+        e.command(&C::BufferNext);
+        e.command(&C::Motion(1, Motion::Down));
+        e.command(&C::MarkSet('A'));
+        assert_eq!(a.get_mark('A'), None);
+        assert_eq!(b.get_mark('A'), Some(3));
+
+        e.command(&C::Marks);
+        let list = e.layout.get_buffer().get_text().to_string();
+        assert!(list.contains(" A      2    0 b2\n"), "{}", list);
+
+        e.command(&C::DelMarks(vec!['A']));
+        assert_eq!(b.get_mark('A'), None);
+        e.command(&C::Motion(1, Motion::MarkLine('A')));
+        assert_eq!(e.layout.get_buffer().get_path(), "[marks]");
+    }
+}
@@ -0,0 +1,66 @@
+use editor_core::{Buffer, Command, GrepMatch};
+
+// results of the last search, one line in the list buffer for each match
+pub struct Quickfix {
+    pub id: usize,
+    pub items: Vec<GrepMatch>,
+    pub current: Option<usize>,
+    pub buf: Buffer,
+}
+
+impl Default for Quickfix {
+    fn default() -> Self {
+        let mut buf = Buffer::default();
+        buf.set_path("[grep]");
+        Self {
+            id: 0,
+            items: vec![],
+            current: None,
+            buf,
+        }
+    }
+}
+
+impl Quickfix {
+    // start a new list, results from earlier searches are ignored
+    pub fn reset(&mut self, id: usize) {
+        self.id = id;
+        self.items.clear();
+        self.current = None;
+        self.buf.reset_text("");
+    }
+
+    pub fn add(&mut self, id: usize, m: &GrepMatch) -> bool {
+        if id != self.id {
+            return false;
+        }
+        // a search can find thousands of matches, so they aren't undoable
+        self.buf.append_text(&format!("{}\n", m.format()));
+        self.items.push(m.clone());
+        true
+    }
+
+    // commands to open the file at a match
+    pub fn open(&mut self, inx: usize) -> Vec<Command> {
+        match self.items.get(inx) {
+            Some(m) => {
+                self.current = Some(inx);
+                vec![Command::Open(m.path.clone()), Command::Line(m.line as i64)]
+            }
+            None => vec![],
+        }
+    }
+
+    pub fn next(&mut self, dx: i32) -> Vec<Command> {
+        if self.items.is_empty() {
+            return vec![];
+        }
+        let inx = match self.current {
+            Some(inx) => inx as i64 + dx as i64,
+            None if dx < 0 => self.items.len() as i64 - 1,
+            None => 0,
+        };
+        let inx = inx.clamp(0, self.items.len() as i64 - 1) as usize;
+        self.open(inx)
+    }
+}
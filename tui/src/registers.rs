@@ -0,0 +1,301 @@
+use crate::editor::Editor;
+use editor_core::{Command, Motion, Register, RegisterKind, RegisterValue};
+
+impl Editor {
+    // a register that can't be used for this command, the command does nothing
+    fn register_error(&mut self, reg: &Register) -> Vec<Command> {
+        self.layout
+            .output(&format!("E354: Invalid register name: '{}'", reg.0));
+        vec![]
+    }
+
+    pub fn register_delete(&mut self, reps: usize, reg: &Register, m: &Motion) -> Vec<Command> {
+        if !reg.is_writable() {
+            return self.register_error(reg);
+        }
+        let block = self.layout.get_buffer_mut();
+        let value = block.motion_value(m, reps);
+        if !value.text.is_empty() {
+            self.registers.delete(reg, value);
+            self.clipboard_copy(reg);
+        }
+        self.layout.get_buffer_mut().delete_motion(m, reps).update();
+        vec![]
+    }
+
+    pub fn register_yank(&mut self, reg: &Register, m: &Motion) -> Vec<Command> {
+        if !reg.is_writable() {
+            return self.register_error(reg);
+        }
+        let block = self.layout.get_buffer_mut();
+        let value = block.motion_value(m, 1);
+        if *m == Motion::Selection {
+            block.visual_start();
+        }
+        if !value.text.is_empty() {
+            self.registers.yank(reg, value);
+            self.clipboard_copy(reg);
+        }
+        self.update();
+        vec![]
+    }
+
+    pub fn register_paste(&mut self, reps: usize, reg: &Register, m: &Motion) -> Vec<Command> {
+        if !reg.is_valid() {
+            return self.register_error(reg);
+        }
+        self.register_refresh();
+        let value = if reg.is_clipboard() {
+            self.clipboard_paste(reg)
+        } else {
+            self.registers.value(reg)
+        };
+        if let Some(value) = value {
+            self.layout
+                .get_buffer_mut()
+                .paste(&value, *m == Motion::Right, reps)
+                .update();
+        }
+        vec![]
+    }
+
+    // the clipboard registers also go to the system clipboard
+    fn clipboard_copy(&mut self, reg: &Register) {
+        if !reg.is_clipboard() {
+            return;
+        }
+        let s = self.registers.get(reg);
+        if let Err(err) = self.clipboard.copy(reg.0, &s) {
+            self.layout.output(&err);
+        }
+    }
+
+    // what's on the system clipboard, keeping how it was yanked if it came from here,
+    // otherwise falling back to the last copy
+    fn clipboard_paste(&mut self, reg: &Register) -> Option<RegisterValue> {
+        let last = self.registers.value(reg);
+        match self.clipboard.paste() {
+            Ok(Some(s)) => match last {
+                Some(value) if value.text == s => Some(value),
+                _ if s.is_empty() => None,
+                _ if s.ends_with('\n') => Some(RegisterValue::new(&s, RegisterKind::Linewise)),
+                _ => Some(RegisterValue::new(&s, RegisterKind::Charwise)),
+            },
+            Ok(None) => last,
+            Err(err) => {
+                self.layout.output(&err);
+                last
+            }
+        }
+    }
+
+    // the file name register follows the current buffer
+    fn register_refresh(&mut self) {
+        let path = self.layout.get_buffer().get_path();
+        self.registers.set_read_only(&Register::FILE_NAME, &path);
+    }
+
+    // remember a command line for the : and / registers, before it runs
+    pub fn command_history(&mut self) {
+        let line = self.layout.command_line();
+        let line = line.trim_end_matches('\n');
+        match line.split_at(line.chars().next().map_or(0, |ch| ch.len_utf8())) {
+            (":", rest) if !rest.is_empty() => {
+                self.registers.set_read_only(&Register::COMMAND, rest)
+            }
+            ("/" | "?", rest) if !rest.is_empty() => {
+                self.registers.set_read_only(&Register::SEARCH, rest)
+            }
+            _ => (),
+        }
+    }
+
+    // keep track of the text typed in insert mode, for the . register
+    pub fn insert_record(&mut self, c: &Command) {
+        let mode = self.layout.get_buffer().mode();
+        match c {
+            Command::Insert(s) if mode.is_insert() => self.inserted.push_str(s),
+            Command::RemoveChar(dx) if mode.is_insert() && *dx < 0 => {
+                self.inserted.pop();
+            }
+            Command::Mode(m) if m.is_insert() && !mode.is_insert() => self.inserted.clear(),
+            Command::Mode(m) if mode.is_insert() && !m.is_insert() => {
+                let inserted = self.inserted.clone();
+                self.registers
+                    .set_read_only(&Register::LAST_INSERT, &inserted);
+            }
+            _ => (),
+        }
+    }
+
+    // :registers, the type, name and contents of each register, with line endings as ^J
+    pub fn registers_list(&mut self, names: &[char]) -> Vec<Command> {
+        self.register_refresh();
+        let mut out = String::from("Type Name Content\n");
+        for (reg, value) in self.registers.list() {
+            if !names.is_empty() && !names.contains(&reg.0) {
+                continue;
+            }
+            let content = value
+                .text
+                .chars()
+                .map(|ch| match ch {
+                    '\n' => "^J".to_string(),
+                    '\t' => "^I".to_string(),
+                    ch => ch.to_string(),
+                })
+                .collect::<String>();
+            out.push_str(&format!(
+                "  {}  \"{}   {}\n",
+                value.kind.letter(),
+                reg.0,
+                content
+            ));
+        }
+        self.show_list("[registers]", &out);
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{editor, keys};
+    use crate::DrawCommand;
+    use editor_bindings::range::{range_string, Elem};
+    use editor_bindings::InputReader;
+    use editor_core::{Clipboard, ClipboardProvider, Register, RegisterKind};
+
+    #[test]
+    fn test_registers() {
+        let (mut e, mut buf) = editor("one two\nthree\n");
+        buf.set_path("a.txt");
+        let mut reader = InputReader::default();
+
+        // linewise text pastes on its own line
+        keys(&mut e, &mut reader, range_string("yyp"));
+        assert_eq!(buf.get_text().to_string(), "one two\none two\nthree\n");
+        assert_eq!(e.registers.get(&Register('0')), "one two\n");
+
+        // deleting lines shifts the numbered registers, small deletes go to -
+        keys(&mut e, &mut reader, range_string("ddx"));
+        assert_eq!(buf.get_text().to_string(), "one two\nhree\n");
+        assert_eq!(e.registers.get(&Register('1')), "one two\n");
+        assert_eq!(e.registers.get(&Register('-')), "t");
+        keys(&mut e, &mut reader, range_string("\"1P"));
+        assert_eq!(buf.get_text().to_string(), "one two\none two\nhree\n");
+
+        // charwise text pastes after the cursor, and uppercase appends
+        keys(&mut e, &mut reader, range_string("gg\"ayw\"Ayw"));
+        assert_eq!(e.registers.get(&Register('a')), "one one ");
+        keys(&mut e, &mut reader, range_string("\"_dd"));
+        assert_eq!(e.registers.get(&Register('"')), "one one ");
+        keys(&mut e, &mut reader, range_string("\"ap"));
+        assert_eq!(buf.get_text().to_string(), "oone one ne two\nhree\n");
+
+        // the read-only registers
+        keys(&mut e, &mut reader, range_string("\"%P"));
+        assert_eq!(buf.get_text().to_string(), "oone onea.txt ne two\nhree\n");
+        let mut elems = range_string("ixy");
+        elems.push(Elem::Esc);
+        elems.extend(range_string("\".p"));
+        keys(&mut e, &mut reader, elems);
+        assert_eq!(
+            buf.get_text().to_string(),
+            "oone onea.txxytxy ne two\nhree\n"
+        );
+
+        let mut elems = range_string(":1");
+        elems.push(Elem::Enter);
+        elems.extend(range_string("/two"));
+        elems.push(Elem::Enter);
+        keys(&mut e, &mut reader, elems);
+        assert_eq!(e.registers.get(&Register(':')), "1");
+        assert_eq!(e.registers.get(&Register('/')), "two");
+
+        e.command(&editor_core::Command::Registers(vec!['a', '.']));
+        let list = e.layout.get_buffer().get_text().to_string();
+        assert_eq!(
+            list,
+            "Type Name Content\n  c  \"a   one one \n  c  \".   xy\n"
+        );
+    }
+
+    #[test]
+    fn test_clipboard() {
+        let (mut e, buf) = editor("one\ntwo\n");
+        let mut reader = InputReader::default();
+
+        // a stub provider that keeps the clipboard in a file
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("clipboard");
+        let provider = ClipboardProvider::new(
+            &format!("tee {}", file.display()),
+            &format!("cat {}", file.display()),
+        );
+        e.set_clipboard(Clipboard::new(Some(provider)));
+
+        // copies go to the provider and to the terminal
+        keys(&mut e, &mut reader, range_string("\"+yy"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one\n");
+        let osc52 = e
+            .generate_commands()
+            .into_iter()
+            .filter_map(|c| match c {
+                DrawCommand::Clipboard(selection, s) => Some((selection, s)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(osc52, vec![('c', "one\n".to_string())]);
+
+        // pastes read the provider, text copied elsewhere is linewise if it ends a line
+        keys(&mut e, &mut reader, range_string("j\"+p"));
+        assert_eq!(buf.get_text().to_string(), "one\ntwo\none\n");
+        std::fs::write(&file, "x").unwrap();
+        keys(&mut e, &mut reader, range_string("\"*P"));
+        assert_eq!(buf.get_text().to_string(), "one\ntwo\nxone\n");
+        std::fs::write(&file, "y\n").unwrap();
+        keys(&mut e, &mut reader, range_string("\"+p"));
+        assert_eq!(buf.get_text().to_string(), "one\ntwo\nxone\ny\n");
+    }
+
+    #[test]
+    fn test_register_block() {
+        let (mut e, buf) = editor("abc\ndef\n");
+        let mut reader = InputReader::default();
+        let mut elems = vec![Elem::Control('v')];
+        elems.extend(range_string("jly"));
+        keys(&mut e, &mut reader, elems);
+        let value = e.registers.value(&Register('0')).unwrap();
+        assert_eq!(
+            (value.text.as_str(), value.kind),
+            ("ab\nde", RegisterKind::Blockwise)
+        );
+
+        // the block goes in at the cursor column, adding lines at the end
+        keys(&mut e, &mut reader, range_string("$p"));
+        assert_eq!(buf.get_text().to_string(), "abcab\ndefde\n");
+        keys(&mut e, &mut reader, range_string("jP"));
+        assert_eq!(buf.get_text().to_string(), "abcab\ndefabde\n   de\n");
+
+        // a block paste is one undo
+        keys(&mut e, &mut reader, range_string("u"));
+        assert_eq!(buf.get_text().to_string(), "abcab\ndefde\n");
+        let (mut e, buf) = editor("ab\ncd");
+        let mut elems = vec![Elem::Control('v')];
+        elems.extend(range_string("jy$pu"));
+        keys(&mut e, &mut reader, elems);
+        assert_eq!(buf.get_text().to_string(), "ab\ncd");
+    }
+
+    #[test]
+    fn test_paste_last_line() {
+        // after the last line, with and without a final newline
+        let (mut e, buf) = editor("a\nb\n");
+        let mut reader = InputReader::default();
+        keys(&mut e, &mut reader, range_string("jddp"));
+        assert_eq!(buf.get_text().to_string(), "a\nb\n");
+        let (mut e, buf) = editor("a\nb");
+        keys(&mut e, &mut reader, range_string("yyjp"));
+        assert_eq!(buf.get_text().to_string(), "a\nb\na");
+    }
+}
@@ -0,0 +1,315 @@
+use crate::editor::Editor;
+use editor_core::{Buffer, Command, GrepMatch};
+use log::*;
+use regex::Regex;
+
+// each hit is shown as three lines in the preview buffer, newlines in the text are escaped
+const HIT_LINES: usize = 3;
+
+fn escape(s: &str) -> String {
+    s.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+pub struct ReplaceHit {
+    pub m: GrepMatch,
+    pub after: String,
+    pub enabled: bool,
+}
+
+impl ReplaceHit {
+    fn format(&self) -> String {
+        format!(
+            "{} {}:{}\n    - {}\n    + {}\n",
+            if self.enabled { "[x]" } else { "[ ]" },
+            self.m.path,
+            self.m.line,
+            escape(&self.m.text),
+            escape(&self.after)
+        )
+    }
+}
+
+// a pending replace across files, previewed in a buffer before it's applied
+pub struct ReplacePreview {
+    pub id: usize,
+    pub hits: Vec<ReplaceHit>,
+    pub buf: Buffer,
+    pattern: Option<Regex>,
+    replacement: String,
+    all: bool,
+}
+
+impl Default for ReplacePreview {
+    fn default() -> Self {
+        let mut buf = Buffer::default();
+        buf.set_path("[replace]");
+        Self {
+            id: 0,
+            hits: vec![],
+            buf,
+            pattern: None,
+            replacement: String::new(),
+            all: false,
+        }
+    }
+}
+
+impl ReplacePreview {
+    pub fn reset(&mut self, id: usize, pattern: Regex, replacement: &str, all: bool) {
+        self.id = id;
+        self.hits.clear();
+        self.pattern = Some(pattern);
+        self.replacement = replacement.to_string();
+        self.all = all;
+        self.buf.reset_text("");
+    }
+
+    fn replace(&self, text: &str) -> Option<String> {
+        let limit = if self.all { 0 } else { 1 };
+        self.pattern.as_ref().map(|re| {
+            re.replacen(text, limit, self.replacement.as_str())
+                .to_string()
+        })
+    }
+
+    pub fn add(&mut self, id: usize, m: &GrepMatch) -> bool {
+        if id != self.id {
+            return false;
+        }
+        if let Some(after) = self.replace(&m.text) {
+            let hit = ReplaceHit {
+                m: m.clone(),
+                after,
+                enabled: true,
+            };
+            self.buf.append_text(&hit.format());
+            self.hits.push(hit);
+        }
+        true
+    }
+
+    // toggle the hit shown on a line of the preview buffer, the preview is redrawn without an undo
+    pub fn toggle(&mut self, line_inx: usize) -> &mut Self {
+        let inx = line_inx / HIT_LINES;
+        if let Some(hit) = self.hits.get_mut(inx) {
+            hit.enabled = !hit.enabled;
+            let text = self.hits.iter().map(|hit| hit.format()).collect::<String>();
+            self.buf.reset_text(&text);
+        }
+        self
+    }
+
+    // enabled hits grouped by file, in the order the files were found
+    fn files(&self) -> Vec<(String, Vec<&GrepMatch>)> {
+        let mut files: Vec<(String, Vec<&GrepMatch>)> = vec![];
+        for hit in self.hits.iter().filter(|hit| hit.enabled) {
+            match files.iter_mut().find(|(path, _)| *path == hit.m.path) {
+                Some((_, hits)) => hits.push(&hit.m),
+                None => files.push((hit.m.path.clone(), vec![&hit.m])),
+            }
+        }
+        files
+    }
+}
+
+impl Editor {
+    // change every file with an enabled hit, each file is a single undo, hits on lines that changed
+    // since the search are skipped, and files with unsaved changes are changed but not saved
+    pub fn replace_apply(&mut self) -> Vec<Command> {
+        let re = match &self.replace.pattern {
+            Some(re) => re.clone(),
+            None => {
+                self.layout.output("no replace pending");
+                return vec![];
+            }
+        };
+        let replacement = self.replace.replacement.clone();
+        let all = self.replace.all;
+
+        let mut out = vec![];
+        let mut changed = 0;
+        let mut skipped = 0;
+        let mut unsaved = 0;
+        for (path, hits) in self.replace.files() {
+            let (mut buf, edited) = match self.layout.find_buffer(&path) {
+                Some(buf) => {
+                    let edited = match std::fs::read_to_string(&path) {
+                        Ok(disk) => buf.get_text() != disk.as_str(),
+                        Err(_) => true,
+                    };
+                    (buf, edited)
+                }
+                None => match Buffer::from_path(&path) {
+                    Ok(buf) => (buf, false),
+                    Err(err) => {
+                        error!("replace: {:?}", (&path, err));
+                        continue;
+                    }
+                },
+            };
+
+            buf.change_start();
+            let mut modified = false;
+            for m in hits {
+                let line_inx = m.line - 1;
+                let text = buf.get_text();
+                let same = line_inx < text.len_lines()
+                    && text
+                        .line(line_inx)
+                        .to_string()
+                        .trim_end_matches(&['\r', '\n'][..])
+                        == m.text;
+                if !same {
+                    skipped += 1;
+                    continue;
+                }
+                modified |= buf.substitute_line(line_inx, &re, &replacement, all);
+            }
+            buf.change_end();
+
+            if modified {
+                changed += 1;
+                self.layout.show_buffer(&buf);
+                match edited {
+                    true => unsaved += 1,
+                    false => out.push(Command::SaveBuffer(buf.get_path(), buf.get_text())),
+                }
+            }
+        }
+        self.replace.pattern = None;
+        let mut status = format!("replace: {} files changed", changed);
+        if skipped > 0 {
+            status += &format!(", {} lines changed since the search were skipped", skipped);
+        }
+        if unsaved > 0 {
+            status += &format!(", {} files with unsaved changes were not saved", unsaved);
+        }
+        self.layout.output(&status);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{editor, editor_with};
+    use editor_core::{Buffer, Command};
+    use std::fs;
+
+    #[test]
+    fn test_replace_preview() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt").to_string_lossy().to_string();
+        let b = dir.path().join("b.txt").to_string_lossy().to_string();
+        fs::write(&a, "foo 1\nbar\nfoo 2\n").unwrap();
+        fs::write(&b, "foo 3\n").unwrap();
+        let mut e = editor_with(&[Buffer::from_path(&a).unwrap()]);
+
+        use Command::*;
+        let search = e.command(&Replace("fo(o)".into(), "x\\1".into(), false, vec![]));
+        assert_eq!(search, vec![GrepSearch(1, "fo(o)".into(), vec![])]);
+        let m = |path: &str, line, text: &str| editor_core::GrepMatch {
+            path: path.into(),
+            line,
+            col: 1,
+            text: text.into(),
+        };
+        e.command(&GrepMatch(1, m(&a, 1, "foo 1")));
+        e.command(&GrepMatch(1, m(&a, 3, "foo 2")));
+        e.command(&GrepMatch(1, m(&b, 1, "foo 3")));
+        let preview = e.layout.get_buffer().get_text().to_string();
+        assert!(preview.starts_with(&format!("[x] {}:1\n    - foo 1\n    + xo 1\n", a)));
+
+        // skip the only hit in b.txt
+        e.command(&Line(8));
+        e.command(&Select);
+        assert!(e
+            .layout
+            .get_buffer()
+            .get_text()
+            .to_string()
+            .contains(&format!("[ ] {}:1\n", b)));
+        e.command(&Undo);
+        assert!(e
+            .layout
+            .get_buffer()
+            .get_text()
+            .to_string()
+            .contains(&format!("[ ] {}:1\n", b)));
+
+        let commands = e.command(&ReplaceApply);
+        assert_eq!(commands.len(), 1);
+        match &commands[0] {
+            SaveBuffer(path, text) => {
+                assert_eq!(path, &a);
+                assert_eq!(text.to_string(), "xo 1\nbar\nxo 2\n");
+            }
+            c => panic!("unexpected {:?}", c),
+        }
+
+        // the changes to a file are a single undo
+        e.command(&Undo);
+        assert_eq!(
+            e.layout.get_buffer().get_text().to_string(),
+            "foo 1\nbar\nfoo 2\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_newline() {
+        let (mut e, _) = editor("");
+        use Command::*;
+        e.command(&Replace("o".into(), "\n".into(), false, vec![]));
+        let m = |line, text: &str| editor_core::GrepMatch {
+            path: "a.txt".into(),
+            line,
+            col: 1,
+            text: text.into(),
+        };
+        e.command(&GrepMatch(1, m(1, "foo")));
+        e.command(&GrepMatch(1, m(2, "bo")));
+        let preview = e.layout.get_buffer().get_text().to_string();
+        assert_eq!(
+            preview,
+            "[x] a.txt:1\n    - foo\n    + f\\no\n[x] a.txt:2\n    - bo\n    + b\\n\n"
+        );
+
+        // the lines of the second hit still toggle it
+        e.command(&Line(5));
+        e.command(&Select);
+        let hits = e
+            .replace
+            .hits
+            .iter()
+            .map(|hit| hit.enabled)
+            .collect::<Vec<_>>();
+        assert_eq!(hits, vec![true, false]);
+    }
+
+    #[test]
+    fn test_replace_edited_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt").to_string_lossy().to_string();
+        fs::write(&a, "foo 1\nbar\nfoo 2\n").unwrap();
+        let mut buf = Buffer::from_path(&a).unwrap();
+        let mut e = editor_with(&[buf.clone()]);
+
+        use Command::*;
+        e.command(&Replace("foo".into(), "x".into(), false, vec![]));
+        let m = |line, text: &str| editor_core::GrepMatch {
+            path: a.clone(),
+            line,
+            col: 1,
+            text: text.into(),
+        };
+        e.command(&GrepMatch(1, m(1, "foo 1")));
+        e.command(&GrepMatch(1, m(3, "foo 2")));
+        e.command(&GrepMatch(1, m(5, "foo 3")));
+
+        // the first line was edited and the buffer got shorter since the search
+        buf.replace_buffer("foo one\nbar\nfoo 2\n");
+        let commands = e.command(&ReplaceApply);
+        assert_eq!(commands, vec![]);
+        assert_eq!(buf.get_text().to_string(), "foo one\nbar\nx 2\n");
+        assert_eq!(fs::read_to_string(&a).unwrap(), "foo 1\nbar\nfoo 2\n");
+    }
+}
@@ -9,6 +9,7 @@ use crossterm::style::Stylize;
 use crossterm::terminal;
 use crossterm::{queue, style, terminal::ClearType};
 use editor_bindings::InputReader;
+use editor_core::clipboard::osc52;
 use editor_core::Command;
 use log::*;
 use std::convert::TryInto;
@@ -248,10 +249,15 @@ fn handle_command(out: &mut Stdout, command: &DrawCommand) {
                     Highlight => queue!(out, style::Print(s.negative())).unwrap(),
                     Bold => queue!(out, style::Print(s.bold())).unwrap(),
                     Dim => queue!(out, style::Print(s.dim())).unwrap(),
+                    Select => queue!(out, style::Print(s.on_dark_grey())).unwrap(),
                 }
             }
         }
 
+        Clipboard(selection, s) => {
+            queue!(out, style::Print(osc52(*selection, s))).unwrap();
+        }
+
         // Draw the cursor at position
         DrawCommand::Cursor(a, b) => {
             debug!("Cursor: {:?}", (a, b));
@@ -0,0 +1,40 @@
+// helpers for tests that drive an editor with keys
+use crate::editor::*;
+use editor_bindings::range::Elem;
+use editor_bindings::InputReader;
+use editor_core::{Buffer, ViewPos};
+
+// an editor with one window showing a buffer with the text
+pub fn editor(s: &str) -> (Editor, Buffer) {
+    let buf = Buffer::from_string(&s.to_string());
+    (editor_with(std::slice::from_ref(&buf)), buf)
+}
+
+// an editor with a window for each buffer, the last one is the current window
+pub fn editor_with(bufs: &[Buffer]) -> Editor {
+    let view = ViewPos {
+        w: 40,
+        h: 10,
+        x0: 0,
+        y0: 0,
+    };
+    let config = EditorConfig {
+        version: "unknown".to_string(),
+    };
+    let mut layout = EditorComplexLayout::new(&config, view);
+    for buf in bufs {
+        layout.add_window(buf.clone());
+    }
+    Editor::new(config, Box::new(layout))
+}
+
+// feed keys through the reader, running the commands the editor sends back as well
+pub fn keys(e: &mut Editor, reader: &mut InputReader, elems: Vec<Elem>) {
+    for elem in elems {
+        let mut commands = reader.add(elem);
+        while !commands.is_empty() {
+            let c = commands.remove(0);
+            commands.extend(e.command(&c));
+        }
+    }
+}
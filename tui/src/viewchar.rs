@@ -20,6 +20,7 @@ pub enum LineFormatType {
     Normal,
     Highlight,
     Bold,
+    Select,
 }
 
 #[derive(Debug)]
@@ -143,7 +144,8 @@ impl ViewCharCollection {
     pub fn lc_to_r(&self, lc: usize) -> usize {
         match self.elements.iter().find(|v| v.lc >= lc) {
             Some(v) => v.r,
-            None => 0,
+            // past the last element, at the end of the line
+            None => self.current_r,
         }
     }
 
@@ -154,6 +156,11 @@ impl ViewCharCollection {
         }
     }
 
+    // the render column and width of each element
+    pub fn columns(&self) -> Vec<(usize, usize)> {
+        self.elements.iter().map(|v| (v.r, v.width)).collect()
+    }
+
     pub fn char_length_range(&self, start: usize, end: usize) -> usize {
         let mut a = start;
         if a >= self.elements.len() {
@@ -0,0 +1,272 @@
+use crate::string_to_elements;
+use editor_core::{nth_next_grapheme_boundary, BufferConfig, Mode};
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+// a visual selection from the anchor to the head, both ends are included
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Selection {
+    pub mode: Mode,
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(mode: Mode, anchor: usize, head: usize) -> Self {
+        Self { mode, anchor, head }
+    }
+
+    // keep both ends inside the text, after it has been edited
+    pub fn clamp(&self, text: &Rope) -> Self {
+        let last = text.len_chars();
+        Self::new(
+            self.mode,
+            std::cmp::min(self.anchor, last),
+            std::cmp::min(self.head, last),
+        )
+    }
+
+    pub fn start(&self) -> usize {
+        std::cmp::min(self.anchor, self.head)
+    }
+
+    // first and last line of the selection
+    pub fn lines(&self, text: &Rope) -> (usize, usize) {
+        let s = self.clamp(text);
+        let a = text.char_to_line(s.anchor);
+        let b = text.char_to_line(s.head);
+        (std::cmp::min(a, b), std::cmp::max(a, b))
+    }
+
+    // the selected chars, a single range unless the selection is blockwise
+    pub fn ranges(&self, text: &Rope, config: &BufferConfig) -> Vec<(usize, usize)> {
+        let s = self.clamp(text);
+        let (l0, l1) = self.lines(text);
+        match self.mode {
+            Mode::VisualLine => vec![(text.line_to_char(l0), text.line_to_char(l1 + 1))],
+            Mode::VisualBlock => {
+                let (a0, a1) = column(text, config, s.anchor);
+                let (h0, h1) = column(text, config, s.head);
+                let (c0, c1) = (std::cmp::min(a0, h0), std::cmp::max(a1, h1));
+                (l0..=l1)
+                    .map(|line_inx| block_range(text, config, line_inx, c0, c1))
+                    .collect()
+            }
+            _ => {
+                let end = std::cmp::max(s.anchor, s.head);
+                if end >= text.len_chars() {
+                    vec![(s.start(), end)]
+                } else {
+                    vec![(
+                        s.start(),
+                        nth_next_grapheme_boundary(text.slice(..), end, 1),
+                    )]
+                }
+            }
+        }
+    }
+}
+
+// the graphemes of a line that are selected, as a range of grapheme indices
+pub fn line_selection(text: &Rope, line_inx: usize, ranges: &[(usize, usize)]) -> (usize, usize) {
+    let lc0 = text.line_to_char(line_inx);
+    let line = text.line(line_inx).to_string();
+    let lc1 = lc0 + line.chars().count();
+    for (start, end) in ranges {
+        let (start, end) = (std::cmp::max(*start, lc0), std::cmp::min(*end, lc1));
+        if start < end {
+            let mut c = lc0;
+            let mut selected = (None, 0);
+            for (inx, g) in line.graphemes(true).enumerate() {
+                if c >= start && c < end {
+                    selected.0.get_or_insert(inx);
+                    selected.1 = inx + 1;
+                }
+                c += g.chars().count();
+            }
+            return (selected.0.unwrap_or(0), selected.1);
+        }
+    }
+    (0, 0)
+}
+
+// the graphemes of a line, with the render column, width and char offset of each
+fn line_columns(
+    text: &Rope,
+    config: &BufferConfig,
+    line_inx: usize,
+) -> Vec<(usize, usize, usize, String)> {
+    let line = text.line(line_inx).to_string();
+    let columns = string_to_elements(&line, config).columns();
+    let mut lc = text.line_to_char(line_inx);
+    line.graphemes(true)
+        .zip(columns)
+        .map(|(g, (r, w))| {
+            let c = lc;
+            lc += g.chars().count();
+            (r, w, c, g.to_string())
+        })
+        .collect()
+}
+
+// the render columns covered by the grapheme at a char position
+pub fn column(text: &Rope, config: &BufferConfig, c: usize) -> (usize, usize) {
+    let line_inx = text.char_to_line(c);
+    let columns = line_columns(text, config, line_inx);
+    match columns.iter().find(|(_, _, lc, _)| *lc >= c) {
+        Some((r, w, _, _)) => (*r, r + std::cmp::max(1, *w)),
+        None => match columns.last() {
+            Some((r, w, _, _)) => (r + w, r + w + 1),
+            None => (0, 1),
+        },
+    }
+}
+
+// the char at a render column of a line, and how many columns short of it the line ends
+pub fn column_char(
+    text: &Rope,
+    config: &BufferConfig,
+    line_inx: usize,
+    col: usize,
+) -> (usize, usize) {
+    let columns = line_columns(text, config, line_inx);
+    for (r, _, c, g) in columns.iter() {
+        if g.ends_with('\n') {
+            return (*c, col.saturating_sub(*r));
+        }
+        if *r >= col {
+            return (*c, 0);
+        }
+    }
+    match columns.last() {
+        Some((r, w, c, g)) => (c + g.chars().count(), col.saturating_sub(r + w)),
+        None => (text.line_to_char(line_inx), col),
+    }
+}
+
+// the render width of a string that doesn't have a line ending
+pub fn text_width(s: &str, config: &BufferConfig) -> usize {
+    string_to_elements(&s.to_string(), config)
+        .columns()
+        .last()
+        .map(|(r, w)| r + w)
+        .unwrap_or(0)
+}
+
+// the chars on a line that overlap the render columns c0..c1, line endings are never included
+fn block_range(
+    text: &Rope,
+    config: &BufferConfig,
+    line_inx: usize,
+    c0: usize,
+    c1: usize,
+) -> (usize, usize) {
+    let columns = line_columns(text, config, line_inx);
+    let selected = columns
+        .iter()
+        .filter(|(r, w, _, g)| !g.ends_with('\n') && *r < c1 && r + w > c0)
+        .collect::<Vec<_>>();
+    match (selected.first(), selected.last()) {
+        (Some((_, _, start, _)), Some((_, _, end, g))) => (*start, end + g.chars().count()),
+        _ => {
+            let end = columns
+                .iter()
+                .find(|(_, _, _, g)| g.ends_with('\n'))
+                .map(|(_, _, lc, _)| *lc)
+                .unwrap_or_else(|| text.line_to_char(line_inx) + text.line(line_inx).len_chars());
+            (end, end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{editor, keys};
+    use crate::{DrawCommand, LineFormatType};
+    use editor_bindings::range::{range_string, Elem};
+    use editor_bindings::InputReader;
+    use editor_core::Register;
+
+    fn with(s: &str, elem: Elem) -> Vec<Elem> {
+        let mut elems = range_string(s);
+        elems.push(elem);
+        elems
+    }
+
+    #[test]
+    fn test_visual_operators() {
+        let (mut e, buf) = editor("one two\nthree four\nfive\n");
+        let mut reader = InputReader::default();
+
+        keys(&mut e, &mut reader, range_string("vwd"));
+        assert_eq!(buf.get_text().to_string(), "wo\nthree four\nfive\n");
+        e.command(&editor_core::Command::Undo);
+
+        // the selection is shown while visual mode is active
+        keys(&mut e, &mut reader, range_string("Vj"));
+        let selected = e.generate_commands().iter().any(|c| match c {
+            DrawCommand::Format(_, _, _, formats) => {
+                formats.iter().any(|f| f.format == LineFormatType::Select)
+            }
+            _ => false,
+        });
+        assert!(selected);
+        keys(&mut e, &mut reader, range_string("y"));
+        assert_eq!(e.registers.get(&Register('0')), "one two\nthree four\n");
+
+        // gv selects the same lines again
+        keys(&mut e, &mut reader, range_string("gv>"));
+        assert_eq!(
+            buf.get_text().to_string(),
+            "    one two\n    three four\nfive\n"
+        );
+
+        // : starts with the range of the selection
+        keys(&mut e, &mut reader, with("gvj:<", Elem::Enter));
+        assert_eq!(buf.get_text().to_string(), "one two\nthree four\nfive\n");
+    }
+
+    #[test]
+    fn test_visual_block() {
+        let (mut e, buf) = editor("abcd\nefgh\nij\n");
+        let mut reader = InputReader::default();
+        let mut elems = with("l", Elem::Control('v'));
+        elems.extend(range_string("jjlU"));
+        keys(&mut e, &mut reader, elems);
+        assert_eq!(buf.get_text().to_string(), "aBCd\neFGh\niJ\n");
+        // the change to every line is one undo
+        keys(&mut e, &mut reader, range_string("u"));
+        assert_eq!(buf.get_text().to_string(), "abcd\nefgh\nij\n");
+        keys(&mut e, &mut reader, range_string("gvod"));
+        assert_eq!(buf.get_text().to_string(), "ad\neh\ni\n");
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let config = BufferConfig::default();
+        let text = Rope::from("abcd\nefgh\nij\n");
+
+        let s = Selection::new(Mode::Visual, 6, 1);
+        assert_eq!(s.ranges(&text, &config), vec![(1, 7)]);
+
+        let s = Selection::new(Mode::VisualLine, 6, 1);
+        assert_eq!(s.ranges(&text, &config), vec![(0, 10)]);
+
+        // the short line only has the chars that reach the columns
+        let s = Selection::new(Mode::VisualBlock, 1, 12);
+        assert_eq!(s.ranges(&text, &config), vec![(1, 3), (6, 8), (11, 12)]);
+    }
+
+    #[test]
+    fn test_block_wide_chars() {
+        let config = BufferConfig::default();
+        // the wide char covers columns 1 and 2, a tab expands to several columns
+        let text = Rope::from("a\u{4e16}b\n\tx\nabcd\n");
+        let s = Selection::new(Mode::VisualBlock, 1, 9);
+        assert_eq!(s.ranges(&text, &config), vec![(1, 2), (4, 5), (8, 10)]);
+        let ranges = s.ranges(&text, &config);
+        assert_eq!(line_selection(&text, 0, &ranges), (1, 2));
+        assert_eq!(line_selection(&text, 2, &ranges), (1, 3));
+    }
+}